@@ -1,16 +1,37 @@
 extern crate glsl_to_spirv;
+extern crate ron;
+#[macro_use]
+extern crate serde_derive;
 
 use std::error::Error;
 
+// the renderer looks variants up with the same manifest, with what build.rs does not use
+#[allow(dead_code)]
+#[path = "src/frontend/graphic/shader_preprocessor.rs"]
+mod shader_preprocessor;
+
+use shader_preprocessor::{
+    PermutationManifest,
+    PERMUTATION_MANIFEST_NAME,
+};
+
 fn main() -> Result<(), Box<Error>> {
     use glsl_to_spirv::ShaderType;
     
     // Tell the build script to only run again if we change our source shaders
     println!("cargo:rerun-if-changed=res/shaders");
+    println!("cargo:rerun-if-changed=res/shaders/include");
     
     // Create destination path if necessary
     std::fs::create_dir_all("res/shaders/gen/")?;
     
+    // shaders not listed in the manifest are compiled once without any define
+    let manifest_path = format!("res/shaders/{}", PERMUTATION_MANIFEST_NAME);
+    let manifest = match std::fs::read(&manifest_path) {
+        Ok(manifest_file) => PermutationManifest::parse(&manifest_file)?,
+        Err(_) => PermutationManifest::default(),
+    };
+    
     for entry in std::fs::read_dir("res/shaders")? {
         let entry = entry?;
         
@@ -31,21 +52,41 @@ fn main() -> Result<(), Box<Error>> {
             if let Some(shader_type) = some_shader_type {
                 use std::io::Read;
                 
-                let source = std::fs::read_to_string(&in_path)?;
-                let mut compiled_file = glsl_to_spirv::compile(&source, shader_type)?;
-                
-                let mut compiled_bytes = Vec::new();
-                compiled_file.read_to_end(&mut compiled_bytes)?;
-                
-                let out_path = format!(
-                    "res/shaders/gen/{}.spv",
-                    in_path.file_name().unwrap().to_string_lossy()
-                );
-                
-                std::fs::write(&out_path, &compiled_bytes)?;
+                let source_name = in_path.file_name().unwrap().to_string_lossy().into_owned();
+                for defines in manifest.variants(&source_name) {
+                    let preprocessed = shader_preprocessor::preprocess(&source_name, &defines, |name| {
+                        std::fs::read_to_string(format!("res/shaders/{}", name))
+                            .map_err(|error| format!("cannot read {}: {}", name, error))
+                    })?;
+                    
+                    let mut compiled_file = glsl_to_spirv::compile(&preprocessed.source, shader_type.clone())
+                        .map_err(|log| compile_error(&source_name, &defines, &log, &preprocessed.files))?;
+                    
+                    let mut compiled_bytes = Vec::new();
+                    compiled_file.read_to_end(&mut compiled_bytes)?;
+                    
+                    let out_path = format!(
+                        "res/shaders/gen/{}",
+                        shader_preprocessor::variant_file_name(&source_name, &defines)
+                    );
+                    
+                    std::fs::write(&out_path, &compiled_bytes)?;
+                }
             }
         }
     }
     
     Ok(())
 }
+
+/// the errors glslang reports for a variant, at the line of the file they are in
+fn compile_error(source_name: &str, defines: &[String], log: &str, files: &[String]) -> String {
+    let mut message = format!("cannot compile {} with {:?}", source_name, defines);
+    for (file, line, error) in shader_preprocessor::parse_log(log, files) {
+        match line {
+            Some(line) => message.push_str(&format!("\n    {}:{}: {}", file, line, error)),
+            None => message.push_str(&format!("\n    {}: {}", file, error)),
+        }
+    }
+    message
+}
@@ -0,0 +1,21 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+// add the top of the bloom pyramid back onto the image it was taken from
+
+layout(set = 0, binding = 0) uniform sampler2D source;
+layout(set = 0, binding = 1) uniform sampler2D bloom;
+
+layout(push_constant) uniform CompositeBlock {
+    float intensity;
+} composite_block;
+
+layout(location = 0) in vec2 fTexCoord;
+
+layout(location = 0) out vec4 fColor;
+
+void main()
+{
+    vec4 color = texture(source, fTexCoord);
+    fColor = vec4(color.rgb + texture(bloom, fTexCoord).rgb * composite_block.intensity, color.a);
+}
@@ -0,0 +1,21 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+#include "include/box_filter.glsl"
+
+// the next smaller level of the bloom pyramid
+
+layout(set = 0, binding = 0) uniform sampler2D source;
+
+layout(push_constant) uniform DownsampleBlock {
+    vec2 texel_size;
+} downsample_block;
+
+layout(location = 0) in vec2 fTexCoord;
+
+layout(location = 0) out vec4 fColor;
+
+void main()
+{
+    fColor = vec4(box_filter4(source, fTexCoord, downsample_block.texel_size), 1.0);
+}
@@ -0,0 +1,32 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+#include "include/box_filter.glsl"
+
+// keep what is brighter than the threshold, at half the resolution of the source
+
+layout(set = 0, binding = 0) uniform sampler2D source;
+
+layout(push_constant) uniform PrefilterBlock {
+    vec2 texel_size;
+    float threshold;
+} prefilter_block;
+
+layout(location = 0) in vec2 fTexCoord;
+
+layout(location = 0) out vec4 fColor;
+
+void main()
+{
+    vec3 color = box_filter4(source, fTexCoord, prefilter_block.texel_size);
+
+    // a soft knee below the threshold avoids a hard cut between lit and unlit pixels
+    float threshold = prefilter_block.threshold;
+    float knee = threshold * 0.5;
+    float brightness = max(color.r, max(color.g, color.b));
+    float soft = clamp(brightness - threshold + knee, 0.0, 2.0 * knee);
+    soft = soft * soft / (4.0 * knee + 0.00001);
+    float contribution = max(soft, brightness - threshold) / max(brightness, 0.00001);
+
+    fColor = vec4(color * contribution, 1.0);
+}
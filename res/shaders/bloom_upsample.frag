@@ -0,0 +1,34 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+// a level of the bloom pyramid plus the smaller one below it, blurred while upsampling
+
+layout(set = 0, binding = 0) uniform sampler2D level;
+layout(set = 0, binding = 1) uniform sampler2D smaller_level;
+
+layout(push_constant) uniform UpsampleBlock {
+    // of smaller_level
+    vec2 texel_size;
+} upsample_block;
+
+layout(location = 0) in vec2 fTexCoord;
+
+layout(location = 0) out vec4 fColor;
+
+void main()
+{
+    // 3x3 tent filter
+    vec4 offset = upsample_block.texel_size.xyxy * vec4(1.0, 1.0, -1.0, 0.0);
+    vec3 blurred =
+        texture(smaller_level, fTexCoord - offset.xy).rgb +
+        texture(smaller_level, fTexCoord - offset.wy).rgb * 2.0 +
+        texture(smaller_level, fTexCoord - offset.zy).rgb +
+        texture(smaller_level, fTexCoord + offset.zw).rgb * 2.0 +
+        texture(smaller_level, fTexCoord).rgb * 4.0 +
+        texture(smaller_level, fTexCoord + offset.xw).rgb * 2.0 +
+        texture(smaller_level, fTexCoord + offset.zy).rgb +
+        texture(smaller_level, fTexCoord + offset.wy).rgb * 2.0 +
+        texture(smaller_level, fTexCoord + offset.xy).rgb;
+
+    fColor = vec4(texture(level, fTexCoord).rgb + blurred / 16.0, 1.0);
+}
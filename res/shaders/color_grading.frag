@@ -0,0 +1,51 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+// map every color through a 3D lookup table, stored as a horizontal strip of size slices
+// of size x size texels where red goes along each slice, green down it and blue across the slices
+
+layout(set = 0, binding = 0) uniform sampler2D source;
+layout(set = 1, binding = 0) uniform sampler2D lut;
+
+layout(push_constant) uniform GradingBlock {
+    // texels along every axis of the table
+    float size;
+    // how much of the graded color is used, 0 leaves the image as is
+    float intensity;
+} grading_block;
+
+layout(location = 0) in vec2 fTexCoord;
+
+layout(location = 0) out vec4 fColor;
+
+vec3 linear_to_srgb(vec3 color)
+{
+    return mix(
+        color * 12.92,
+        1.055 * pow(color, vec3(1.0 / 2.4)) - 0.055,
+        step(vec3(0.0031308), color)
+    );
+}
+
+void main()
+{
+    vec4 color = texture(source, fTexCoord);
+    float size = grading_block.size;
+
+    // the table is indexed by sRGB encoded colors
+    // and its texels are decoded to linear colors when sampled
+    vec3 index = clamp(linear_to_srgb(clamp(color.rgb, 0.0, 1.0)), 0.0, 1.0) * (size - 1.0);
+
+    // red and green are filtered by the sampler, blue between the two nearest slices
+    float slice = floor(index.b);
+    float slice_blend = index.b - slice;
+    vec2 slice_coord = (index.rg + 0.5) / vec2(size * size, size);
+    vec2 slice_offset = vec2(1.0 / size, 0.0);
+    vec3 graded = mix(
+        texture(lut, slice_coord + slice_offset * slice).rgb,
+        texture(lut, slice_coord + slice_offset * min(slice + 1.0, size - 1.0)).rgb,
+        slice_blend
+    );
+
+    fColor = vec4(mix(color.rgb, graded, grading_block.intensity), color.a);
+}
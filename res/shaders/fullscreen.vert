@@ -0,0 +1,15 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) out vec2 fTexCoord;
+
+out gl_PerVertex {
+  vec4 gl_Position;
+};
+
+// a single triangle covering the whole viewport, no vertex buffer needed
+void main()
+{
+    fTexCoord = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+    gl_Position = vec4(fTexCoord * 2.0 - 1.0, 0.0, 1.0);
+}
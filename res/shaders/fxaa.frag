@@ -0,0 +1,73 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+// fast approximate antialiasing: blur along the edges found in the luma of the image
+
+layout(set = 0, binding = 0) uniform sampler2D source;
+
+layout(push_constant) uniform FxaaBlock {
+    vec2 texel_size;
+    // the longest distance in texels sampled along an edge
+    float span_max;
+    // how much the blur direction is shortened on bright edges
+    float reduce_mul;
+    // the least it is shortened by, keeps dark edges from blurring too far
+    float reduce_min;
+} fxaa_block;
+
+layout(location = 0) in vec2 fTexCoord;
+
+layout(location = 0) out vec4 fColor;
+
+const vec3 LUMA = vec3(0.299, 0.587, 0.114);
+
+void main()
+{
+    vec2 texel = fxaa_block.texel_size;
+    vec3 color_nw = texture(source, fTexCoord + vec2(-1.0, -1.0) * texel).rgb;
+    vec3 color_ne = texture(source, fTexCoord + vec2(1.0, -1.0) * texel).rgb;
+    vec3 color_sw = texture(source, fTexCoord + vec2(-1.0, 1.0) * texel).rgb;
+    vec3 color_se = texture(source, fTexCoord + vec2(1.0, 1.0) * texel).rgb;
+    vec4 color_m = texture(source, fTexCoord);
+
+    float luma_nw = dot(color_nw, LUMA);
+    float luma_ne = dot(color_ne, LUMA);
+    float luma_sw = dot(color_sw, LUMA);
+    float luma_se = dot(color_se, LUMA);
+    float luma_m = dot(color_m.rgb, LUMA);
+    float luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    float luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+    // perpendicular to the gradient of the luma, which is along the edge
+    vec2 direction = vec2(
+        -((luma_nw + luma_ne) - (luma_sw + luma_se)),
+        (luma_nw + luma_sw) - (luma_ne + luma_se)
+    );
+    float direction_reduce = max(
+        (luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * fxaa_block.reduce_mul,
+        fxaa_block.reduce_min
+    );
+    float direction_scale = 1.0 / (min(abs(direction.x), abs(direction.y)) + direction_reduce);
+    direction = clamp(
+        direction * direction_scale,
+        vec2(-fxaa_block.span_max),
+        vec2(fxaa_block.span_max)
+    ) * texel;
+
+    vec3 color_a = 0.5 * (
+        texture(source, fTexCoord + direction * (1.0 / 3.0 - 0.5)).rgb +
+        texture(source, fTexCoord + direction * (2.0 / 3.0 - 0.5)).rgb
+    );
+    vec3 color_b = color_a * 0.5 + 0.25 * (
+        texture(source, fTexCoord + direction * -0.5).rgb +
+        texture(source, fTexCoord + direction * 0.5).rgb
+    );
+
+    // the wider blur is only taken if it did not cross into another edge
+    float luma_b = dot(color_b, LUMA);
+    if (luma_b < luma_min || luma_b > luma_max) {
+        fColor = vec4(color_a, color_m.a);
+    } else {
+        fColor = vec4(color_b, color_m.a);
+    }
+}
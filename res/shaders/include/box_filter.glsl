@@ -0,0 +1,11 @@
+// the average of a 4x4 texel area of source around uv, in four bilinear taps
+vec3 box_filter4(sampler2D source, vec2 uv, vec2 texel_size)
+{
+    vec4 offset = texel_size.xyxy * vec4(-1.0, -1.0, 1.0, 1.0);
+    return 0.25 * (
+        texture(source, uv + offset.xy).rgb +
+        texture(source, uv + offset.zy).rgb +
+        texture(source, uv + offset.xw).rgb +
+        texture(source, uv + offset.zw).rgb
+    );
+}
@@ -3,11 +3,23 @@
 
 layout(set = 0, binding = 1) uniform UniformBlock {
     vec4 ambient_light;
+    // scale in xy and offset in zw of the texture coordinates of each map, see FragUniformBlock
+    vec4 normal_uv_transform;
+    vec4 diffuse_uv_transform;
+    vec4 specular_uv_transform;
 } uniform_block;
 
+// the HAS_* toggles are defined per variant, see permutations.ron,
+// a map the material does not have leaves its set unbound
+#ifdef HAS_NORMALMAP
 layout(set = 1, binding = 0) uniform sampler2D normal_texture;
+#endif
+#ifdef HAS_BASECOLORMAP
 layout(set = 2, binding = 0) uniform sampler2D diffuse_texture;
+#endif
+#ifdef HAS_SPECULARMAP
 layout(set = 3, binding = 0) uniform sampler2D specular_texture;
+#endif
 
 layout(location = 0) in vec3 view;
 layout(location = 1) in vec3 light;
@@ -17,10 +29,28 @@ layout(location = 0) out vec4 fColor;
 
 void main()
 {
-    // Sample the textures.
-    vec4 normal = texture(normal_texture, texture_coord);
-    vec4 diffuse = texture(diffuse_texture, texture_coord);
-    vec4 specular = texture(specular_texture, texture_coord);
+    // Sample the textures, a missing map is a flat normal, white or no specular.
+#ifdef HAS_NORMALMAP
+    vec4 normal = texture(
+        normal_texture,
+        texture_coord * uniform_block.normal_uv_transform.xy + uniform_block.normal_uv_transform.zw);
+#else
+    vec4 normal = vec4(0.5, 0.5, 1.0, 1.0);
+#endif
+#ifdef HAS_BASECOLORMAP
+    vec4 diffuse = texture(
+        diffuse_texture,
+        texture_coord * uniform_block.diffuse_uv_transform.xy + uniform_block.diffuse_uv_transform.zw);
+#else
+    vec4 diffuse = vec4(1.0);
+#endif
+#ifdef HAS_SPECULARMAP
+    vec4 specular = texture(
+        specular_texture,
+        texture_coord * uniform_block.specular_uv_transform.xy + uniform_block.specular_uv_transform.zw);
+#else
+    vec4 specular = vec4(0.0);
+#endif
 
     // Determine the per-fragment lighting vectors.
 
@@ -13,6 +13,13 @@ layout(set = 0, binding = 0) uniform UniformBlock {
     vec3 light_position;
 } uniform_block;
 
+// per-draw data, see ObjectPushConstants
+layout(push_constant) uniform ObjectBlock {
+    mat4 model_matrix;
+    mat3 normal_matrix;
+    uint material_index;
+} object_block;
+
 layout(location = 0) out vec3 fView;
 layout(location = 1) out vec3 fLight;
 layout(location = 2) out vec2 fTexCoord;
@@ -27,17 +34,21 @@ void main()
     vec4 vPosition4 = vec4(vPosition, 1.0);
     vec4 vlight_position4 = vec4(uniform_block.light_position, 1.0);
 
-    vec3 N = vec3(uniform_block.normal_matrix * vec4(vNormal, 0.0));
-    vec3 T = vec3(uniform_block.normal_matrix * vec4(vTangent, 0.0));
+    vec4 world_position = object_block.model_matrix * vPosition4;
+    vec3 world_normal = object_block.normal_matrix * vNormal;
+    vec3 world_tangent = object_block.normal_matrix * vTangent;
+
+    vec3 N = vec3(uniform_block.normal_matrix * vec4(world_normal, 0.0));
+    vec3 T = vec3(uniform_block.normal_matrix * vec4(world_tangent, 0.0));
     mat3 M = transpose(mat3(T, cross(N, T), N));
 
     // Compute the per-fragment attributes.
 
-    fView     =  M * vec3(uniform_block.view_matrix * vPosition4);
+    fView     =  M * vec3(uniform_block.view_matrix * world_position);
     fLight    =  M * vec3(uniform_block.view_matrix * vlight_position4);
     fTexCoord =  vTexCoord;
 
     gl_Position = uniform_block.projection_matrix
         * uniform_block.view_matrix
-        * vPosition4;
+        * world_position;
 }
\ No newline at end of file
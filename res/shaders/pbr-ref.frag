@@ -37,13 +37,7 @@
 #extension GL_OES_standard_derivatives : enable
 
 #define USE_IBL;
-#define HAS_BASECOLORMAP;
-#define HAS_NORMALMAP;
-#define HAS_EMISSIVEMAP;
-#define HAS_METALROUGHNESSMAP;
-#define HAS_OCCLUSIONMAP;
-#define HAS_NORMALS;
-#define HAS_TANGENTS;
+// the HAS_* toggles are defined per variant, see permutations.ron
 
 precision highp float;
 
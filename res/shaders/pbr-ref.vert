@@ -21,9 +21,7 @@
 #version 450
 #extension GL_ARB_separate_shader_objects : enable
 
-#define HAS_NORMALS;
-#define HAS_TANGENTS;
-#define HAS_UV;
+// HAS_NORMALS, HAS_TANGENTS and HAS_UV are defined per variant, see permutations.ron
 
 layout(location = 0) in vec4 i_Position;
 #ifdef HAS_NORMALS
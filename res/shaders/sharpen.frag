@@ -0,0 +1,30 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+// unsharp mask over the four direct neighbours
+
+layout(set = 0, binding = 0) uniform sampler2D source;
+
+layout(push_constant) uniform SharpenBlock {
+    vec2 texel_size;
+    // 0 leaves the image as is
+    float strength;
+} sharpen_block;
+
+layout(location = 0) in vec2 fTexCoord;
+
+layout(location = 0) out vec4 fColor;
+
+void main()
+{
+    vec2 texel = sharpen_block.texel_size;
+    vec4 color = texture(source, fTexCoord);
+    vec3 neighbours = 0.25 * (
+        texture(source, fTexCoord + vec2(texel.x, 0.0)).rgb +
+        texture(source, fTexCoord - vec2(texel.x, 0.0)).rgb +
+        texture(source, fTexCoord + vec2(0.0, texel.y)).rgb +
+        texture(source, fTexCoord - vec2(0.0, texel.y)).rgb
+    );
+    vec3 sharpened = color.rgb + (color.rgb - neighbours) * sharpen_block.strength;
+    fColor = vec4(max(sharpened, 0.0), color.a);
+}
@@ -0,0 +1,47 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(set = 0, binding = 0) uniform sampler2D hdr_color;
+
+// see TonemapPushConstants
+layout(push_constant) uniform TonemapBlock {
+    float exposure;
+    uint operator;
+} tonemap_block;
+
+layout(location = 0) in vec2 fTexCoord;
+
+layout(location = 0) out vec4 fColor;
+
+const uint OPERATOR_REINHARD = 0;
+const uint OPERATOR_ACES_FILMIC = 1;
+const uint OPERATOR_LINEAR = 2;
+
+vec3 reinhard(vec3 color)
+{
+    return color / (1.0 + color);
+}
+
+// Narkowicz's fit of the ACES reference rendering transform
+vec3 aces_filmic(vec3 color)
+{
+    return clamp(
+        (color * (2.51 * color + 0.03)) / (color * (2.43 * color + 0.59) + 0.14),
+        0.0,
+        1.0
+    );
+}
+
+void main()
+{
+    vec3 color = texture(hdr_color, fTexCoord).rgb * tonemap_block.exposure;
+
+    // the result is linear, the sRGB swapchain encodes it
+    if (tonemap_block.operator == OPERATOR_ACES_FILMIC) {
+        fColor = vec4(aces_filmic(color), 1.0);
+    } else if (tonemap_block.operator == OPERATOR_LINEAR) {
+        fColor = vec4(color, 1.0);
+    } else {
+        fColor = vec4(reinhard(color), 1.0);
+    }
+}
@@ -0,0 +1,33 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+// darken the image towards its corners
+
+layout(set = 0, binding = 0) uniform sampler2D source;
+
+layout(push_constant) uniform VignetteBlock {
+    // how dark the corners get, 0 leaves the image as is
+    float intensity;
+    // distance from the center, in half heights, where the darkening starts
+    float radius;
+    // distance over which it fades in
+    float softness;
+    // width over height of the image, keeps the vignette round
+    float aspect_ratio;
+} vignette_block;
+
+layout(location = 0) in vec2 fTexCoord;
+
+layout(location = 0) out vec4 fColor;
+
+void main()
+{
+    vec4 color = texture(source, fTexCoord);
+    vec2 position = (fTexCoord - 0.5) * 2.0 * vec2(vignette_block.aspect_ratio, 1.0);
+    float vignette = smoothstep(
+        vignette_block.radius,
+        vignette_block.radius + vignette_block.softness,
+        length(position)
+    );
+    fColor = vec4(color.rgb * (1.0 - vignette * vignette_block.intensity), color.a);
+}
@@ -0,0 +1,256 @@
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::{
+        Path,
+        PathBuf,
+    },
+    sync::{
+        atomic::{
+            AtomicBool,
+            Ordering,
+        },
+        Arc,
+    },
+    thread::{
+        self,
+        JoinHandle,
+    },
+};
+
+use ::image::{
+    hdr::HDREncoder,
+    Rgb,
+    RgbaImage,
+};
+
+use super::constants::{
+    OFFSCREEN_COLOR_FORMAT,
+    OFFSCREEN_HDR_COLOR_FORMAT,
+};
+use super::hal::{
+    buffer::BufferState,
+    error::{
+        RendererError,
+        RendererResult,
+    },
+    prelude::*,
+};
+
+/// what a headless renderer reads back
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OffscreenOutput {
+    /// the post-processed image as it would be presented, 8 bits per channel
+    Display,
+    /// the linear HDR color of the scene with the effects up to Tonemap, as floats
+    Hdr,
+}
+
+impl OffscreenOutput {
+    /// Hdr for a Radiance .hdr path, which is the only float format the image crate writes
+    pub fn for_path(path: &Path) -> OffscreenOutput {
+        match extension(path).as_ref().map(|extension| extension.as_str()) {
+            Some("hdr") => OffscreenOutput::Hdr,
+            _ => OffscreenOutput::Display,
+        }
+    }
+    
+    /// the format of the color image read back
+    pub fn format(&self) -> Format {
+        match self {
+            OffscreenOutput::Display => OFFSCREEN_COLOR_FORMAT,
+            OffscreenOutput::Hdr => OFFSCREEN_HDR_COLOR_FORMAT,
+        }
+    }
+}
+
+/// an image read back by a headless renderer
+pub enum OffscreenImage {
+    Display(RgbaImage),
+    /// linear RGBA row by row from the top
+    Hdr {
+        width: u32,
+        height: u32,
+        pixels: Vec<[f32; 4]>,
+    },
+}
+
+impl OffscreenImage {
+    /// the pixels of a color image of output's format read back with tightly packed rows
+    pub fn from_readback(output: OffscreenOutput, extent: Extent, bytes: Vec<u8>) -> OffscreenImage {
+        match output {
+            OffscreenOutput::Display => OffscreenImage::Display(
+                RgbaImage::from_raw(extent.width, extent.height, bytes)
+                    .expect("readback buffer is smaller than the image")
+            ),
+            OffscreenOutput::Hdr => OffscreenImage::Hdr {
+                width: extent.width,
+                height: extent.height,
+                pixels: bytes
+                    .chunks(16)
+                    .map(|pixel| {
+                        let mut channels = [0.0; 4];
+                        for (channel, bytes) in channels.iter_mut().zip(pixel.chunks(4)) {
+                            *channel = f32::from_bits(
+                                u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+                            );
+                        }
+                        channels
+                    })
+                    .collect(),
+            },
+        }
+    }
+}
+
+/// a swapchain image being copied to host memory,
+/// it can be read once the fence of the frame context it was recorded in has signaled
+pub struct PendingScreenshot {
+    pub frame_context_index: usize,
+    pub readback_buffer: BufferState<u8>,
+    pub extent: Extent,
+    pub color_format: Format,
+}
+
+impl PendingScreenshot {
+    /// copy the pixels out of the readback buffer and leave the conversion and encoding
+    /// to another thread, so that the frames after it are not held up
+    pub fn save(self, directory: &str) -> RendererResult<ScreenshotThread> {
+        let bytes = self.readback_buffer.read_bytes()?;
+        let (extent, color_format) = (self.extent, self.color_format);
+        let path = timestamped_path(directory, "screenshot", "png");
+        let done = Arc::new(AtomicBool::new(false));
+        let thread_done = done.clone();
+        let handle = thread::spawn(move || {
+            let result = image_from_swapchain_pixels(bytes, extent, color_format)
+                .and_then(|image| save_image(&image, &path));
+            if let Err(error) = result {
+                println!("[WARN][Screenshot Failed]{}", error);
+            }
+            thread_done.store(true, Ordering::Release);
+        });
+        Ok(ScreenshotThread {
+            handle,
+            done,
+        })
+    }
+}
+
+/// a thread encoding and writing a screenshot
+pub struct ScreenshotThread {
+    handle: JoinHandle<()>,
+    // set by the thread once the screenshot has been written or has failed
+    done: Arc<AtomicBool>,
+}
+
+impl ScreenshotThread {
+    /// whether join would return without waiting
+    pub fn is_done(&self) -> bool {
+        self.done.load(Ordering::Acquire)
+    }
+    
+    pub fn join(self) {
+        if self.handle.join().is_err() {
+            println!("[WARN][Screenshot Failed]the encoding thread panicked");
+        }
+    }
+}
+
+/// the pixels of a swapchain image as RGBA, the swizzle depends on the surface format
+/// and alpha is made opaque since what the compositor does with it is not what was seen
+pub fn image_from_swapchain_pixels(
+    mut bytes: Vec<u8>,
+    extent: Extent,
+    color_format: Format,
+) -> RendererResult<RgbaImage> {
+    match color_format {
+        Format::Rgba8Srgb | Format::Rgba8Unorm => {}
+        Format::Bgra8Srgb | Format::Bgra8Unorm => {
+            for pixel in bytes.chunks_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+        _ => return Err(RendererError::Creation {
+            resource: "screenshot".to_string(),
+            reason: format!("cannot convert pixels of {:?} to RGBA", color_format),
+        }),
+    }
+    for pixel in bytes.chunks_mut(4) {
+        pixel[3] = 255;
+    }
+    Ok(RgbaImage::from_raw(extent.width, extent.height, bytes)
+        .expect("readback buffer is smaller than the image"))
+}
+
+/// directory/prefix-YYYYmmdd-HHMMSS-mmm.extension for the current local time
+pub fn timestamped_path(directory: &str, prefix: &str, extension: &str) -> PathBuf {
+    let now = time::now();
+    let timestamp = now.strftime("%Y%m%d-%H%M%S").unwrap();
+    Path::new(directory).join(format!(
+        "{}-{}-{:03}.{}",
+        prefix,
+        timestamp,
+        now.tm_nsec / 1_000_000,
+        extension,
+    ))
+}
+
+/// write an image read back by a headless renderer, an HDR one only goes to a Radiance .hdr
+pub fn save_offscreen_image(image: &OffscreenImage, path: &Path) -> RendererResult<()> {
+    match image {
+        OffscreenImage::Display(image) => save_image(image, path),
+        OffscreenImage::Hdr { width, height, pixels } => save_hdr(*width, *height, pixels, path),
+    }
+}
+
+/// write an image read back from the renderer, the file format follows the extension of path
+///
+/// a Radiance .hdr is only written from the HDR color, which a headless renderer reads back
+/// when it is created with OffscreenOutput::Hdr
+pub fn save_image(image: &RgbaImage, path: &Path) -> RendererResult<()> {
+    let path_name = path.to_string_lossy();
+    if extension(path).as_ref().map(|extension| extension.as_str()) == Some("hdr") {
+        return Err(RendererError::write(
+            &path_name,
+            "a Radiance HDR is written from the HDR color, render it with OffscreenOutput::Hdr",
+        ));
+    }
+    create_parent_directory(path)?;
+    image
+        .save(path)
+        .map_err(|error| RendererError::write(&path_name, error))?;
+    println!("[INFO][Image Saved]{} {}x{}", path_name, image.width(), image.height());
+    Ok(())
+}
+
+/// write linear RGBA pixels, row by row from the top, to a Radiance .hdr at path,
+/// which has no alpha channel
+pub fn save_hdr(width: u32, height: u32, pixels: &[[f32; 4]], path: &Path) -> RendererResult<()> {
+    let path_name = path.to_string_lossy();
+    if extension(path).as_ref().map(|extension| extension.as_str()) != Some("hdr") {
+        return Err(RendererError::write(&path_name, "an HDR render can only be saved as .hdr"));
+    }
+    create_parent_directory(path)?;
+    let rgb_pixels: Vec<Rgb<f32>> = pixels
+        .iter()
+        .map(|pixel| Rgb([pixel[0], pixel[1], pixel[2]]))
+        .collect();
+    let file = File::create(path).map_err(|error| RendererError::write(&path_name, error))?;
+    HDREncoder::new(BufWriter::new(file))
+        .encode(&rgb_pixels, width as usize, height as usize)
+        .map_err(|error| RendererError::write(&path_name, error))?;
+    println!("[INFO][Image Saved]{} {}x{}", path_name, width, height);
+    Ok(())
+}
+
+fn extension(path: &Path) -> Option<String> {
+    path.extension().map(|extension| extension.to_string_lossy().to_lowercase())
+}
+
+fn create_parent_directory(path: &Path) -> RendererResult<()> {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => ::std::fs::create_dir_all(parent)
+            .map_err(|error| RendererError::write(&path.to_string_lossy(), error)),
+        _ => Ok(()),
+    }
+}
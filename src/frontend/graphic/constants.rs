@@ -1,13 +1,70 @@
 use super::hal::prelude::*;
+use super::hal::swapchain::PresentModePolicy;
+use super::hal::tonemap::{
+    TonemapOperator,
+    TonemapSettings,
+};
+use super::window::WindowMode;
 
 pub const WINDOW_SIZE: winit::dpi::LogicalSize = winit::dpi::LogicalSize {
     width: RENDER_SIZE.width as f64,
     height: RENDER_SIZE.height as f64,
 };
+/// size the window is created with, the swapchain follows the size of the window after that
 pub const RENDER_SIZE: gfx_hal::window::Extent2D = gfx_hal::window::Extent2D {
     width: 1920,
     height: 1080,
 };
+/// edge length in texels of each face of a cubemap converted from an environment map
+pub const ENVIRONMENT_MAP_FACE_SIZE: u32 = 512;
+/// textures in every descriptor pool of a TextureRegistry, another pool is added when they are all used
+pub const TEXTURE_DESCRIPTOR_POOL_SETS: usize = 64;
+/// path under res/ of the model shown by the renderer
+pub const MODEL_PATH: &str = "models/Chest.obj";
+/// path under res/ of the material of the model, and the directory its texture maps are in
+pub const MATERIAL_PATH: &str = "models/Chest.mtl";
+pub const MATERIAL_DIRECTORY: &str = "models";
+/// present mode policy the swapchain is first created with, V cycles through the others
+pub const DEFAULT_PRESENT_MODE_POLICY: PresentModePolicy = PresentModePolicy::Vsync;
+/// number of frames the CPU may record ahead of the GPU,
+/// each has its own fence, semaphores, command pool and uniform ring slot
+pub const FRAMES_IN_FLIGHT: usize = 2;
+/// whether the depth attachment needs a stencil aspect, which narrows the depth formats to pick from
+pub const DEPTH_STENCIL_REQUIRED: bool = false;
+/// format of the color image the scene is rendered to, tonemapped into the output image afterwards
+pub const HDR_COLOR_FORMAT: Format = Format::Rgba16Sfloat;
+/// tonemapping the renderer starts with, T cycles the operator and +/- change the exposure
+pub const DEFAULT_TONEMAP_SETTINGS: TonemapSettings = TonemapSettings {
+    operator: TonemapOperator::AcesFilmic,
+    exposure: 0.0,
+};
+/// stops the exposure changes by with every press of + or -
+pub const EXPOSURE_STEP: f32 = 0.5;
+/// path under res/ of the RON encoded PostProcessConfig, reloaded whenever it changes
+pub const POST_PROCESS_CONFIG_PATH: &str = "post_process.ron";
+/// format of the images passed between post-processing passes, wide enough for the HDR ones
+pub const POST_PROCESS_FORMAT: Format = Format::Rgba16Sfloat;
+/// most times Bloom may halve the bright parts, an image 65536 texels wide is one texel by then
+pub const MAX_BLOOM_LEVELS: usize = 16;
+/// format of the color image a headless renderer tonemaps to and reads back, stored as is in a PNG
+pub const OFFSCREEN_COLOR_FORMAT: Format = Format::Rgba8Srgb;
+/// format of the color image a headless renderer keeps linear HDR colors in for a Radiance HDR,
+/// read back as the 32 bit floats the file is encoded from
+pub const OFFSCREEN_HDR_COLOR_FORMAT: Format = Format::Rgba32Sfloat;
+/// where `--headless` saves its render when no path is given
+pub const HEADLESS_RENDER_PATH: &str = "render.png";
+/// where F12 screenshots are saved, relative to the working directory
+pub const SCREENSHOT_DIRECTORY: &str = "screenshots";
+/// samples per pixel asked for, lowered to what the adapter supports
+pub const MSAA_SAMPLES: image::NumSamples = 4;
+/// how often files loaded from res/ are checked for modification
+pub const HOT_RELOAD_POLL_INTERVAL_MS: i64 = 500;
+/// directory under res/ of the GLSL sources, compiled again whenever one changes
+pub const SHADER_SOURCE_DIRECTORY: &str = "shaders";
+/// directory under res/ build.rs writes the SPIR-V of every source to
+pub const SHADER_GEN_DIRECTORY: &str = "shaders/gen";
+/// the mode Alt+Enter switches to from windowed
+pub const FULLSCREEN_MODE: WindowMode = WindowMode::Borderless { monitor: None };
 pub const WINDOW_TITLE: &str = &"gem";
 pub const INSTANCE_NAME: &str = WINDOW_TITLE;
 pub const COLOR_RANGE: SubresourceRange =
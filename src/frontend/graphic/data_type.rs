@@ -1,3 +1,18 @@
+use std::{
+    mem,
+    slice,
+};
+
+use cgmath::{
+    Matrix,
+    Matrix4,
+    SquareMatrix,
+};
+use gfx_hal::{
+    format::Format,
+    pso::Element,
+};
+
 #[derive(Debug, Clone, Copy)]
 #[repr(C)]
 pub struct Vertex {
@@ -7,17 +22,128 @@ pub struct Vertex {
     pub texture: [f32; 2],
 }
 
+impl Vertex {
+    /// the locations the vertex shaders read the fields from
+    pub const POSITION_LOCATION: u32 = 0;
+    pub const NORMAL_LOCATION: u32 = 1;
+    pub const TANGENT_LOCATION: u32 = 2;
+    pub const TEXTURE_LOCATION: u32 = 3;
+    
+    /// the location every field is read from together with its format and offset
+    pub fn elements() -> Vec<(u32, Element<Format>)> {
+        let vertex = Vertex {
+            position: [0.0; 3],
+            normal: [0.0; 3],
+            tangent: [0.0; 3],
+            texture: [0.0; 2],
+        };
+        let base = &vertex as *const Vertex as usize;
+        let offset = |field: *const f32| (field as usize - base) as u32;
+        vec![
+            (Vertex::POSITION_LOCATION, Element {
+                format: Format::Rgb32Sfloat,
+                offset: offset(vertex.position.as_ptr()),
+            }),
+            (Vertex::NORMAL_LOCATION, Element {
+                format: Format::Rgb32Sfloat,
+                offset: offset(vertex.normal.as_ptr()),
+            }),
+            (Vertex::TANGENT_LOCATION, Element {
+                format: Format::Rgb32Sfloat,
+                offset: offset(vertex.tangent.as_ptr()),
+            }),
+            (Vertex::TEXTURE_LOCATION, Element {
+                format: Format::Rg32Sfloat,
+                offset: offset(vertex.texture.as_ptr()),
+            }),
+        ]
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 #[repr(C)]
 pub struct VertUniformBlock {
     pub projection_matrix: [[f32; 4]; 4],
-    pub model_view_matrix: [[f32; 4]; 4],
+    pub view_matrix: [[f32; 4]; 4],
     pub normal_matrix: [[f32; 4]; 4],
     pub light_position: [f32; 3],
 }
 
+/// laid out like the std140 uniform block in object.frag
 #[derive(Debug, Clone, Copy)]
 #[repr(C)]
 pub struct FragUniformBlock {
     pub ambient_light: [f32; 4],
+    // the TextureOptions::uv_transform of every texture map of the material
+    pub normal_uv_transform: [f32; 4],
+    pub diffuse_uv_transform: [f32; 4],
+    pub specular_uv_transform: [f32; 4],
+}
+
+/// per-draw data of the object pipeline, pushed as push constants before every draw,
+/// laid out like the std430 push constant block in object.vert where each column of the
+/// mat3 takes 16 bytes
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct ObjectPushConstants {
+    pub model_matrix: [[f32; 4]; 4],
+    pub normal_matrix: [[f32; 4]; 3],
+    pub material_index: u32,
+    pub _padding: [u32; 3],
+}
+
+/// the minimum maxPushConstantsSize every implementation supports
+pub const MAX_PUSH_CONSTANTS_SIZE: usize = 128;
+
+impl ObjectPushConstants {
+    /// the normal matrix is derived from model_matrix so that
+    /// non-uniformly scaled objects still get perpendicular normals
+    pub fn new(model_matrix: Matrix4<f32>, material_index: u32) -> ObjectPushConstants {
+        let normal = model_matrix
+            .invert()
+            .map(|inverse| inverse.transpose())
+            .unwrap_or_else(Matrix4::identity);
+        ObjectPushConstants {
+            model_matrix: model_matrix.into(),
+            normal_matrix: [
+                [normal.x.x, normal.x.y, normal.x.z, 0.0],
+                [normal.y.x, normal.y.y, normal.y.z, 0.0],
+                [normal.z.x, normal.z.y, normal.z.z, 0.0],
+            ],
+            material_index,
+            _padding: [0; 3],
+        }
+    }
+    
+    /// push constants are given to the command buffer as 32 bit words
+    pub fn as_words(&self) -> &[u32] {
+        unsafe {
+            slice::from_raw_parts(
+                self as *const ObjectPushConstants as *const u32,
+                mem::size_of::<ObjectPushConstants>() / mem::size_of::<u32>(),
+            )
+        }
+    }
+}
+
+/// what the tonemapping pass is told about the frame, laid out like the push constant block
+/// in tonemap.frag
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct TonemapPushConstants {
+    // linear scale applied to the HDR color before the curve
+    pub exposure: f32,
+    pub operator: u32,
+}
+
+impl TonemapPushConstants {
+    /// push constants are given to the command buffer as 32 bit words
+    pub fn as_words(&self) -> &[u32] {
+        unsafe {
+            slice::from_raw_parts(
+                self as *const TonemapPushConstants as *const u32,
+                mem::size_of::<TonemapPushConstants>() / mem::size_of::<u32>(),
+            )
+        }
+    }
 }
@@ -0,0 +1,131 @@
+use std::io::{BufReader, Cursor};
+
+use ::image::{hdr::HDRDecoder, ImageResult};
+
+use crate::PI;
+
+/// number of faces of a cubemap, in the order of +X, -X, +Y, -Y, +Z, -Z
+pub const CUBE_FACES: usize = 6;
+
+/// a decoded floating-point Radiance image, stored row by row
+pub struct HdrImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<[f32; 3]>,
+}
+
+impl HdrImage {
+    pub fn from_radiance_bytes(bytes: &[u8]) -> ImageResult<HdrImage> {
+        let decoder = HDRDecoder::new(BufReader::new(Cursor::new(bytes)))?;
+        let metadata = decoder.metadata();
+        let pixels = decoder
+            .read_image_hdr()?
+            .into_iter()
+            .map(|pixel| pixel.data)
+            .collect();
+        Ok(HdrImage {
+            width: metadata.width,
+            height: metadata.height,
+            pixels,
+        })
+    }
+    
+    fn texel(&self, x: u32, y: u32) -> [f32; 3] {
+        self.pixels[(y * self.width + x) as usize]
+    }
+    
+    /// bilinear sample with the horizontal axis wrapping around
+    /// and the vertical axis clamped at the poles
+    fn sample(&self, s: f32, t: f32) -> [f32; 3] {
+        let x = s * self.width as f32 - 0.5;
+        let y = (t * self.height as f32 - 0.5)
+            .max(0.0)
+            .min((self.height - 1) as f32);
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let fx = x - x0;
+        let fy = y - y0;
+        
+        let wrap = |x: f32| -> u32 {
+            let width = self.width as i64;
+            (((x as i64 % width) + width) % width) as u32
+        };
+        let x0_index = wrap(x0);
+        let x1_index = wrap(x0 + 1.0);
+        let y0_index = y0 as u32;
+        let y1_index = (y0_index + 1).min(self.height - 1);
+        
+        let a = self.texel(x0_index, y0_index);
+        let b = self.texel(x1_index, y0_index);
+        let c = self.texel(x0_index, y1_index);
+        let d = self.texel(x1_index, y1_index);
+        
+        let mut result = [0.0; 3];
+        for channel in 0..3 {
+            let top = a[channel] * (1.0 - fx) + b[channel] * fx;
+            let bottom = c[channel] * (1.0 - fx) + d[channel] * fx;
+            result[channel] = top * (1.0 - fy) + bottom * fy;
+        }
+        result
+    }
+}
+
+/// six square faces of RGBA floating-point texels,
+/// laid out face after face so that it can be copied into a layered image directly
+pub struct CubemapData {
+    pub face_size: u32,
+    pub texels: Vec<[f32; 4]>,
+}
+
+impl CubemapData {
+    pub fn into_bytes(self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.texels.len() * 16);
+        for texel in self.texels {
+            for channel in texel.iter() {
+                bytes.extend_from_slice(&channel.to_bits().to_le_bytes());
+            }
+        }
+        bytes
+    }
+}
+
+/// direction through the texel center of a cubemap face,
+/// following the face orientation convention of Vulkan
+fn face_direction(face: usize, u: f32, v: f32) -> [f32; 3] {
+    match face {
+        0 => [1.0, -v, -u],
+        1 => [-1.0, -v, u],
+        2 => [u, 1.0, v],
+        3 => [u, -1.0, -v],
+        4 => [u, -v, 1.0],
+        5 => [-u, -v, -1.0],
+        _ => unreachable!(),
+    }
+}
+
+/// resample a latitude-longitude environment map into the six faces of a cubemap
+pub fn equirectangular_to_cubemap(image: &HdrImage, face_size: u32) -> CubemapData {
+    let mut texels = Vec::with_capacity(CUBE_FACES * (face_size * face_size) as usize);
+    for face in 0..CUBE_FACES {
+        for y in 0..face_size {
+            for x in 0..face_size {
+                let u = 2.0 * (x as f32 + 0.5) / face_size as f32 - 1.0;
+                let v = 2.0 * (y as f32 + 0.5) / face_size as f32 - 1.0;
+                let [dx, dy, dz] = face_direction(face, u, v);
+                let length = (dx * dx + dy * dy + dz * dz).sqrt();
+                
+                let phi = dz.atan2(dx);
+                let theta = (dy / length).max(-1.0).min(1.0).acos();
+                let s = 0.5 + phi / (2.0 * PI);
+                let t = theta / PI;
+                
+                let [r, g, b] = image.sample(s, t);
+                texels.push([r, g, b, 1.0]);
+            }
+        }
+    }
+    CubemapData {
+        face_size,
+        texels,
+    }
+}
@@ -1,4 +1,10 @@
-use super::prelude::*;
+use super::{
+    error::{
+        RendererError,
+        RendererResult,
+    },
+    prelude::*,
+};
 
 /// a struct contain Adapter
 /// and caching some information of Adapter, which is good for later use
@@ -9,12 +15,15 @@ pub struct AdapterState {
 }
 
 impl AdapterState {
-    pub fn new(adapters: &mut Vec<Adapter<B>>) -> AdapterState {
+    pub fn new(adapters: &mut Vec<Adapter<B>>) -> RendererResult<AdapterState> {
         for adapter in adapters.iter() {
             println!("[INFO][Adapter Detected]{:?}", adapter.info);
         }
+        if adapters.is_empty() {
+            return Err(RendererError::NoAdapter);
+        }
         
-        AdapterState::new_from_adapter(adapters.remove(0))
+        Ok(AdapterState::new_from_adapter(adapters.remove(0)))
     }
     
     fn new_from_adapter(adapter: Adapter<B>) -> AdapterState {
@@ -33,9 +42,10 @@ impl AdapterState {
     }
     pub fn choose_memory_type_from_memory_requirement(
         &self,
+        resource: &str,
         memory_requirements: Requirements,
         properties: Properties,
-    ) -> MemoryTypeId {
+    ) -> RendererResult<MemoryTypeId> {
         self.memory_types
             .iter()
             .enumerate()
@@ -43,8 +53,12 @@ impl AdapterState {
                 memory_requirements.type_mask & (1 << id) != 0
                     && memory_type.properties.contains(properties)
             })
-            .unwrap()
-            .into()
+            .map(|id| id.into())
+            .ok_or_else(|| RendererError::NoSuitableMemoryType {
+                resource: resource.to_string(),
+                type_mask: memory_requirements.type_mask,
+                properties,
+            })
     }
 }
 
@@ -0,0 +1,858 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    iter,
+    ops::Range,
+    ptr,
+};
+
+use super::{
+    error::{
+        RendererError,
+        RendererResult,
+    },
+    prelude::*,
+};
+
+/// size of the blocks requested from the device for long lived resources,
+/// resources larger than this get a block of their own
+pub const GENERAL_CHUNK_SIZE: u64 = 64 * 1024 * 1024;
+/// size of the blocks requested from the device for short lived resources
+pub const LINEAR_CHUNK_SIZE: u64 = 16 * 1024 * 1024;
+/// the smallest maxMemoryAllocationCount an implementation is allowed to report
+pub const MIN_MEMORY_ALLOCATION_COUNT: usize = 4096;
+
+/// how an allocation is placed inside the blocks of its memory type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AllocationStrategy {
+    /// resources that live for a while and are freed in any order,
+    /// served first fit from a free list
+    General,
+    /// resources that live for a short time such as staging buffers,
+    /// bump allocated and the block rewound once everything in it is freed
+    Linear,
+}
+
+/// what an allocation is used for, only used to account for memory usage
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MemoryCategory {
+    Vertex,
+    Index,
+    Uniform,
+    Texture,
+    Depth,
+    /// color attachments rendered to and not presented
+    RenderTarget,
+    /// host visible buffers only copied from
+    Staging,
+    /// host visible buffers the device copies results into
+    Readback,
+    Other,
+}
+
+impl MemoryCategory {
+    pub fn from_buffer_usage(usage: buffer::Usage) -> MemoryCategory {
+        if usage.contains(buffer::Usage::VERTEX) {
+            MemoryCategory::Vertex
+        } else if usage.contains(buffer::Usage::INDEX) {
+            MemoryCategory::Index
+        } else if usage.contains(buffer::Usage::UNIFORM) {
+            MemoryCategory::Uniform
+        } else if usage.contains(buffer::Usage::TRANSFER_SRC) {
+            MemoryCategory::Staging
+        } else if usage.contains(buffer::Usage::TRANSFER_DST) {
+            MemoryCategory::Readback
+        } else {
+            MemoryCategory::Other
+        }
+    }
+    
+    pub fn from_image_usage(usage: image::Usage) -> MemoryCategory {
+        if usage.contains(image::Usage::DEPTH_STENCIL_ATTACHMENT) {
+            MemoryCategory::Depth
+        } else if usage.contains(image::Usage::COLOR_ATTACHMENT) {
+            MemoryCategory::RenderTarget
+        } else if usage.contains(image::Usage::SAMPLED) {
+            MemoryCategory::Texture
+        } else {
+            MemoryCategory::Other
+        }
+    }
+}
+
+/// a range of device memory handed out by MemoryAllocator,
+/// it must be given back through MemoryAllocator::free
+#[derive(Debug)]
+pub struct Allocation {
+    memory_type: usize,
+    category: MemoryCategory,
+    strategy: AllocationStrategy,
+    chunk_id: usize,
+    // the range taken out of the free list, including the padding in front of offset
+    reserved: Range<u64>,
+    pub offset: u64,
+    pub size: u64,
+}
+
+impl Allocation {
+    pub fn memory_type(&self) -> MemoryTypeId {
+        self.memory_type.into()
+    }
+    
+    pub fn category(&self) -> MemoryCategory {
+        self.category
+    }
+}
+
+struct Chunk {
+    id: usize,
+    memory: <B as TB>::Memory,
+    // host visible blocks stay mapped for their whole life
+    mapped: Option<*mut u8>,
+    space: ChunkSpace,
+}
+
+/// which ranges of a block are handed out, kept apart from the device memory of the block
+struct ChunkSpace {
+    size: u64,
+    // sorted and never adjacent, only used by AllocationStrategy::General
+    free_ranges: Vec<Range<u64>>,
+    // only used by AllocationStrategy::Linear
+    cursor: u64,
+    allocation_count: usize,
+}
+
+impl ChunkSpace {
+    fn new(size: u64) -> ChunkSpace {
+        ChunkSpace {
+            size,
+            free_ranges: vec![0..size],
+            cursor: 0,
+            allocation_count: 0,
+        }
+    }
+    
+    /// the range reserved for size bytes and the aligned offset in it, none if they do not fit
+    fn allocate(
+        &mut self,
+        strategy: AllocationStrategy,
+        size: u64,
+        alignment: u64,
+    ) -> Option<(Range<u64>, u64)> {
+        match strategy {
+            AllocationStrategy::General => self.allocate_from_free_list(size, alignment),
+            AllocationStrategy::Linear => self.allocate_linear(size, alignment),
+        }
+    }
+    
+    /// give back a range returned by allocate, a linear block is rewound once it is empty
+    fn free(&mut self, strategy: AllocationStrategy, reserved: Range<u64>) {
+        self.allocation_count -= 1;
+        match strategy {
+            AllocationStrategy::General => self.free_to_free_list(reserved),
+            AllocationStrategy::Linear => if self.allocation_count == 0 {
+                self.cursor = 0;
+            },
+        }
+    }
+    
+    fn allocate_from_free_list(&mut self, size: u64, alignment: u64) -> Option<(Range<u64>, u64)> {
+        let position = self.free_ranges.iter().position(|range| {
+            let offset = align_up(range.start, alignment);
+            offset + size <= range.end
+        })?;
+        let range = self.free_ranges.remove(position);
+        let offset = align_up(range.start, alignment);
+        let end = offset + size;
+        if end < range.end {
+            self.free_ranges.insert(position, end..range.end);
+        }
+        self.allocation_count += 1;
+        Some((range.start..end, offset))
+    }
+    
+    fn allocate_linear(&mut self, size: u64, alignment: u64) -> Option<(Range<u64>, u64)> {
+        let offset = align_up(self.cursor, alignment);
+        if offset + size > self.size {
+            return None;
+        }
+        let reserved = self.cursor..offset + size;
+        self.cursor = offset + size;
+        self.allocation_count += 1;
+        Some((reserved, offset))
+    }
+    
+    fn free_to_free_list(&mut self, reserved: Range<u64>) {
+        let position = self.free_ranges
+            .iter()
+            .position(|range| range.start > reserved.start)
+            .unwrap_or(self.free_ranges.len());
+        self.free_ranges.insert(position, reserved);
+        
+        // merge with the following range, then with the preceding one
+        if position + 1 < self.free_ranges.len()
+            && self.free_ranges[position].end == self.free_ranges[position + 1].start {
+            let next = self.free_ranges.remove(position + 1);
+            self.free_ranges[position].end = next.end;
+        }
+        if position > 0
+            && self.free_ranges[position - 1].end == self.free_ranges[position].start {
+            let current = self.free_ranges.remove(position);
+            self.free_ranges[position - 1].end = current.end;
+        }
+    }
+    
+    fn used(&self, strategy: AllocationStrategy) -> u64 {
+        match strategy {
+            AllocationStrategy::General =>
+                self.size - self.free_ranges.iter().map(|range| range.end - range.start).sum::<u64>(),
+            AllocationStrategy::Linear => self.cursor,
+        }
+    }
+    
+    fn largest_free_range(&self, strategy: AllocationStrategy) -> u64 {
+        match strategy {
+            AllocationStrategy::General => self.free_ranges
+                .iter()
+                .map(|range| range.end - range.start)
+                .max()
+                .unwrap_or(0),
+            AllocationStrategy::Linear => self.size - self.cursor,
+        }
+    }
+}
+
+struct MemoryTypePool {
+    properties: Properties,
+    general: Vec<Chunk>,
+    linear: Vec<Chunk>,
+}
+
+impl MemoryTypePool {
+    fn chunks_mut(&mut self, strategy: AllocationStrategy) -> &mut Vec<Chunk> {
+        match strategy {
+            AllocationStrategy::General => &mut self.general,
+            AllocationStrategy::Linear => &mut self.linear,
+        }
+    }
+}
+
+/// usage of one memory type, as reported by MemoryAllocator::stats
+#[derive(Debug, Clone, Default)]
+pub struct MemoryTypeStats {
+    pub memory_type: usize,
+    /// number of blocks allocated from the device
+    pub chunk_count: usize,
+    pub allocation_count: usize,
+    /// bytes allocated from the device
+    pub reserved: u64,
+    /// bytes handed out, including alignment padding
+    pub used: u64,
+    pub largest_free_range: u64,
+    /// 0 when all free memory is one range, approaching 1 the more it is scattered
+    pub fragmentation: f32,
+}
+
+/// the memory held by the allocations of one category in one memory type
+#[derive(Debug, Clone)]
+pub struct MemoryUsage {
+    pub category: MemoryCategory,
+    pub memory_type: usize,
+    pub allocation_count: usize,
+    pub bytes: u64,
+    /// the most bytes held at once since the allocator was created
+    pub peak_bytes: u64,
+}
+
+/// a snapshot of the memory used by the renderer
+#[derive(Debug, Clone)]
+pub struct MemoryReport {
+    pub usages: Vec<MemoryUsage>,
+    /// bytes held by allocations over every category and memory type
+    pub total_bytes: u64,
+    pub peak_total_bytes: u64,
+    /// bytes allocated from the device in blocks, including what is not handed out yet
+    pub reserved_bytes: u64,
+    pub memory_types: Vec<MemoryTypeStats>,
+}
+
+impl fmt::Display for MemoryReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "[INFO][Memory Usage] {} in use, {} at peak, {} reserved from the device",
+            format_bytes(self.total_bytes),
+            format_bytes(self.peak_total_bytes),
+            format_bytes(self.reserved_bytes)
+        )?;
+        for usage in self.usages.iter() {
+            writeln!(
+                f,
+                "    {:<14} memory type {:<2} {:>6} allocations {:>12} in use {:>12} at peak",
+                format!("{:?}", usage.category),
+                usage.memory_type,
+                usage.allocation_count,
+                format_bytes(usage.bytes),
+                format_bytes(usage.peak_bytes)
+            )?;
+        }
+        for stats in self.memory_types.iter() {
+            writeln!(
+                f,
+                "    memory type {:<2} {:>3} blocks {:>12} reserved {:>12} used, fragmentation {:.2}",
+                stats.memory_type,
+                stats.chunk_count,
+                format_bytes(stats.reserved),
+                format_bytes(stats.used),
+                stats.fragmentation
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const KIB: u64 = 1024;
+    const MIB: u64 = 1024 * KIB;
+    if bytes >= MIB {
+        format!("{:.1} MiB", bytes as f64 / MIB as f64)
+    } else if bytes >= KIB {
+        format!("{:.1} KiB", bytes as f64 / KIB as f64)
+    } else {
+        format!("{} B", bytes)
+    }
+}
+
+/// sub-allocates resources out of large blocks of device memory,
+/// so that the number of device allocations stays far below maxMemoryAllocationCount
+pub struct MemoryAllocator {
+    pools: Vec<MemoryTypePool>,
+    // alignment between linear and optimal resources sharing a block
+    buffer_image_granularity: u64,
+    non_coherent_atom_size: u64,
+    device_allocation_count: usize,
+    next_chunk_id: usize,
+    usages: BTreeMap<(MemoryCategory, usize), MemoryUsage>,
+    total_bytes: u64,
+    peak_total_bytes: u64,
+}
+
+impl MemoryAllocator {
+    pub fn new(memory_types: &[MemoryType], limits: &Limits) -> MemoryAllocator {
+        MemoryAllocator {
+            pools: memory_types
+                .iter()
+                .map(|memory_type| MemoryTypePool {
+                    properties: memory_type.properties,
+                    general: vec![],
+                    linear: vec![],
+                })
+                .collect(),
+            buffer_image_granularity: (limits.buffer_image_granularity as u64).max(1),
+            non_coherent_atom_size: (limits.non_coherent_atom_size as u64).max(1),
+            device_allocation_count: 0,
+            next_chunk_id: 0,
+            usages: BTreeMap::new(),
+            total_bytes: 0,
+            peak_total_bytes: 0,
+        }
+    }
+    
+    /// the first memory type allowed by requirements that has all of properties
+    pub fn choose_memory_type(
+        &self,
+        resource: &str,
+        requirements: &Requirements,
+        properties: Properties,
+    ) -> RendererResult<usize> {
+        self.pools
+            .iter()
+            .enumerate()
+            .position(|(id, pool)| {
+                requirements.type_mask & (1 << id) != 0
+                    && pool.properties.contains(properties)
+            })
+            .ok_or_else(|| RendererError::NoSuitableMemoryType {
+                resource: resource.to_string(),
+                type_mask: requirements.type_mask,
+                properties,
+            })
+    }
+    
+    /// resource only names what the memory is for in errors
+    pub fn allocate(
+        &mut self,
+        device: &<B as TB>::Device,
+        resource: &str,
+        category: MemoryCategory,
+        requirements: Requirements,
+        properties: Properties,
+        strategy: AllocationStrategy,
+    ) -> RendererResult<Allocation> {
+        let memory_type = self.choose_memory_type(resource, &requirements, properties)?;
+        // every block may hold both buffers and optimal tiling images,
+        // aligning everything to the granularity keeps them from sharing a page
+        let alignment = requirements.alignment.max(self.buffer_image_granularity);
+        let size = align_up(requirements.size, self.buffer_image_granularity);
+        
+        let found = self.pools[memory_type]
+            .chunks_mut(strategy)
+            .iter_mut()
+            .filter_map(|chunk| chunk.space
+                .allocate(strategy, size, alignment)
+                .map(|placed| (chunk.id, placed)))
+            .next();
+        
+        let (chunk_id, (reserved, offset)) = match found {
+            Some(found) => found,
+            None => {
+                let default_chunk_size = match strategy {
+                    AllocationStrategy::General => GENERAL_CHUNK_SIZE,
+                    AllocationStrategy::Linear => LINEAR_CHUNK_SIZE,
+                };
+                let chunk_size = default_chunk_size.max(size);
+                let mut chunk = self.allocate_chunk(device, resource, memory_type, chunk_size)?;
+                let placed = chunk.space.allocate(strategy, size, alignment).unwrap();
+                let chunk_id = chunk.id;
+                self.pools[memory_type].chunks_mut(strategy).push(chunk);
+                (chunk_id, placed)
+            }
+        };
+        
+        self.track_allocate(category, memory_type, size);
+        
+        Ok(Allocation {
+            memory_type,
+            category,
+            strategy,
+            chunk_id,
+            reserved,
+            offset,
+            size,
+        })
+    }
+    
+    pub fn free(&mut self, device: &<B as TB>::Device, allocation: Allocation) {
+        self.track_free(allocation.category, allocation.memory_type, allocation.size);
+        
+        let chunks = self.pools[allocation.memory_type].chunks_mut(allocation.strategy);
+        let index = chunks
+            .iter()
+            .position(|chunk| chunk.id == allocation.chunk_id)
+            .expect("allocation does not belong to this allocator");
+        
+        let empty = {
+            let space = &mut chunks[index].space;
+            space.free(allocation.strategy, allocation.reserved);
+            space.allocation_count == 0
+        };
+        
+        // keep one empty block around so that allocating and freeing in a loop
+        // does not go to the device every time
+        let empty_chunk_count = chunks
+            .iter()
+            .filter(|chunk| chunk.space.allocation_count == 0)
+            .count();
+        if empty && empty_chunk_count > 1 {
+            let chunk = chunks.remove(index);
+            self.free_chunk(device, chunk);
+        }
+    }
+    
+    pub fn memory(&self, allocation: &Allocation) -> &<B as TB>::Memory {
+        &self.chunk(allocation).memory
+    }
+    
+    /// copy bytes into a host visible allocation at offset from its start,
+    /// flushing them when the memory is not host coherent
+    pub fn write(
+        &self,
+        device: &<B as TB>::Device,
+        allocation: &Allocation,
+        offset: u64,
+        bytes: &[u8],
+    ) -> RendererResult<()> {
+        self.copy(allocation, offset, bytes);
+        self.flush(device, allocation, offset..offset + bytes.len() as u64)
+    }
+    
+    /// copy bytes into a host visible allocation without flushing them,
+    /// the caller must flush the range before the device reads it
+    pub fn copy(&self, allocation: &Allocation, offset: u64, bytes: &[u8]) {
+        assert!(
+            offset + bytes.len() as u64 <= allocation.size,
+            "write out of the bounds of the allocation"
+        );
+        let chunk = self.chunk(allocation);
+        let mapped = chunk.mapped.expect("allocation is not host visible");
+        unsafe {
+            ptr::copy_nonoverlapping(
+                bytes.as_ptr(),
+                mapped.offset((allocation.offset + offset) as isize),
+                bytes.len(),
+            );
+        }
+    }
+    
+    /// make host writes to a range of the allocation visible to the device,
+    /// the range is widened to nonCoherentAtomSize as required
+    pub fn flush(
+        &self,
+        device: &<B as TB>::Device,
+        allocation: &Allocation,
+        range: Range<u64>,
+    ) -> RendererResult<()> {
+        let pool = &self.pools[allocation.memory_type];
+        if pool.properties.contains(Properties::COHERENT) || range.start == range.end {
+            return Ok(());
+        }
+        let chunk = self.chunk(allocation);
+        let start = align_down(allocation.offset + range.start, self.non_coherent_atom_size);
+        let end = align_up(allocation.offset + range.end, self.non_coherent_atom_size)
+            .min(chunk.space.size);
+        unsafe {
+            device
+                .flush_mapped_memory_ranges(iter::once((&chunk.memory, start..end)))
+                .map_err(|error| RendererError::device("flush mapped memory", error))
+        }
+    }
+    
+    /// copy len bytes out of a host visible allocation at offset from its start,
+    /// invalidating them first when the memory is not host coherent
+    pub fn read(
+        &self,
+        device: &<B as TB>::Device,
+        allocation: &Allocation,
+        offset: u64,
+        len: usize,
+    ) -> RendererResult<Vec<u8>> {
+        assert!(
+            offset + len as u64 <= allocation.size,
+            "read out of the bounds of the allocation"
+        );
+        let pool = &self.pools[allocation.memory_type];
+        let chunk = self.chunk(allocation);
+        let mapped = chunk.mapped.expect("allocation is not host visible");
+        if !pool.properties.contains(Properties::COHERENT) && len > 0 {
+            let start = align_down(allocation.offset + offset, self.non_coherent_atom_size);
+            let end = align_up(allocation.offset + offset + len as u64, self.non_coherent_atom_size)
+                .min(chunk.space.size);
+            unsafe {
+                device
+                    .invalidate_mapped_memory_ranges(iter::once((&chunk.memory, start..end)))
+                    .map_err(|error| RendererError::device("invalidate mapped memory", error))?;
+            }
+        }
+        let mut bytes = vec![0u8; len];
+        unsafe {
+            ptr::copy_nonoverlapping(
+                mapped.offset((allocation.offset + offset) as isize),
+                bytes.as_mut_ptr(),
+                len,
+            );
+        }
+        Ok(bytes)
+    }
+    
+    pub fn stats(&self) -> Vec<MemoryTypeStats> {
+        self.pools
+            .iter()
+            .enumerate()
+            .filter(|(_, pool)| !pool.general.is_empty() || !pool.linear.is_empty())
+            .map(|(memory_type, pool)| {
+                let mut stats = MemoryTypeStats {
+                    memory_type,
+                    ..MemoryTypeStats::default()
+                };
+                let mut free = 0;
+                let strategies = iter::repeat(AllocationStrategy::General)
+                    .zip(pool.general.iter())
+                    .chain(iter::repeat(AllocationStrategy::Linear).zip(pool.linear.iter()));
+                for (strategy, chunk) in strategies {
+                    let space = &chunk.space;
+                    stats.chunk_count += 1;
+                    stats.allocation_count += space.allocation_count;
+                    stats.reserved += space.size;
+                    stats.used += space.used(strategy);
+                    free += space.size - space.used(strategy);
+                    stats.largest_free_range = stats.largest_free_range
+                        .max(space.largest_free_range(strategy));
+                }
+                stats.fragmentation = if free == 0 {
+                    0.0
+                } else {
+                    1.0 - stats.largest_free_range as f32 / free as f32
+                };
+                stats
+            })
+            .collect()
+    }
+    
+    pub fn report(&self) -> MemoryReport {
+        let memory_types = self.stats();
+        MemoryReport {
+            usages: self.usages.values().cloned().collect(),
+            total_bytes: self.total_bytes,
+            peak_total_bytes: self.peak_total_bytes,
+            reserved_bytes: memory_types.iter().map(|stats| stats.reserved).sum(),
+            memory_types,
+        }
+    }
+    
+    /// free every block, all allocations must have been freed or leaked before this
+    pub fn dispose(&mut self, device: &<B as TB>::Device) {
+        let chunks = self.pools
+            .iter_mut()
+            .flat_map(|pool| pool.general.drain(..).chain(pool.linear.drain(..)))
+            .collect::<Vec<_>>();
+        for usage in self.usages.values().filter(|usage| usage.allocation_count > 0) {
+            println!(
+                "[WARN][Memory Leaked]{} allocations of {:?} holding {} in memory type {}",
+                usage.allocation_count,
+                usage.category,
+                format_bytes(usage.bytes),
+                usage.memory_type
+            );
+        }
+        for chunk in chunks {
+            if chunk.space.allocation_count > 0 {
+                println!(
+                    "[WARN][Memory Leaked]{} allocations in block {}",
+                    chunk.space.allocation_count,
+                    chunk.id
+                );
+            }
+            self.free_chunk(device, chunk);
+        }
+    }
+    
+    fn track_allocate(&mut self, category: MemoryCategory, memory_type: usize, size: u64) {
+        let usage = self.usages
+            .entry((category, memory_type))
+            .or_insert(MemoryUsage {
+                category,
+                memory_type,
+                allocation_count: 0,
+                bytes: 0,
+                peak_bytes: 0,
+            });
+        usage.allocation_count += 1;
+        usage.bytes += size;
+        usage.peak_bytes = usage.peak_bytes.max(usage.bytes);
+        self.total_bytes += size;
+        self.peak_total_bytes = self.peak_total_bytes.max(self.total_bytes);
+    }
+    
+    fn track_free(&mut self, category: MemoryCategory, memory_type: usize, size: u64) {
+        let usage = self.usages
+            .get_mut(&(category, memory_type))
+            .expect("allocation was not tracked");
+        usage.allocation_count -= 1;
+        usage.bytes -= size;
+        self.total_bytes -= size;
+    }
+    
+    fn chunk(&self, allocation: &Allocation) -> &Chunk {
+        let pool = &self.pools[allocation.memory_type];
+        let chunks = match allocation.strategy {
+            AllocationStrategy::General => &pool.general,
+            AllocationStrategy::Linear => &pool.linear,
+        };
+        chunks
+            .iter()
+            .find(|chunk| chunk.id == allocation.chunk_id)
+            .expect("allocation does not belong to this allocator")
+    }
+    
+    fn allocate_chunk(
+        &mut self,
+        device: &<B as TB>::Device,
+        resource: &str,
+        memory_type: usize,
+        size: u64,
+    ) -> RendererResult<Chunk> {
+        if self.device_allocation_count >= MIN_MEMORY_ALLOCATION_COUNT {
+            println!(
+                "[WARN][Device Memory Allocations]{} blocks allocated",
+                self.device_allocation_count
+            );
+        }
+        let memory = unsafe {
+            device.allocate_memory(memory_type.into(), size)
+        }.map_err(|error| RendererError::Memory {
+            resource: resource.to_string(),
+            reason: format!(
+                "allocating a block of {} bytes of memory type {} failed with {:?}",
+                size,
+                memory_type,
+                error
+            ),
+        })?;
+        let mapped = if self.pools[memory_type].properties.contains(Properties::CPU_VISIBLE) {
+            match unsafe { device.map_memory(&memory, 0..size) } {
+                Ok(mapped) => Some(mapped),
+                Err(error) => {
+                    unsafe {
+                        device.free_memory(memory);
+                    }
+                    return Err(RendererError::Memory {
+                        resource: resource.to_string(),
+                        reason: format!(
+                            "mapping a block of memory type {} failed with {:?}",
+                            memory_type,
+                            error
+                        ),
+                    });
+                }
+            }
+        } else {
+            None
+        };
+        self.device_allocation_count += 1;
+        self.next_chunk_id += 1;
+        Ok(Chunk {
+            id: self.next_chunk_id,
+            memory,
+            mapped,
+            space: ChunkSpace::new(size),
+        })
+    }
+    
+    fn free_chunk(&mut self, device: &<B as TB>::Device, chunk: Chunk) {
+        unsafe {
+            if chunk.mapped.is_some() {
+                device.unmap_memory(&chunk.memory);
+            }
+            device.free_memory(chunk.memory);
+        }
+        self.device_allocation_count -= 1;
+    }
+}
+
+fn align_up(value: u64, alignment: u64) -> u64 {
+    (value + alignment - 1) / alignment * alignment
+}
+
+fn align_down(value: u64, alignment: u64) -> u64 {
+    value / alignment * alignment
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    const GENERAL: AllocationStrategy = AllocationStrategy::General;
+    const LINEAR: AllocationStrategy = AllocationStrategy::Linear;
+    
+    #[test]
+    fn free_list_splits_the_range_it_allocates_from() {
+        let mut space = ChunkSpace::new(1024);
+        assert_eq!(space.allocate(GENERAL, 100, 1), Some((0..100, 0)));
+        assert_eq!(space.allocate(GENERAL, 100, 1), Some((100..200, 100)));
+        assert_eq!(space.free_ranges, vec![200..1024]);
+        assert_eq!(space.allocation_count, 2);
+        assert_eq!(space.used(GENERAL), 200);
+    }
+    
+    #[test]
+    fn free_list_reserves_the_alignment_padding() {
+        let mut space = ChunkSpace::new(1024);
+        assert_eq!(space.allocate(GENERAL, 10, 1), Some((0..10, 0)));
+        assert_eq!(space.allocate(GENERAL, 16, 64), Some((10..80, 64)));
+        assert_eq!(space.free_ranges, vec![80..1024]);
+        
+        // the padding goes back with the allocation
+        space.free(GENERAL, 10..80);
+        assert_eq!(space.free_ranges, vec![10..1024]);
+    }
+    
+    #[test]
+    fn free_list_coalesces_with_both_neighbours() {
+        let mut space = ChunkSpace::new(1024);
+        let (a, _) = space.allocate(GENERAL, 100, 1).unwrap();
+        let (b, _) = space.allocate(GENERAL, 100, 1).unwrap();
+        let (c, _) = space.allocate(GENERAL, 100, 1).unwrap();
+        
+        space.free(GENERAL, a);
+        space.free(GENERAL, c);
+        assert_eq!(space.free_ranges, vec![0..100, 200..1024]);
+        space.free(GENERAL, b);
+        assert_eq!(space.free_ranges, vec![0..1024]);
+        assert_eq!(space.allocation_count, 0);
+        assert_eq!(space.used(GENERAL), 0);
+    }
+    
+    #[test]
+    fn free_list_is_first_fit() {
+        let mut space = ChunkSpace::new(1024);
+        let (a, _) = space.allocate(GENERAL, 100, 1).unwrap();
+        space.allocate(GENERAL, 100, 1).unwrap();
+        space.free(GENERAL, a);
+        
+        assert_eq!(space.allocate(GENERAL, 50, 1), Some((0..50, 0)));
+        // the rest of the hole is too small
+        assert_eq!(space.allocate(GENERAL, 60, 1), Some((200..260, 200)));
+        assert_eq!(space.free_ranges, vec![50..100, 260..1024]);
+        assert_eq!(space.largest_free_range(GENERAL), 764);
+        // and too small once aligned
+        assert_eq!(space.allocate(GENERAL, 40, 64), Some((260..360, 320)));
+    }
+    
+    #[test]
+    fn free_list_exhaustion() {
+        let mut space = ChunkSpace::new(1024);
+        assert_eq!(space.allocate(GENERAL, 1024, 256), Some((0..1024, 0)));
+        assert!(space.free_ranges.is_empty());
+        assert_eq!(space.allocate(GENERAL, 1, 1), None);
+        assert_eq!(space.largest_free_range(GENERAL), 0);
+        
+        space.free(GENERAL, 0..1024);
+        // fits unaligned but not aligned
+        space.allocate(GENERAL, 8, 1).unwrap();
+        assert_eq!(space.allocate(GENERAL, 1000, 64), None);
+        assert_eq!(space.allocation_count, 1);
+    }
+    
+    #[test]
+    fn linear_bumps_and_rewinds_once_empty() {
+        let mut space = ChunkSpace::new(1024);
+        let (a, _) = space.allocate(LINEAR, 10, 1).unwrap();
+        assert_eq!(a, 0..10);
+        let (b, offset) = space.allocate(LINEAR, 16, 64).unwrap();
+        assert_eq!((b.clone(), offset), (10..80, 64));
+        assert_eq!(space.used(LINEAR), 80);
+        assert_eq!(space.largest_free_range(LINEAR), 944);
+        
+        // freed space is not reused until the block is empty
+        space.free(LINEAR, a);
+        assert_eq!(space.allocate(LINEAR, 8, 1), Some((80..88, 80)));
+        space.free(LINEAR, b);
+        space.free(LINEAR, 80..88);
+        assert_eq!(space.used(LINEAR), 0);
+        assert_eq!(space.allocate(LINEAR, 8, 1), Some((0..8, 0)));
+    }
+    
+    #[test]
+    fn linear_exhaustion() {
+        let mut space = ChunkSpace::new(1024);
+        space.allocate(LINEAR, 1000, 1).unwrap();
+        assert_eq!(space.allocate(LINEAR, 24, 1), Some((1000..1024, 1000)));
+        assert_eq!(space.allocate(LINEAR, 1, 1), None);
+        
+        let mut space = ChunkSpace::new(1024);
+        space.allocate(LINEAR, 1000, 1).unwrap();
+        // would fit without the alignment
+        assert_eq!(space.allocate(LINEAR, 16, 256), None);
+        assert_eq!(space.allocation_count, 1);
+    }
+    
+    #[test]
+    fn alignment() {
+        assert_eq!(align_up(0, 64), 0);
+        assert_eq!(align_up(1, 64), 64);
+        assert_eq!(align_up(64, 64), 64);
+        assert_eq!(align_down(127, 64), 64);
+        assert_eq!(align_down(128, 64), 128);
+    }
+}
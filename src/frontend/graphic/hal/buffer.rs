@@ -1,49 +1,204 @@
 use std::{
     cell::RefCell,
     marker::PhantomData,
+    mem,
     rc::Rc,
+    slice,
 };
 
 use super::{
     adapter::AdapterState,
+    allocator::{
+        Allocation,
+        AllocationStrategy,
+        MemoryCategory,
+    },
     device::DeviceState,
+    error::{
+        RendererError,
+        RendererResult,
+    },
     prelude::*,
 };
 
 pub struct BufferState<I> {
     pub buffer: Option<<B as TB>::Buffer>,
-    pub memory: Option<<B as TB>::Memory>,
+    pub allocation: Option<Allocation>,
     pub size: Option<u64>,
     pub device_state: Rc<RefCell<DeviceState>>,
     pub _phantom_data: PhantomData<I>,
 }
 
 impl<I: Sized + Copy> BufferState<I> {
-    pub fn new_from_items(
+    /// create a buffer of size bytes without initializing it
+    pub fn new_empty(
         device_state: Rc<RefCell<DeviceState>>,
-        adapter_state: &AdapterState,
-        items: Vec<I>,
+        size: u64,
         usage: buffer::Usage,
-    ) -> BufferState<I> {
-        let (buffer, memory, size) = unsafe {
-            let device = &device_state.borrow_mut().device;
-            let (buffer, memory, size) = create_buffer(
-                device,
-                adapter_state,
-                Properties::CPU_VISIBLE,
+        properties: Properties,
+        strategy: AllocationStrategy,
+    ) -> RendererResult<BufferState<I>> {
+        let (buffer, allocation) = unsafe {
+            create_empty_buffer(
+                &mut device_state.borrow_mut(),
+                size,
                 usage,
-                items);
-            (Some(buffer), Some(memory), Some(size))
-        };
+                properties,
+                strategy,
+            )
+        }?;
         
-        BufferState {
-            buffer,
-            memory,
-            size,
+        Ok(BufferState {
+            buffer: Some(buffer),
+            allocation: Some(allocation),
+            size: Some(size),
             device_state,
             _phantom_data: PhantomData,
+        })
+    }
+    
+    /// create a host visible buffer holding items
+    pub fn new_from_items(
+        device_state: Rc<RefCell<DeviceState>>,
+        items: Vec<I>,
+        usage: buffer::Usage,
+    ) -> RendererResult<BufferState<I>> {
+        let mut buffer_state = BufferState::new_empty(
+            device_state,
+            (items.len() * mem::size_of::<I>()) as u64,
+            usage,
+            Properties::CPU_VISIBLE,
+            AllocationStrategy::General,
+        )?;
+        buffer_state.update_buffer(items)?;
+        Ok(buffer_state)
+    }
+    
+    /// create a short lived host visible buffer holding items to be copied to the device
+    pub fn new_staging(
+        device_state: Rc<RefCell<DeviceState>>,
+        items: Vec<I>,
+    ) -> RendererResult<BufferState<I>> {
+        let mut buffer_state = BufferState::new_empty(
+            device_state,
+            (items.len() * mem::size_of::<I>()) as u64,
+            buffer::Usage::TRANSFER_SRC,
+            Properties::CPU_VISIBLE,
+            AllocationStrategy::Linear,
+        )?;
+        buffer_state.update_buffer(items)?;
+        Ok(buffer_state)
+    }
+    
+    /// create a short lived host visible buffer of size bytes for the device to copy results into
+    pub fn new_readback(
+        device_state: Rc<RefCell<DeviceState>>,
+        size: u64,
+    ) -> RendererResult<BufferState<I>> {
+        BufferState::new_empty(
+            device_state,
+            size,
+            buffer::Usage::TRANSFER_DST,
+            Properties::CPU_VISIBLE,
+            AllocationStrategy::Linear,
+        )
+    }
+    
+    /// create a buffer in DEVICE_LOCAL memory for data that is never written by the CPU again,
+    /// the items are copied through a temporary staging buffer and this blocks until the copy is done,
+    /// there has to be at least one as buffers cannot be empty
+    pub fn new_device_local(
+        device_state: Rc<RefCell<DeviceState>>,
+        items: Vec<I>,
+        usage: buffer::Usage,
+    ) -> RendererResult<BufferState<I>> {
+        if items.is_empty() {
+            return Err(RendererError::asset(
+                "device local buffer",
+                format!("no items to fill a {:?} buffer with", usage),
+            ));
+        }
+        let staging_buffer = BufferState::new_staging(
+            device_state.clone(),
+            items,
+        )?;
+        let size = staging_buffer.size.unwrap();
+        
+        let buffer_state = BufferState::new_empty(
+            device_state.clone(),
+            size,
+            usage | buffer::Usage::TRANSFER_DST,
+            Properties::DEVICE_LOCAL,
+            AllocationStrategy::General,
+        )?;
+        
+        let mut staging_pool = {
+            let device = &device_state.borrow().device;
+            unsafe {
+                device.create_command_pool_typed(
+                    &device_state.borrow().queue_group,
+                    CommandPoolCreateFlags::TRANSIENT,
+                )
+            }.map_err(|error| RendererError::creation("buffer upload command pool", error))?
+        };
+        
+        let mut transferred_fence = {
+            let device = &device_state.borrow().device;
+            
+            device.create_fence(false)
+        }.map_err(|error| RendererError::creation("buffer upload fence", error))?;
+        
+        unsafe {
+            let mut command_buffer = staging_pool.acquire_command_buffer::<OneShot>();
+            command_buffer.begin();
+            
+            command_buffer.copy_buffer(
+                staging_buffer.buffer.as_ref().unwrap(),
+                buffer_state.buffer.as_ref().unwrap(),
+                &[
+                    BufferCopy {
+                        src: 0,
+                        dst: 0,
+                        size,
+                    }
+                ],
+            );
+            
+            command_buffer.pipeline_barrier(
+                PipelineStage::TRANSFER..PipelineStage::VERTEX_INPUT,
+                Dependencies::empty(),
+                &[
+                    Barrier::AllBuffers(
+                        buffer::Access::TRANSFER_WRITE
+                            ..buffer::Access::VERTEX_BUFFER_READ | buffer::Access::INDEX_BUFFER_READ
+                    )
+                ],
+            );
+            
+            command_buffer.finish();
+            
+            device_state.borrow_mut().queue_group.queues[0]
+                .submit_nosemaphores(
+                    Some(&command_buffer),
+                    Some(&mut transferred_fence),
+                );
+            
+            let device = &device_state.borrow().device;
+            
+            let waited = device.wait_for_fence(&transferred_fence, !0);
+            
+            device.destroy_fence(transferred_fence);
+            device.destroy_command_pool(staging_pool.into_raw());
+            waited.map_err(|error| RendererError::memory("buffer upload", error))?;
         }
+        
+        // the staging buffer is dropped and its memory freed here
+        Ok(buffer_state)
     }
+    
+    /// create a staging buffer for an image of height rows of width pixels,
+    /// each row padded to the pitch alignment required for buffer to image copies,
+    /// returning the buffer with the padded row size in bytes
     pub fn new_texture_buffer(
         device_state: Rc<RefCell<DeviceState>>,
         adapter_state: &AdapterState,
@@ -52,197 +207,102 @@ impl<I: Sized + Copy> BufferState<I> {
         bytes_pixels: u32,
         image: Vec<I>,
         usage: buffer::Usage,
-    ) -> (BufferState<I>, u32)
+    ) -> RendererResult<(BufferState<I>, u32)>
     {
-        let (buffer, memory, size, pitch_size) = unsafe {
-            let device = &device_state.borrow_mut().device;
-            let (buffer, mut memory, size, pitch_size) =
-                create_empty_buffer_with_chunk_size_align_to_size::<u8>(
-                    device,
-                    &adapter_state.memory_types,
-                    Properties::CPU_VISIBLE,
-                    usage,
-                    height,
-                    width * bytes_pixels,
-                    adapter_state.limits.min_buffer_copy_pitch_alignment as u32);
-            fill_buffer_with_alignment(
-                device,
-                &mut memory,
-                image,
-                height,
-                width * bytes_pixels,
-                pitch_size,
-                size,
-            );
-            (Some(buffer), Some(memory), Some(size), pitch_size)
-        };
-        (BufferState {
-            buffer,
-            memory,
-            size,
+        let alignment = adapter_state.limits.min_buffer_copy_pitch_alignment as u32;
+        let row_size = width * bytes_pixels * mem::size_of::<I>() as u32;
+        let pitch_size = (row_size + alignment - 1) / alignment * alignment;
+        
+        let buffer_state = BufferState::new_empty(
             device_state,
-            _phantom_data: PhantomData,
-        }, pitch_size
-        )
-    }
-    pub fn update_buffer(&mut self, items: Vec<I>) {
-        unsafe {
-            fill_buffer(
-                &self.device_state.borrow().device,
-                self.memory.as_mut().unwrap(),
-                items,
-                self.size.unwrap(),
-            );
+            height as u64 * pitch_size as u64,
+            usage,
+            Properties::CPU_VISIBLE,
+            AllocationStrategy::Linear,
+        )?;
+        
+        let source = as_bytes(&image);
+        let mut padded = vec![0u8; (height * pitch_size) as usize];
+        for row in 0..height as usize {
+            let source_row = &source[row * row_size as usize..(row + 1) * row_size as usize];
+            let dest_base = row * pitch_size as usize;
+            padded[dest_base..dest_base + source_row.len()].copy_from_slice(source_row);
         }
+        buffer_state.write_bytes(0, &padded)?;
+        
+        Ok((buffer_state, pitch_size))
     }
-}
-
-
-unsafe fn create_buffer<I: Copy>(
-    device: &<B as TB>::Device,
-    adapter_state: &AdapterState,
-    properties: Properties,
-    usage: buffer::Usage,
-    items: Vec<I>,
-) -> (<B as TB>::Buffer, <B as TB>::Memory, u64) {
-    let memory_types = &adapter_state.memory_types;
-    let (buffer, mut memory, size) = create_empty_buffer::<I>(
-        device,
-        memory_types,
-        properties,
-        usage,
-        items.len() as u64,
-    );
     
-    fill_buffer(
-        device,
-        &mut memory,
-        items,
-        size,
-    );
-    (buffer, memory, size)
-}
-
-unsafe fn create_empty_buffer<I>(
-    device: &<B as TB>::Device,
-    memory_types: &Vec<MemoryType>,
-    properties: Properties,
-    usage: buffer::Usage,
-    item_count: u64,
-) -> (<B as TB>::Buffer, <B as TB>::Memory, u64) {
-    let stride = ::std::mem::size_of::<I>() as u64;
-    let upload_size = item_count * stride;
-    let mut buffer = device.create_buffer(upload_size, usage).unwrap();
-    let req = device.get_buffer_requirements(&buffer);
-    let upload_type = memory_types
-        .iter()
-        .enumerate()
-        .position(|(id, ty)| req.type_mask & (1 << id) != 0 && ty.properties.contains(properties))
-        .unwrap()
-        .into();
-    let memory = device.allocate_memory(upload_type, req.size).unwrap();
-    // TODO: error-check?
-    device.bind_buffer_memory(&memory, 0, &mut buffer)
-          .expect("failed to bind buffer memory");
-    (buffer, memory, req.size)
-}
-
-/// Pushes data into a buffer.
-unsafe fn fill_buffer<I: Copy>(
-    device: &<B as TB>::Device,
-    buffer_memory: &mut <B as TB>::Memory,
-    items: Vec<I>,
-    size: u64,
-) {
-    // NOTE: MESH -> items
-    // NOTE: Recalc buffer_len
-    let buffer_size = size;
+    /// overwrite the buffer from its start with items
+    pub fn update_buffer(&mut self, items: Vec<I>) -> RendererResult<()> {
+        self.write_bytes(0, as_bytes(&items))
+    }
     
-    let mut dest = device
-        .acquire_mapping_writer::<I>(&buffer_memory, 0..buffer_size)
-        .unwrap();
+    /// the whole content of a host visible buffer,
+    /// whatever the device wrote has to be finished before
+    pub fn read_bytes(&self) -> RendererResult<Vec<u8>> {
+        let device_state = self.device_state.borrow();
+        device_state.read(self.allocation.as_ref().unwrap(), 0, self.size.unwrap() as usize)
+    }
     
-    dest[0..items.len()].copy_from_slice(items.as_slice());
-    device.release_mapping_writer(dest).expect("failed to release mapping writer");
+    /// write raw bytes at offset bytes from the start of the buffer
+    pub fn write_bytes(&self, offset: u64, bytes: &[u8]) -> RendererResult<()> {
+        let device_state = self.device_state.borrow();
+        device_state.write(self.allocation.as_ref().unwrap(), offset, bytes)
+    }
 }
 
-
-unsafe fn create_empty_buffer_with_size(
-    device: &<B as TB>::Device,
-    memory_types: &Vec<MemoryType>,
-    properties: Properties,
-    usage: buffer::Usage,
-    buffer_size: u64) -> (<B as TB>::Buffer, <B as TB>::Memory, u64)
-{
-    let mut buffer = device.create_buffer(buffer_size, usage).unwrap();
-    let req = device.get_buffer_requirements(&buffer);
-    let upload_type = memory_types
-        .iter()
-        .enumerate()
-        .position(|(id, ty)| req.type_mask & (1 << id) != 0 && ty.properties.contains(properties))
-        .unwrap()
-        .into();
-    let memory = device.allocate_memory(upload_type, req.size).unwrap();
-    // TODO: error-check?
-    device.bind_buffer_memory(&memory, 0, &mut buffer)
-          .expect("failed to bind buffer memory");
-    (buffer, memory, req.size)
+impl<I> Drop for BufferState<I> {
+    fn drop(&mut self) {
+        let mut device_state = self.device_state.borrow_mut();
+        unsafe {
+            if let Some(buffer) = self.buffer.take() {
+                device_state.device.destroy_buffer(buffer);
+            }
+        }
+        if let Some(allocation) = self.allocation.take() {
+            device_state.free(allocation);
+        }
+    }
 }
 
-unsafe fn create_empty_buffer_with_chunk_size_align_to_size<I>(
-    device: &<B as TB>::Device,
-    memory_types: &Vec<MemoryType>,
-    properties: Properties,
-    usage: buffer::Usage,
-    num_of_chunk: u32,
-    items_count_each_chunk: u32,
-    alignment: u32,
-) -> (<B as TB>::Buffer, <B as TB>::Memory, u64, u32) {
-    let alignment_mask = alignment - 1;
-    let stride = ::std::mem::size_of::<I>();
-    
-    let chunk_size = (items_count_each_chunk * stride as u32 + alignment_mask) & !alignment_mask;
-    let upload_size = num_of_chunk as u64 * chunk_size as u64;
-    
-    let mut buffer = device.create_buffer(upload_size, usage).unwrap();
-    let mem_req = device.get_buffer_requirements(&buffer);
-    let upload_type = memory_types
-        .iter()
-        .enumerate()
-        .position(|(id, ty)| mem_req.type_mask & (1 << id) != 0 && ty.properties.contains(properties))
-        .unwrap()
-        .into();
-    
-    let memory = device.allocate_memory(upload_type, mem_req.size).unwrap();
-    // TODO: error-check?
-    device.bind_buffer_memory(&memory, 0, &mut buffer)
-          .expect("failed to bind buffer memory");
-    (buffer, memory, mem_req.size, chunk_size)
+/// view a slice of plain data as its bytes
+pub fn as_bytes<I: Copy>(items: &[I]) -> &[u8] {
+    unsafe {
+        slice::from_raw_parts(
+            items.as_ptr() as *const u8,
+            items.len() * mem::size_of::<I>(),
+        )
+    }
 }
 
-unsafe fn fill_buffer_with_alignment<I: Copy>(
-    device: &<B as TB>::Device,
-    buffer_memory: &mut <B as TB>::Memory,
-    items: Vec<I>,
-    num_of_chunk: u32,
-    items_count_each_chunk: u32,
-    row_pitch: u32,
+unsafe fn create_empty_buffer(
+    device_state: &mut DeviceState,
     size: u64,
-) {
-    let mut data_target = device
-        .acquire_mapping_writer::<I>(&buffer_memory, 0..size)
-        .unwrap();
-    let stride = ::std::mem::size_of::<I>() as usize;
-    for current_chunk in 0..num_of_chunk as usize {
-        let data_source_slice = &items
-            [current_chunk * (items_count_each_chunk as usize) * stride
-            ..(current_chunk + 1) * (items_count_each_chunk as usize) * stride];
-        let dest_base = current_chunk * row_pitch as usize;
-        
-        data_target[dest_base..dest_base + data_source_slice.len()]
-            .copy_from_slice(data_source_slice);
+    usage: buffer::Usage,
+    properties: Properties,
+    strategy: AllocationStrategy,
+) -> RendererResult<(<B as TB>::Buffer, Allocation)> {
+    let resource = format!("buffer of {} bytes for {:?}", size, usage);
+    let mut buffer = device_state.device
+        .create_buffer(size, usage)
+        .map_err(|error| RendererError::creation(&resource, error))?;
+    let requirements = device_state.device.get_buffer_requirements(&buffer);
+    let category = MemoryCategory::from_buffer_usage(usage);
+    let allocation = device_state.allocate(&resource, category, requirements, properties, strategy);
+    let allocation = match allocation {
+        Ok(allocation) => allocation,
+        Err(error) => {
+            device_state.device.destroy_buffer(buffer);
+            return Err(error);
+        }
+    };
+    let bound = device_state.device
+        .bind_buffer_memory(device_state.memory(&allocation), allocation.offset, &mut buffer);
+    if let Err(error) = bound {
+        device_state.device.destroy_buffer(buffer);
+        device_state.free(allocation);
+        return Err(RendererError::memory(&resource, error));
     }
-    
-    device.release_mapping_writer(data_target).unwrap();
+    Ok((buffer, allocation))
 }
-
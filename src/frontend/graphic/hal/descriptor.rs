@@ -7,6 +7,10 @@ use std::borrow::Borrow;
 use super::{
     descriptor,
     device::DeviceState,
+    error::{
+        RendererError,
+        RendererResult,
+    },
     prelude::*,
 };
 
@@ -19,22 +23,61 @@ impl DescriptorPoolState {
     pub fn new(
         device_state: Rc<RefCell<DeviceState>>,
         pool_size_descs: &[DescriptorRangeDesc],
-    ) -> DescriptorPoolState {
+    ) -> RendererResult<DescriptorPoolState> {
+        DescriptorPoolState::new_with_max_sets(
+            device_state,
+            1,
+            pool_size_descs,
+            pso::DescriptorPoolCreateFlags::empty(),
+        )
+    }
+    
+    /// a pool that many descriptor sets can be allocated from,
+    /// pass FREE_DESCRIPTOR_SET in flags if sets are to be freed individually
+    pub fn new_with_max_sets(
+        device_state: Rc<RefCell<DeviceState>>,
+        max_sets: usize,
+        pool_size_descs: &[DescriptorRangeDesc],
+        flags: pso::DescriptorPoolCreateFlags,
+    ) -> RendererResult<DescriptorPoolState> {
         let descriptor_pool = unsafe {
             let device = &device_state.borrow_mut().device;
+            
+            device.create_descriptor_pool(
+                max_sets,
+                pool_size_descs,
+                flags,
+            )
+        }.map_err(|error| RendererError::creation(
+            &format!("descriptor pool of {} sets", max_sets),
+            error,
+        ))?;
         
-            let descriptor_pool =
-                device.create_descriptor_pool(
-                    1,
-                    pool_size_descs,
-                    pso::DescriptorPoolCreateFlags::empty(),
-                ).ok();
-            descriptor_pool
-        };
-    
-        DescriptorPoolState {
+        Ok(DescriptorPoolState {
             device_state,
-            descriptor_pool,
+            descriptor_pool: Some(descriptor_pool),
+        })
+    }
+    
+    pub fn allocate_set(
+        &mut self,
+        descriptor_set_layout: &<B as TB>::DescriptorSetLayout,
+    ) -> RendererResult<<B as TB>::DescriptorSet> {
+        unsafe {
+            self.descriptor_pool
+                .as_mut()
+                .unwrap()
+                .allocate_set(descriptor_set_layout)
+        }.map_err(|error| RendererError::memory("descriptor set", error))
+    }
+    
+    /// only valid for pools created with FREE_DESCRIPTOR_SET
+    pub fn free_set(&mut self, descriptor_set: <B as TB>::DescriptorSet) {
+        unsafe {
+            self.descriptor_pool
+                .as_mut()
+                .unwrap()
+                .free_sets(Some(descriptor_set));
         }
     }
 }
@@ -55,6 +98,8 @@ pub struct DescriptorState {
     device_state: Rc<RefCell<DeviceState>>,
     pub descriptor_set_layout: Option<<B as TB>::DescriptorSetLayout>,
     pub descriptor_set: Option<<B as TB>::DescriptorSet>,
+    // what the layout was created with, for pipelines to be checked against
+    bindings: Vec<DescriptorSetLayoutBinding>,
 }
 
 impl DescriptorState {
@@ -62,7 +107,7 @@ impl DescriptorState {
         device_state: Rc<RefCell<DeviceState>>,
         sets: &[DescriptorSetLayoutBinding],
         immutable_samplers: &[<B as TB>::Sampler],
-    ) -> Self {
+    ) -> RendererResult<Self> {
         let descriptor_set_layout = unsafe {
             let device = &device_state.borrow_mut().device;
             
@@ -70,32 +115,32 @@ impl DescriptorState {
                 sets,
                 immutable_samplers,
             )
-        }.ok();
+        }.map_err(|error| RendererError::creation("descriptor set layout", error))?;
         
-        DescriptorState {
+        Ok(DescriptorState {
             device_state,
-            descriptor_set_layout,
+            descriptor_set_layout: Some(descriptor_set_layout),
             descriptor_set: None,
-        }
+            bindings: sets.to_vec(),
+        })
     }
+    
+    pub fn bindings(&self) -> &[DescriptorSetLayoutBinding] {
+        &self.bindings
+    }
+    
     pub fn allocate_descriptor_set(
         &mut self,
         descriptor_pool_state: &mut DescriptorPoolState,
-    ) {
+    ) -> RendererResult<()> {
         if let Some(descriptor_set_layout) =
         self.descriptor_set_layout.as_ref()
         {
-            let descriptor_pool =
-                descriptor_pool_state.descriptor_pool.as_mut().unwrap();
-            
-            self.descriptor_set = unsafe {
-                let descriptor_set =
-                    descriptor_pool.allocate_set(
-                        descriptor_set_layout
-                    ).unwrap();
-                Some(descriptor_set)
-            }
+            self.descriptor_set = Some(
+                descriptor_pool_state.allocate_set(descriptor_set_layout)?
+            );
         }
+        Ok(())
     }
 }
 
@@ -109,4 +154,19 @@ impl Drop for DescriptorState {
             }
         }
     }
-}
\ No newline at end of file
+}
+
+/// how many descriptors of every type a pool needs for one set with these bindings
+pub fn descriptor_ranges(bindings: &[DescriptorSetLayoutBinding]) -> Vec<DescriptorRangeDesc> {
+    let mut ranges: Vec<DescriptorRangeDesc> = vec![];
+    for binding in bindings {
+        match ranges.iter_mut().find(|range| range.ty == binding.ty) {
+            Some(range) => range.count += binding.count,
+            None => ranges.push(DescriptorRangeDesc {
+                ty: binding.ty,
+                count: binding.count,
+            }),
+        }
+    }
+    ranges
+}
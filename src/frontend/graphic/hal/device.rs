@@ -1,26 +1,110 @@
+use std::ops::Range;
+
 use crate::frontend::graphic::hal::adapter::AdapterState;
 
-use super::prelude::*;
+use super::{
+    allocator::{
+        Allocation,
+        AllocationStrategy,
+        MemoryAllocator,
+        MemoryCategory,
+        MemoryReport,
+    },
+    error::{
+        RendererError,
+        RendererResult,
+    },
+    prelude::*,
+};
 
 pub struct DeviceState {
     pub device: <B as TB>::Device,
     pub queue_group: gfx_hal::QueueGroup<B, Graphics>,
+    // every HAL wrapper takes its device memory from here
+    pub allocator: MemoryAllocator,
 }
 
 impl DeviceState {
+    /// without a surface any graphics queue family will do, which is what headless rendering needs
     pub fn new(
         adapter_state: &AdapterState,
-        surface: &<B as TB>::Surface,
-    ) -> Self {
+        surface: Option<&<B as TB>::Surface>,
+    ) -> RendererResult<Self> {
         let (device, queue_group) = adapter_state.adapter
-                                                 .open_with::<_, Graphics>(
+            .open_with::<_, Graphics>(
                 1,
-                |family| surface.supports_queue_family(family))
-                                                 .unwrap();
-    
-        DeviceState {
+                |family| surface.map_or(true, |surface| surface.supports_queue_family(family)))
+            .map_err(|error| RendererError::creation(
+                "logical device with a graphics queue",
+                error,
+            ))?;
+        
+        let allocator = MemoryAllocator::new(
+            &adapter_state.memory_types,
+            &adapter_state.limits,
+        );
+        
+        Ok(DeviceState {
             device,
             queue_group,
-        }
+            allocator,
+        })
+    }
+    
+    /// resource only names what the memory is for in errors
+    pub fn allocate(
+        &mut self,
+        resource: &str,
+        category: MemoryCategory,
+        requirements: Requirements,
+        properties: Properties,
+        strategy: AllocationStrategy,
+    ) -> RendererResult<Allocation> {
+        self.allocator
+            .allocate(&self.device, resource, category, requirements, properties, strategy)
+    }
+    
+    pub fn free(&mut self, allocation: Allocation) {
+        self.allocator.free(&self.device, allocation);
+    }
+    
+    pub fn memory(&self, allocation: &Allocation) -> &<B as TB>::Memory {
+        self.allocator.memory(allocation)
+    }
+    
+    pub fn write(&self, allocation: &Allocation, offset: u64, bytes: &[u8]) -> RendererResult<()> {
+        self.allocator.write(&self.device, allocation, offset, bytes)
+    }
+    
+    /// like write but leaves flushing to a later call of flush
+    pub fn copy(&self, allocation: &Allocation, offset: u64, bytes: &[u8]) {
+        self.allocator.copy(allocation, offset, bytes);
+    }
+    
+    pub fn flush(&self, allocation: &Allocation, range: Range<u64>) -> RendererResult<()> {
+        self.allocator.flush(&self.device, allocation, range)
+    }
+    
+    /// read back what the device wrote to a host visible allocation
+    pub fn read(&self, allocation: &Allocation, offset: u64, len: usize) -> RendererResult<Vec<u8>> {
+        self.allocator.read(&self.device, allocation, offset, len)
+    }
+    
+    /// block until the device has finished everything submitted to it
+    pub fn wait_idle(&self) -> RendererResult<()> {
+        self.device
+            .wait_idle()
+            .map_err(|error| RendererError::device("wait for the device to be idle", error))
+    }
+    
+    /// the memory every HAL wrapper holds right now, by category and memory type
+    pub fn memory_report(&self) -> MemoryReport {
+        self.allocator.report()
+    }
+}
+
+impl Drop for DeviceState {
+    fn drop(&mut self) {
+        self.allocator.dispose(&self.device);
     }
 }
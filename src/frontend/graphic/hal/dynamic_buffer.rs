@@ -0,0 +1,307 @@
+use std::{
+    cell::RefCell,
+    mem,
+    ops::Range,
+    rc::Rc,
+};
+
+use super::{
+    allocator::AllocationStrategy,
+    buffer::{
+        as_bytes,
+        BufferState,
+    },
+    device::DeviceState,
+    error::RendererResult,
+    frame::{
+        FrameTimeline,
+        RetireQueue,
+    },
+    prelude::*,
+};
+
+/// a host visible buffer whose number of items changes over time,
+/// such as debug and procedural geometry regenerated every frame
+///
+/// every frame context has its own copy so that writing the frame being recorded
+/// never touches what a frame in flight reads, a copy catches up on the writes made
+/// to the others when its frame context comes around again
+///
+/// when it runs out of room copies twice as large take their place,
+/// the previous ones are kept alive until every frame that may still read them has completed
+pub struct DynamicBufferState<I> {
+    device_state: Rc<RefCell<DeviceState>>,
+    usage: buffer::Usage,
+    // one per frame context, indexed by the frame context index
+    copies: Vec<BufferState<I>>,
+    // the copy of the frame being recorded
+    current: usize,
+    stale: StaleRanges,
+    // what the device should see, kept so that copies can catch up and survive a growth
+    items: Vec<I>,
+    capacity: usize,
+    // bytes written to the current copy since the last flush
+    dirty: Option<Range<u64>>,
+    // copies replaced by growth
+    retired: RetireQueue<BufferState<I>>,
+    // the timeline given to the last begin_frame, replaced copies are retired with it
+    timeline: FrameTimeline,
+}
+
+impl<I: Sized + Copy> DynamicBufferState<I> {
+    pub fn new(
+        device_state: Rc<RefCell<DeviceState>>,
+        usage: buffer::Usage,
+        capacity: usize,
+        frame_count: usize,
+    ) -> RendererResult<DynamicBufferState<I>> {
+        assert!(frame_count > 0, "a dynamic buffer needs a copy for at least one frame");
+        let capacity = capacity.max(1);
+        let copies = DynamicBufferState::<I>::create_copies(
+            &device_state,
+            usage,
+            capacity,
+            frame_count,
+        )?;
+        Ok(DynamicBufferState {
+            device_state,
+            usage,
+            copies,
+            current: 0,
+            stale: StaleRanges::new(frame_count),
+            items: Vec::with_capacity(capacity),
+            capacity,
+            dirty: None,
+            retired: RetireQueue::new(),
+            timeline: FrameTimeline::default(),
+        })
+    }
+    
+    /// the copy to draw the frame being recorded with
+    pub fn buffer(&self) -> &<B as TB>::Buffer {
+        self.copies[self.current].buffer.as_ref().unwrap()
+    }
+    
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+    
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+    
+    /// to be called once per frame before writing, once the fence of the frame context
+    /// at frame_index has been waited for, switches to its copy and brings it up to date
+    /// then frees the replaced copies no frame in flight can be reading anymore
+    pub fn begin_frame(
+        &mut self,
+        frame_index: usize,
+        timeline: FrameTimeline,
+    ) -> RendererResult<()> {
+        assert!(frame_index < self.copies.len(), "frame context index out of range");
+        self.flush()?;
+        self.current = frame_index;
+        self.timeline = timeline;
+        
+        if let Some(stale) = self.stale.take(frame_index, self.items.len()) {
+            let item_size = mem::size_of::<I>() as u64;
+            self.device_state.borrow().copy(
+                self.copies[frame_index].allocation.as_ref().unwrap(),
+                stale.start as u64 * item_size,
+                as_bytes(&self.items[stale.clone()]),
+            );
+            self.mark_dirty(stale.start as u64 * item_size..stale.end as u64 * item_size);
+        }
+        
+        // dropping a buffer state destroys the buffer
+        self.retired.collect(timeline);
+        Ok(())
+    }
+    
+    /// replace every item and flush them
+    pub fn set_items(&mut self, items: &[I]) -> RendererResult<()> {
+        self.items.clear();
+        self.write(0, items)?;
+        self.flush()
+    }
+    
+    /// overwrite the items from offset on, growing the buffer if they do not fit,
+    /// nothing written is visible to the device until flush is called
+    pub fn write(&mut self, offset: usize, items: &[I]) -> RendererResult<()> {
+        assert!(offset <= self.items.len(), "write leaves a gap in the dynamic buffer");
+        let end = offset + items.len();
+        self.reserve(end)?;
+        
+        let overlap = end.min(self.items.len());
+        self.items[offset..overlap].copy_from_slice(&items[..overlap - offset]);
+        self.items.extend_from_slice(&items[overlap - offset..]);
+        
+        let item_size = mem::size_of::<I>() as u64;
+        let bytes = offset as u64 * item_size..end as u64 * item_size;
+        self.device_state.borrow().copy(
+            self.copies[self.current].allocation.as_ref().unwrap(),
+            bytes.start,
+            as_bytes(items),
+        );
+        self.mark_dirty(bytes);
+        self.stale.written(self.current, offset..end);
+        Ok(())
+    }
+    
+    /// drop the items from len on, the buffer keeps its capacity
+    pub fn truncate(&mut self, len: usize) {
+        self.items.truncate(len);
+    }
+    
+    /// make every write to the current copy since the last flush visible to the device
+    pub fn flush(&mut self) -> RendererResult<()> {
+        match self.dirty.take() {
+            Some(dirty) => self.device_state.borrow().flush(
+                self.copies[self.current].allocation.as_ref().unwrap(),
+                dirty,
+            ),
+            None => Ok(()),
+        }
+    }
+    
+    /// make sure capacity is at least the given number of items,
+    /// growing geometrically so that a slowly growing buffer is not recreated every frame
+    pub fn reserve(&mut self, capacity: usize) -> RendererResult<()> {
+        if capacity <= self.capacity {
+            return Ok(());
+        }
+        let capacity = capacity.max(self.capacity * 2);
+        let copies = DynamicBufferState::<I>::create_copies(
+            &self.device_state,
+            self.usage,
+            capacity,
+            self.copies.len(),
+        )?;
+        
+        // the new copy of the current frame gets what the device saw in the old one,
+        // the others catch up on everything when their frame context comes around
+        self.device_state.borrow().write(
+            copies[self.current].allocation.as_ref().unwrap(),
+            0,
+            as_bytes(&self.items),
+        )?;
+        self.dirty = None;
+        self.stale.written(self.current, 0..self.items.len());
+        
+        // the frame being recorded may already use the old copy of the current frame
+        for retired in mem::replace(&mut self.copies, copies) {
+            self.retired.retire(self.timeline, retired);
+        }
+        self.capacity = capacity;
+        Ok(())
+    }
+    
+    fn mark_dirty(&mut self, range: Range<u64>) {
+        self.dirty = Some(merge(self.dirty.take(), range));
+    }
+    
+    fn create_copies(
+        device_state: &Rc<RefCell<DeviceState>>,
+        usage: buffer::Usage,
+        capacity: usize,
+        frame_count: usize,
+    ) -> RendererResult<Vec<BufferState<I>>> {
+        (0..frame_count)
+            .map(|_| BufferState::new_empty(
+                device_state.clone(),
+                (capacity * mem::size_of::<I>()) as u64,
+                usage,
+                Properties::CPU_VISIBLE,
+                AllocationStrategy::General,
+            ))
+            .collect()
+    }
+}
+
+/// the items every copy has missed since it was last brought up to date,
+/// a single range per copy which may cover items that were not written
+struct StaleRanges {
+    ranges: Vec<Option<Range<usize>>>,
+}
+
+impl StaleRanges {
+    fn new(copy_count: usize) -> StaleRanges {
+        StaleRanges {
+            ranges: vec![None; copy_count],
+        }
+    }
+    
+    /// items in range were written to the copy current only
+    fn written(&mut self, current: usize, range: Range<usize>) {
+        if range.start == range.end {
+            return;
+        }
+        for (copy, stale) in self.ranges.iter_mut().enumerate() {
+            if copy != current {
+                *stale = Some(merge(stale.take(), range.clone()));
+            }
+        }
+    }
+    
+    /// the items copy has to catch up on, without those truncated away since
+    fn take(&mut self, copy: usize, len: usize) -> Option<Range<usize>> {
+        self.ranges[copy]
+            .take()
+            .map(|stale| stale.start.min(len)..stale.end.min(len))
+            .filter(|stale| stale.start < stale.end)
+    }
+}
+
+fn merge<T: Ord + Copy>(range: Option<Range<T>>, other: Range<T>) -> Range<T> {
+    match range {
+        Some(range) => range.start.min(other.start)..range.end.max(other.end),
+        None => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn writes_leave_the_other_copies_stale() {
+        let mut stale = StaleRanges::new(3);
+        stale.written(0, 2..5);
+        stale.written(0, 8..10);
+        assert_eq!(stale.take(0, 10), None);
+        assert_eq!(stale.take(1, 10), Some(2..10));
+        assert_eq!(stale.take(2, 10), Some(2..10));
+        // taken ranges are up to date
+        assert_eq!(stale.take(1, 10), None);
+    }
+    
+    #[test]
+    fn copies_catch_up_on_writes_from_every_frame() {
+        let mut stale = StaleRanges::new(2);
+        stale.written(0, 0..4);
+        assert_eq!(stale.take(1, 4), Some(0..4));
+        stale.written(1, 4..6);
+        assert_eq!(stale.take(0, 6), Some(4..6));
+        assert_eq!(stale.take(1, 6), None);
+    }
+    
+    #[test]
+    fn truncated_items_are_not_caught_up_on() {
+        let mut stale = StaleRanges::new(2);
+        stale.written(0, 4..8);
+        assert_eq!(stale.take(1, 6), Some(4..6));
+        stale.written(0, 4..8);
+        assert_eq!(stale.take(1, 2), None);
+    }
+    
+    #[test]
+    fn empty_writes_leave_nothing_stale() {
+        let mut stale = StaleRanges::new(2);
+        stale.written(0, 3..3);
+        assert_eq!(stale.take(1, 3), None);
+    }
+}
@@ -0,0 +1,173 @@
+use std::{
+    error::Error,
+    fmt,
+};
+
+use super::prelude::*;
+
+pub type RendererResult<T> = Result<T, RendererError>;
+
+/// everything that can go wrong while creating the resources of the renderer,
+/// every variant names the resource or file it is about
+#[derive(Debug)]
+pub enum RendererError {
+    /// the instance did not report any adapter to render with
+    NoAdapter,
+    /// a device object could not be created
+    Creation {
+        resource: String,
+        reason: String,
+    },
+    /// none of the memory types allowed for a resource has the properties it needs
+    NoSuitableMemoryType {
+        resource: String,
+        type_mask: u64,
+        properties: Properties,
+    },
+    /// device memory could not be allocated, mapped or bound to a resource
+    Memory {
+        resource: String,
+        reason: String,
+    },
+    /// none of the formats that would do for a resource is supported by the adapter
+    UnsupportedFormat {
+        resource: String,
+        candidates: Vec<Format>,
+    },
+    /// the swapchain could not be created for the surface
+    Swapchain {
+        reason: String,
+    },
+    /// a file under res/ could not be read
+    File {
+        path: String,
+        reason: String,
+    },
+    /// a file produced by the renderer such as a render or a screenshot could not be written
+    Write {
+        path: String,
+        reason: String,
+    },
+    /// a file under res/ was read but its content is unusable
+    Asset {
+        path: String,
+        reason: String,
+    },
+    /// SPIR-V that could not be turned into a shader module or pipeline
+    Shader {
+        path: String,
+        reason: String,
+    },
+    /// GLSL that glslang rejected, with the line of every error when it gave one
+    ShaderCompile {
+        path: String,
+        defines: Vec<String>,
+        // the file under res/ each error is in, which may be one the shader includes
+        errors: Vec<(String, Option<u32>, String)>,
+    },
+    /// the passes added to a render graph cannot be put in an order or given images
+    RenderGraph {
+        reason: String,
+    },
+    /// waiting on, resetting or flushing for the device failed, usually because it was lost
+    Device {
+        operation: String,
+        reason: String,
+    },
+}
+
+impl RendererError {
+    pub fn creation<E: fmt::Debug>(resource: &str, error: E) -> RendererError {
+        RendererError::Creation {
+            resource: resource.to_string(),
+            reason: format!("{:?}", error),
+        }
+    }
+    
+    pub fn memory<E: fmt::Debug>(resource: &str, error: E) -> RendererError {
+        RendererError::Memory {
+            resource: resource.to_string(),
+            reason: format!("{:?}", error),
+        }
+    }
+    
+    pub fn file<E: fmt::Display>(path: &str, error: E) -> RendererError {
+        RendererError::File {
+            path: path.to_string(),
+            reason: error.to_string(),
+        }
+    }
+    
+    pub fn write<E: fmt::Display>(path: &str, error: E) -> RendererError {
+        RendererError::Write {
+            path: path.to_string(),
+            reason: error.to_string(),
+        }
+    }
+    
+    pub fn asset<E: fmt::Display>(path: &str, error: E) -> RendererError {
+        RendererError::Asset {
+            path: path.to_string(),
+            reason: error.to_string(),
+        }
+    }
+    
+    pub fn device<E: fmt::Debug>(operation: &str, error: E) -> RendererError {
+        RendererError::Device {
+            operation: operation.to_string(),
+            reason: format!("{:?}", error),
+        }
+    }
+}
+
+impl fmt::Display for RendererError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RendererError::NoAdapter =>
+                write!(f, "no graphics adapter found"),
+            RendererError::Creation { resource, reason } =>
+                write!(f, "cannot create {}: {}", resource, reason),
+            RendererError::NoSuitableMemoryType { resource, type_mask, properties } =>
+                write!(
+                    f,
+                    "no memory type for {} in mask {:b} has properties {:?}",
+                    resource,
+                    type_mask,
+                    properties
+                ),
+            RendererError::Memory { resource, reason } =>
+                write!(f, "cannot get memory for {}: {}", resource, reason),
+            RendererError::UnsupportedFormat { resource, candidates } =>
+                write!(f, "the adapter supports none of {:?} for {}", candidates, resource),
+            RendererError::Swapchain { reason } =>
+                write!(f, "cannot create swapchain: {}", reason),
+            RendererError::File { path, reason } =>
+                write!(f, "cannot read {}: {}", path, reason),
+            RendererError::Write { path, reason } =>
+                write!(f, "cannot write {}: {}", path, reason),
+            RendererError::Asset { path, reason } =>
+                write!(f, "cannot load {}: {}", path, reason),
+            RendererError::Shader { path, reason } =>
+                write!(f, "cannot use shader {}: {}", path, reason),
+            RendererError::ShaderCompile { path, defines, errors } => {
+                write!(f, "cannot compile shader {}", path)?;
+                if !defines.is_empty() {
+                    write!(f, " with {}", defines.join(", "))?;
+                }
+                for (file, line, message) in errors {
+                    match line {
+                        Some(line) => write!(f, "\n    {}:{}: {}", file, line, message)?,
+                        None => write!(f, "\n    {}: {}", file, message)?,
+                    }
+                }
+                Ok(())
+            }
+            RendererError::RenderGraph { reason } =>
+                write!(f, "cannot compile render graph: {}", reason),
+            RendererError::Device { operation, reason } =>
+                write!(f, "cannot {}: {}", operation, reason),
+        }
+    }
+}
+
+impl Error for RendererError {}
@@ -0,0 +1,280 @@
+use std::{
+    cell::RefCell,
+    rc::Rc,
+};
+
+use super::{
+    device::DeviceState,
+    error::{
+        RendererError,
+        RendererResult,
+    },
+    prelude::*,
+};
+
+/// numbers the frames submitted to the queue in order, starting at 1,
+/// to tell when the GPU is done with what a frame may have used
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameTimeline {
+    submitted: u64,
+    // every frame up to this one has completed
+    completed: u64,
+}
+
+impl FrameTimeline {
+    /// the frame being recorded, or the next one submitted if none is,
+    /// which is the last frame that may use a resource released right now
+    pub fn recording(&self) -> u64 {
+        self.submitted + 1
+    }
+    
+    pub fn is_completed(&self, frame: u64) -> bool {
+        frame <= self.completed
+    }
+}
+
+/// resources frames in flight may still use, each kept until the last frame that may use it
+/// has completed, which a fence signaled for a later submission tells as well
+pub struct RetireQueue<T> {
+    retired: Vec<(u64, T)>,
+}
+
+impl<T> RetireQueue<T> {
+    pub fn new() -> RetireQueue<T> {
+        RetireQueue {
+            retired: vec![],
+        }
+    }
+    
+    /// keep resource until the frame timeline is recording has completed
+    pub fn retire(&mut self, timeline: FrameTimeline, resource: T) {
+        self.retired.push((timeline.recording(), resource));
+    }
+    
+    /// take out every resource the GPU is done with, in the order they were retired
+    pub fn collect(&mut self, timeline: FrameTimeline) -> Vec<T> {
+        let (completed, pending): (Vec<_>, Vec<_>) = self.retired
+            .drain(..)
+            .partition(|(frame, _)| timeline.is_completed(*frame));
+        self.retired = pending;
+        completed.into_iter().map(|(_, resource)| resource).collect()
+    }
+    
+    /// take out every resource whether the GPU is done with it or not,
+    /// for owners that are dropped once the device is idle
+    pub fn drain(&mut self) -> Vec<T> {
+        self.retired.drain(..).map(|(_, resource)| resource).collect()
+    }
+    
+    pub fn len(&self) -> usize {
+        self.retired.len()
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.retired.is_empty()
+    }
+}
+
+impl<T> Default for RetireQueue<T> {
+    fn default() -> RetireQueue<T> {
+        RetireQueue::new()
+    }
+}
+
+/// what a single frame in flight records and synchronizes with,
+/// none of it may be touched again before fence is signaled
+pub struct FrameContext {
+    pub fence: <B as TB>::Fence,
+    // the frame last submitted with this context, 0 before the first one
+    frame: u64,
+    pub command_pool: CommandPool<B, Graphics>,
+    // signaled when the swapchain image is ready to be rendered to
+    pub acquire_semaphore: <B as TB>::Semaphore,
+    // signaled when rendering is done and the image can be presented
+    pub present_semaphore: <B as TB>::Semaphore,
+}
+
+/// a fixed number of frame contexts used in turn,
+/// independent of the number of swapchain images so it survives swapchain rebuilds
+pub struct FrameContextsState {
+    device_state: Rc<RefCell<DeviceState>>,
+    frame_contexts: Option<Vec<FrameContext>>,
+    current_index: usize,
+    timeline: FrameTimeline,
+}
+
+impl FrameContextsState {
+    pub fn new(
+        device_state: Rc<RefCell<DeviceState>>,
+        frames_in_flight: usize,
+    ) -> RendererResult<FrameContextsState> {
+        assert!(frames_in_flight > 0, "at least one frame has to be in flight");
+        
+        // whatever has been created when something fails is destroyed by dropping frame_contexts_state
+        let mut frame_contexts_state = FrameContextsState {
+            device_state: device_state.clone(),
+            frame_contexts: Some(vec![]),
+            current_index: 0,
+            timeline: FrameTimeline::default(),
+        };
+        
+        let device = &device_state.borrow().device;
+        for _ in 0..frames_in_flight {
+            // signaled so that the first wait on every context returns right away
+            let fence = device
+                .create_fence(true)
+                .map_err(|error| RendererError::creation("frame fence", error))?;
+            let command_pool = unsafe {
+                device.create_command_pool_typed(
+                    &device_state.borrow().queue_group,
+                    CommandPoolCreateFlags::empty(),
+                )
+            };
+            let command_pool = match command_pool {
+                Ok(command_pool) => command_pool,
+                Err(error) => {
+                    unsafe { device.destroy_fence(fence); }
+                    return Err(RendererError::creation("frame command pool", error));
+                }
+            };
+            let semaphores = device.create_semaphore().and_then(|acquire_semaphore| {
+                match device.create_semaphore() {
+                    Ok(present_semaphore) => Ok((acquire_semaphore, present_semaphore)),
+                    Err(error) => {
+                        unsafe { device.destroy_semaphore(acquire_semaphore); }
+                        Err(error)
+                    }
+                }
+            });
+            let (acquire_semaphore, present_semaphore) = match semaphores {
+                Ok(semaphores) => semaphores,
+                Err(error) => {
+                    unsafe {
+                        device.destroy_fence(fence);
+                        device.destroy_command_pool(command_pool.into_raw());
+                    }
+                    return Err(RendererError::creation("frame semaphore", error));
+                }
+            };
+            
+            frame_contexts_state.frame_contexts.as_mut().unwrap().push(FrameContext {
+                fence,
+                frame: 0,
+                command_pool,
+                acquire_semaphore,
+                present_semaphore,
+            });
+        }
+        
+        Ok(frame_contexts_state)
+    }
+    
+    /// the number of frames that can be in flight at once
+    pub fn frame_count(&self) -> usize {
+        self.frame_contexts.as_ref().unwrap().len()
+    }
+    
+    /// index of the context the next frame is recorded with,
+    /// per-frame resources such as uniform ring slots are selected with it
+    pub fn current_index(&self) -> usize {
+        self.current_index
+    }
+    
+    pub fn current(&mut self) -> &mut FrameContext {
+        &mut self.frame_contexts.as_mut().unwrap()[self.current_index]
+    }
+    
+    /// the frames submitted so far and those known to have completed,
+    /// as of the last wait
+    pub fn timeline(&self) -> FrameTimeline {
+        self.timeline
+    }
+    
+    /// block until the GPU has finished the last frame recorded with the current context,
+    /// then reset its command pool so it can be recorded again
+    pub fn wait_current(&mut self) -> RendererResult<()> {
+        let device_state = self.device_state.clone();
+        let frame_context = self.current();
+        unsafe {
+            device_state
+                .borrow()
+                .device
+                .wait_for_fence(&frame_context.fence, !0)
+                .map_err(|error| RendererError::device("wait for the frame fence", error))?;
+            frame_context.command_pool.reset();
+        }
+        let frame = self.current().frame;
+        self.timeline.completed = self.timeline.completed.max(frame);
+        Ok(())
+    }
+    
+    /// to be called once the device is idle, every frame submitted has completed then
+    pub fn complete_all(&mut self) {
+        self.timeline.completed = self.timeline.submitted;
+    }
+    
+    /// move on to the next context once the current one has been submitted
+    pub fn advance(&mut self) {
+        self.timeline.submitted += 1;
+        self.current().frame = self.timeline.submitted;
+        self.current_index = (self.current_index + 1) % self.frame_count();
+    }
+}
+
+impl Drop for FrameContextsState {
+    fn drop(&mut self) {
+        let device = &self.device_state.borrow().device;
+        
+        unsafe {
+            if let Some(frame_contexts) = self.frame_contexts.take() {
+                for frame_context in frame_contexts {
+                    device.destroy_fence(frame_context.fence);
+                    device.destroy_command_pool(frame_context.command_pool.into_raw());
+                    device.destroy_semaphore(frame_context.acquire_semaphore);
+                    device.destroy_semaphore(frame_context.present_semaphore);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn timeline(submitted: u64, completed: u64) -> FrameTimeline {
+        FrameTimeline {
+            submitted,
+            completed,
+        }
+    }
+    
+    #[test]
+    fn resources_live_until_the_frame_being_recorded_completes() {
+        let mut queue = RetireQueue::new();
+        queue.retire(timeline(4, 2), "released while recording frame 5");
+        assert!(queue.collect(timeline(4, 4)).is_empty());
+        assert!(queue.collect(timeline(5, 4)).is_empty());
+        assert_eq!(queue.collect(timeline(5, 5)), vec!["released while recording frame 5"]);
+        assert!(queue.is_empty());
+    }
+    
+    #[test]
+    fn a_later_fence_completes_every_earlier_frame() {
+        let mut queue = RetireQueue::new();
+        queue.retire(timeline(0, 0), 1);
+        queue.retire(timeline(1, 0), 2);
+        queue.retire(timeline(3, 1), 3);
+        assert_eq!(queue.collect(timeline(4, 2)), vec![1, 2]);
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue.collect(timeline(6, 6)), vec![3]);
+    }
+    
+    #[test]
+    fn drain_takes_out_what_has_not_completed() {
+        let mut queue = RetireQueue::new();
+        queue.retire(timeline(2, 0), 1);
+        assert_eq!(queue.drain(), vec![1]);
+        assert!(queue.is_empty());
+    }
+}
@@ -0,0 +1,288 @@
+use std::{
+    cell::RefCell,
+    iter,
+    rc::Rc,
+};
+
+use gfs::GemFileSystem;
+
+use crate::frontend::graphic::data_type::MAX_PUSH_CONSTANTS_SIZE;
+
+use super::{
+    descriptor::{
+        DescriptorPoolState,
+        DescriptorState,
+    },
+    device::DeviceState,
+    error::{
+        RendererError,
+        RendererResult,
+    },
+    image::SamplerState,
+    prelude::*,
+    render_graph::PassContext,
+    render_pass::RenderPassState,
+    shader_module::{
+        ShaderCache,
+        ShaderModuleState,
+    },
+};
+
+/// draws the single triangle covering the viewport every fullscreen pass shares
+pub const FULLSCREEN_VERTEX_SHADER_PATH: &str = "shaders/gen/fullscreen.vert.spv";
+/// the stages the push constants of a fullscreen pass are visible to
+pub const FULLSCREEN_PUSH_CONSTANT_STAGES: ShaderStageFlags = ShaderStageFlags::FRAGMENT;
+
+/// what a FullscreenPassState is made of
+pub struct FullscreenPassDesc<'a> {
+    pub fragment_shader_path: &'a str,
+    // images sampled from bindings 0.. of set 0
+    pub input_count: usize,
+    // layouts of the sets bound after set 0, such as the one of a TextureRegistry
+    pub extra_descriptor_states: Vec<&'a DescriptorState>,
+    // in bytes, a multiple of 4
+    pub push_constants_size: usize,
+    pub output_format: Format,
+    // the layout the output image is left in
+    pub output_layout: Layout,
+}
+
+/// a pass drawing one triangle over the whole of a single color attachment,
+/// the fragment shader reads its inputs through one shared clamping, linear sampler
+pub struct FullscreenPassState {
+    device_state: Rc<RefCell<DeviceState>>,
+    pub pipeline: Option<<B as TB>::GraphicsPipeline>,
+    pub pipeline_layout: Option<<B as TB>::PipelineLayout>,
+    // names the pass in errors
+    fragment_shader_path: String,
+    input_count: usize,
+    push_constants_size: usize,
+    // the one set holding the inputs, written again whenever they are recreated
+    descriptor_state: DescriptorState,
+    descriptor_pool_state: DescriptorPoolState,
+    pub sampler_state: Rc<SamplerState>,
+    pub render_pass_state: RenderPassState,
+}
+
+impl FullscreenPassState {
+    pub fn new(
+        device_state: Rc<RefCell<DeviceState>>,
+        desc: &FullscreenPassDesc<'_>,
+        sampler_state: Rc<SamplerState>,
+        gfs: &mut GemFileSystem,
+        shader_cache: &ShaderCache,
+    ) -> RendererResult<FullscreenPassState> {
+        assert!(desc.input_count > 0, "a fullscreen pass has to read something");
+        assert!(desc.push_constants_size <= MAX_PUSH_CONSTANTS_SIZE);
+        assert_eq!(desc.push_constants_size % 4, 0);
+        
+        let vertex_spirv = shader_cache.read(gfs, FULLSCREEN_VERTEX_SHADER_PATH)?;
+        let fragment_spirv = shader_cache.read(gfs, desc.fragment_shader_path)?;
+        
+        let mut descriptor_pool_state = DescriptorPoolState::new(
+            device_state.clone(),
+            &[
+                DescriptorRangeDesc {
+                    ty: DescriptorType::CombinedImageSampler,
+                    count: desc.input_count,
+                },
+            ],
+        )?;
+        let bindings: Vec<DescriptorSetLayoutBinding> = (0..desc.input_count)
+            .map(|binding| DescriptorSetLayoutBinding {
+                binding: binding as u32,
+                ty: DescriptorType::CombinedImageSampler,
+                count: 1,
+                stage_flags: ShaderStageFlags::FRAGMENT,
+                immutable_samplers: false,
+            })
+            .collect();
+        let mut descriptor_state = DescriptorState::new(device_state.clone(), &bindings, &[])?;
+        descriptor_state.allocate_descriptor_set(&mut descriptor_pool_state)?;
+        
+        // every pixel is written, so the attachment is neither multisampled nor depth tested
+        let render_pass_state = RenderPassState::new_with_formats(
+            device_state.clone(),
+            desc.output_format,
+            None,
+            1,
+            desc.output_layout,
+        )?;
+        
+        // whatever has been created when something fails is destroyed by dropping fullscreen_pass_state
+        let mut fullscreen_pass_state = FullscreenPassState {
+            device_state: device_state.clone(),
+            pipeline: None,
+            pipeline_layout: None,
+            fragment_shader_path: desc.fragment_shader_path.to_string(),
+            input_count: desc.input_count,
+            push_constants_size: desc.push_constants_size,
+            descriptor_state,
+            descriptor_pool_state,
+            sampler_state,
+            render_pass_state,
+        };
+        
+        let pipeline_layout = unsafe {
+            let descriptor_set_layouts = iter::once(&fullscreen_pass_state.descriptor_state)
+                .chain(desc.extra_descriptor_states.iter().cloned())
+                .map(|descriptor_state| descriptor_state.descriptor_set_layout.as_ref().unwrap());
+            let push_constants = if desc.push_constants_size > 0 {
+                Some((FULLSCREEN_PUSH_CONSTANT_STAGES, 0..(desc.push_constants_size / 4) as u32))
+            } else {
+                None
+            };
+            device_state.borrow().device.create_pipeline_layout(
+                descriptor_set_layouts,
+                push_constants,
+            )
+        }.map_err(|error| RendererError::creation(
+            &format!("pipeline layout of {}", desc.fragment_shader_path),
+            error,
+        ))?;
+        fullscreen_pass_state.pipeline_layout = Some(pipeline_layout);
+        
+        let vertex_shader_module = ShaderModuleState::new(
+            device_state.clone(),
+            FULLSCREEN_VERTEX_SHADER_PATH,
+            &vertex_spirv,
+        )?;
+        let fragment_shader_module = ShaderModuleState::new(
+            device_state.clone(),
+            desc.fragment_shader_path,
+            &fragment_spirv,
+        )?;
+        
+        let pipeline = unsafe {
+            let shader_set = GraphicsShaderSet {
+                vertex: EntryPoint {
+                    entry: "main",
+                    module: vertex_shader_module.module.as_ref().unwrap(),
+                    specialization: Default::default(),
+                },
+                hull: None,
+                domain: None,
+                geometry: None,
+                fragment: Some(EntryPoint {
+                    entry: "main",
+                    module: fragment_shader_module.module.as_ref().unwrap(),
+                    specialization: Default::default(),
+                }),
+            };
+            
+            let subpass = Subpass {
+                index: 0,
+                main_pass: fullscreen_pass_state.render_pass_state.render_pass.as_ref().unwrap(),
+            };
+            
+            // the fullscreen triangle is generated from the vertex index,
+            // so there are no vertex buffers and no depth test
+            let mut pipeline_desc = GraphicsPipelineDesc::new(
+                shader_set,
+                Primitive::TriangleList,
+                Rasterizer::FILL,
+                fullscreen_pass_state.pipeline_layout.as_ref().unwrap(),
+                subpass,
+            );
+            pipeline_desc
+                .blender
+                .targets
+                .push(ColorBlendDesc(ColorMask::ALL, BlendState::Off));
+            
+            device_state.borrow().device.create_graphics_pipeline(&pipeline_desc, None)
+        }.map_err(|error| RendererError::Shader {
+            path: format!("{} + {}", FULLSCREEN_VERTEX_SHADER_PATH, desc.fragment_shader_path),
+            reason: format!("cannot create graphics pipeline: {:?}", error),
+        })?;
+        fullscreen_pass_state.pipeline = Some(pipeline);
+        
+        Ok(fullscreen_pass_state)
+    }
+    
+    /// the pipeline is created for this render pass and can be used in any compatible one
+    pub fn render_pass(&self) -> &<B as TB>::RenderPass {
+        self.render_pass_state.render_pass.as_ref().unwrap()
+    }
+    
+    /// point binding i of the input set at input_image_views[i], which are sampled
+    /// in ShaderReadOnlyOptimal and must not be in use by a frame in flight
+    pub fn write_inputs(&self, input_image_views: &[&<B as TB>::ImageView]) {
+        assert_eq!(
+            input_image_views.len(),
+            self.input_count,
+            "{} reads {} images",
+            self.fragment_shader_path,
+            self.input_count
+        );
+        let sampler = self.sampler_state.sampler.as_ref().unwrap();
+        let descriptor_set_writes = input_image_views
+            .iter()
+            .enumerate()
+            .map(|(binding, image_view)| DescriptorSetWrite {
+                set: self.descriptor_state.descriptor_set.as_ref().unwrap(),
+                binding: binding as u32,
+                array_offset: 0,
+                descriptors: Some(Descriptor::CombinedImageSampler(
+                    *image_view,
+                    Layout::ShaderReadOnlyOptimal,
+                    sampler,
+                )),
+            });
+        unsafe {
+            self.device_state.borrow().device.write_descriptor_sets(descriptor_set_writes);
+        }
+    }
+    
+    /// record the pass in a render pass of a render graph, which must be compatible with render_pass,
+    /// extra_descriptor_sets are bound after the input set
+    pub unsafe fn record(
+        &self,
+        command_buffer: &mut CommandBuffer<B, Graphics, OneShot>,
+        context: &PassContext<'_>,
+        extra_descriptor_sets: &[&<B as TB>::DescriptorSet],
+        push_constants: &[u32],
+    ) {
+        assert_eq!(push_constants.len() * 4, self.push_constants_size);
+        let pipeline_layout = self.pipeline_layout.as_ref().unwrap();
+        command_buffer.set_viewports(0, &[context.viewport.clone()]);
+        command_buffer.set_scissors(0, &[context.viewport.rect.clone()]);
+        command_buffer.bind_graphics_pipeline(self.pipeline.as_ref().unwrap());
+        command_buffer.bind_graphics_descriptor_sets(
+            pipeline_layout,
+            0,
+            iter::once(self.descriptor_state.descriptor_set.as_ref().unwrap())
+                .chain(extra_descriptor_sets.iter().cloned()),
+            iter::empty::<u32>(),
+        );
+        
+        let mut encoder = command_buffer.begin_render_pass_inline(
+            context.render_pass,
+            context.frame_buffer,
+            context.viewport.rect.clone(),
+            context.clear_values,
+        );
+        if !push_constants.is_empty() {
+            encoder.push_graphics_constants(
+                pipeline_layout,
+                FULLSCREEN_PUSH_CONSTANT_STAGES,
+                0,
+                push_constants,
+            );
+        }
+        encoder.draw(0..3, 0..1);
+    }
+}
+
+impl Drop for FullscreenPassState {
+    fn drop(&mut self) {
+        let device = &self.device_state.borrow().device;
+        unsafe {
+            if let Some(pipeline) = self.pipeline.take() {
+                device.destroy_graphics_pipeline(pipeline);
+            }
+            if let Some(pipeline_layout) = self.pipeline_layout.take() {
+                device.destroy_pipeline_layout(pipeline_layout);
+            }
+        }
+    }
+}
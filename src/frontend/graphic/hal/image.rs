@@ -7,79 +7,164 @@ use std::iter;
 
 use super::{
     adapter::AdapterState,
+    allocator::{
+        Allocation,
+        AllocationStrategy,
+        MemoryCategory,
+    },
     buffer::BufferState,
     descriptor::DescriptorState,
     device::DeviceState,
+    error::{
+        RendererError,
+        RendererResult,
+    },
     prelude::*,
 };
 
 pub const BYTES_PIXEL: u32 = 4;
+pub const BYTES_PIXEL_RGBA32_SFLOAT: u32 = 16;
 pub const COLOR_RANGE: SubresourceRange =
     SubresourceRange {
         aspects: Aspects::COLOR,
         levels: 0..1,
         layers: 0..1,
     };
+pub const CUBE_COLOR_RANGE: SubresourceRange =
+    SubresourceRange {
+        aspects: Aspects::COLOR,
+        levels: 0..1,
+        layers: 0..6,
+    };
+
+/// what kind of view a SampledImageState is sampled through
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampledImageKind {
+    D2,
+    /// six layers, one for each face, viewed as a cube
+    Cube,
+}
+
+impl SampledImageKind {
+    pub fn layers(&self) -> image::Layer {
+        match self {
+            SampledImageKind::D2 => 1,
+            SampledImageKind::Cube => 6,
+        }
+    }
+    
+    pub fn subresource_range(&self) -> SubresourceRange {
+        match self {
+            SampledImageKind::D2 => COLOR_RANGE.clone(),
+            SampledImageKind::Cube => CUBE_COLOR_RANGE.clone(),
+        }
+    }
+}
 
 pub struct ImageState {
     pub device_state: Rc<RefCell<DeviceState>>,
     pub image: Option<<B as TB>::Image>,
-    pub memory: Option<<B as TB>::Memory>,
+    pub allocation: Option<Allocation>,
     pub image_view: Option<<B as TB>::ImageView>,
 }
 
 impl ImageState {
     pub fn new(
         device_state: Rc<RefCell<DeviceState>>,
-        adapter_state: &AdapterState,
         width: u32,
         height: u32,
         color_format: Format,
-    ) -> ImageState {
-        let (image, memory, image_view) = unsafe {
-            let device = &device_state.borrow_mut().device;
-            let mut image = device.create_image(
-                image::Kind::D2(width as image::Size, height as image::Size, 1, 1),
+    ) -> RendererResult<ImageState> {
+        ImageState::new_with_description(
+            device_state,
+            image::Kind::D2(width as image::Size, height as image::Size, 1, 1),
+            color_format,
+            image::Usage::TRANSFER_DST | image::Usage::SAMPLED,
+            image::ViewCapabilities::empty(),
+            image::ViewKind::D2,
+            COLOR_RANGE.clone(),
+        )
+    }
+    
+    pub fn new_cube(
+        device_state: Rc<RefCell<DeviceState>>,
+        face_size: u32,
+        color_format: Format,
+    ) -> RendererResult<ImageState> {
+        ImageState::new_with_description(
+            device_state,
+            image::Kind::D2(face_size as image::Size, face_size as image::Size, 6, 1),
+            color_format,
+            image::Usage::TRANSFER_DST | image::Usage::SAMPLED,
+            image::ViewCapabilities::KIND_CUBE,
+            image::ViewKind::Cube,
+            CUBE_COLOR_RANGE.clone(),
+        )
+    }
+    
+    /// create an optimal tiling image in device local memory together with a view of it
+    pub fn new_with_description(
+        device_state: Rc<RefCell<DeviceState>>,
+        kind: image::Kind,
+        format: Format,
+        usage: image::Usage,
+        view_capabilities: ViewCapabilities,
+        view_kind: ViewKind,
+        range: SubresourceRange,
+    ) -> RendererResult<ImageState> {
+        let resource = format!("{:?} image {:?} for {:?}", format, kind, usage);
+        let mut image_state = ImageState {
+            device_state: device_state.clone(),
+            image: None,
+            allocation: None,
+            image_view: None,
+        };
+        // whatever has been created when something fails is destroyed by dropping image_state
+        unsafe {
+            let mut device_state = device_state.borrow_mut();
+            let mut image = device_state.device.create_image(
+                kind,
                 1,
-                color_format,
+                format,
                 image::Tiling::Optimal,
-                image::Usage::TRANSFER_DST | image::Usage::SAMPLED,
-                image::ViewCapabilities::empty(),
-            ).expect("cannot create Image");
+                usage,
+                view_capabilities,
+            ).map_err(|error| RendererError::creation(&resource, error))?;
             
-            let memory_requirements = device.get_image_requirements(&image);
+            let memory_requirements = device_state.device.get_image_requirements(&image);
             
-            let device_type = adapter_state.choose_memory_type_from_memory_requirement(
+            let allocation = device_state.allocate(
+                &resource,
+                MemoryCategory::from_image_usage(usage),
                 memory_requirements,
                 Properties::DEVICE_LOCAL,
+                AllocationStrategy::General,
             );
+            let allocation = match allocation {
+                Ok(allocation) => allocation,
+                Err(error) => {
+                    device_state.device.destroy_image(image);
+                    return Err(error);
+                }
+            };
             
-            let memory =
-                device
-                    .allocate_memory(device_type, memory_requirements.size)
-                    .unwrap();
-            
-            device
-                .bind_image_memory(&memory, 0, &mut image)
-                .unwrap();
+            let bound = device_state.device
+                .bind_image_memory(device_state.memory(&allocation), allocation.offset, &mut image);
+            image_state.image = Some(image);
+            image_state.allocation = Some(allocation);
+            bound.map_err(|error| RendererError::memory(&resource, error))?;
             
-            let image_view = device
+            let image_view = device_state.device
                 .create_image_view(
-                    &image,
-                    image::ViewKind::D2,
-                    color_format,
+                    image_state.image.as_ref().unwrap(),
+                    view_kind,
+                    format,
                     Swizzle::NO,
-                    COLOR_RANGE.clone(),
-                ).unwrap();
-            
-            (Some(image), Some(memory), Some(image_view))
-        };
-        ImageState {
-            device_state,
-            image,
-            memory,
-            image_view,
+                    range,
+                ).map_err(|error| RendererError::creation(&format!("view of {}", resource), error))?;
+            image_state.image_view = Some(image_view);
         }
+        Ok(image_state)
     }
 }
 
@@ -88,17 +173,37 @@ pub struct SamplerState {
     pub sampler: Option<<B as TB>::Sampler>,
 }
 
+impl Drop for ImageState {
+    fn drop(&mut self) {
+        let mut device_state = self.device_state.borrow_mut();
+        unsafe {
+            if let Some(image_view) = self.image_view.take() {
+                device_state.device.destroy_image_view(image_view);
+            }
+            if let Some(image) = self.image.take() {
+                device_state.device.destroy_image(image);
+            }
+        }
+        if let Some(allocation) = self.allocation.take() {
+            device_state.free(allocation);
+        }
+    }
+}
+
 impl SamplerState {
-    pub fn new(device_state: Rc<RefCell<DeviceState>>, sampler_info: SamplerInfo) -> Self {
+    pub fn new(
+        device_state: Rc<RefCell<DeviceState>>,
+        sampler_info: SamplerInfo,
+    ) -> RendererResult<Self> {
         let sampler = unsafe {
             let device = &device_state.borrow_mut().device;
             
             device.create_sampler(sampler_info)
-        }.ok();
-        SamplerState {
+        }.map_err(|error| RendererError::creation("sampler", error))?;
+        Ok(SamplerState {
             device_state,
-            sampler,
-        }
+            sampler: Some(sampler),
+        })
     }
 }
 
@@ -117,10 +222,14 @@ impl Drop for SamplerState {
 pub struct SampledImageState {
     pub device_state: Rc<RefCell<DeviceState>>,
     pub image_state: ImageState,
-    pub buffer_state: BufferState<u8>,
+    // the staging buffer the image is copied from, released once the copy is done
+    pub buffer_state: Option<BufferState<u8>>,
     pub image_dimensions: (u32, u32),
     pub buffer_pitch_size: u32,
-    pub sampler_state: SamplerState,
+    pub bytes_per_pixel: u32,
+    pub kind: SampledImageKind,
+    // samplers are shared between images through SamplerCache
+    pub sampler_state: Rc<SamplerState>,
 }
 
 impl SampledImageState {
@@ -128,7 +237,8 @@ impl SampledImageState {
         device_state: Rc<RefCell<DeviceState>>,
         adapter_state: &AdapterState,
         image: ::image::ImageBuffer<::image::Rgba<u8>, Vec<u8>>,
-    ) -> SampledImageState {
+        sampler_state: Rc<SamplerState>,
+    ) -> RendererResult<SampledImageState> {
         let (width, height) = image.dimensions();
         let format = Format::Rgba32Sfloat;
         let (buffer_state, buffer_pitch_size) = BufferState::new_texture_buffer(
@@ -139,37 +249,81 @@ impl SampledImageState {
             BYTES_PIXEL,
             image.into_raw(),
             buffer::Usage::TRANSFER_SRC,
-        );
+        )?;
         let image_state = ImageState::new(
             device_state.clone(),
-            adapter_state,
             width,
             height,
             format,
-        );
+        )?;
         
-        let sampler_state = SamplerState::new(
+        Ok(SampledImageState {
+            device_state,
+            image_state,
+            buffer_state: Some(buffer_state),
+            image_dimensions: (width, height),
+            buffer_pitch_size,
+            bytes_per_pixel: BYTES_PIXEL,
+            kind: SampledImageKind::D2,
+            sampler_state,
+        })
+    }
+    
+    /// create a floating-point cube image from six faces of RGBA32 texels,
+    /// the faces are expected to be laid out one after another in the order of
+    /// +X, -X, +Y, -Y, +Z, -Z
+    pub fn new_cube(
+        device_state: Rc<RefCell<DeviceState>>,
+        adapter_state: &AdapterState,
+        face_size: u32,
+        faces: Vec<u8>,
+        sampler_state: Rc<SamplerState>,
+    ) -> RendererResult<SampledImageState> {
+        let format = Format::Rgba32Sfloat;
+        let (buffer_state, buffer_pitch_size) = BufferState::new_texture_buffer(
             device_state.clone(),
-            SamplerInfo::new(
-                Filter::Linear,
-                WrapMode::Clamp,
-            ),
-        );
+            adapter_state,
+            face_size,
+            face_size * SampledImageKind::Cube.layers() as u32,
+            BYTES_PIXEL_RGBA32_SFLOAT,
+            faces,
+            buffer::Usage::TRANSFER_SRC,
+        )?;
+        let image_state = ImageState::new_cube(
+            device_state.clone(),
+            face_size,
+            format,
+        )?;
         
-        SampledImageState {
+        Ok(SampledImageState {
             device_state,
             image_state,
-            buffer_state,
-            image_dimensions: (width, height),
+            buffer_state: Some(buffer_state),
+            image_dimensions: (face_size, face_size),
             buffer_pitch_size,
+            bytes_per_pixel: BYTES_PIXEL_RGBA32_SFLOAT,
+            kind: SampledImageKind::Cube,
             sampler_state,
-        }
+        })
     }
+    
+    /// free the staging buffer, only to be called after the command buffer
+    /// returned by transfer has finished executing
+    pub fn release_staging_buffer(&mut self) {
+        self.buffer_state.take();
+    }
+    
     pub fn register_descriptor(&mut self, descriptor_state: &DescriptorState) {
+        self.write_descriptor_set(
+            descriptor_state.descriptor_set.as_ref().unwrap()
+        );
+    }
+    
+    /// point binding 0 of the given set to this image and its sampler
+    pub fn write_descriptor_set(&self, set: &<B as TB>::DescriptorSet) {
         unsafe {
             let device = &self.device_state.borrow().device;
             
-            let set = descriptor_state.descriptor_set.as_ref().unwrap();
             device.write_descriptor_sets(
                 vec![
                     DescriptorSetWrite {
@@ -200,7 +354,7 @@ impl SampledImageState {
                 ..(Access::TRANSFER_WRITE, Layout::TransferDstOptimal),
             target: self.image_state.image.as_ref().unwrap(),
             families: None,
-            range: COLOR_RANGE.clone(),
+            range: self.kind.subresource_range(),
         };
         
         command_buffer.pipeline_barrier(
@@ -210,19 +364,19 @@ impl SampledImageState {
         );
         
         command_buffer.copy_buffer_to_image(
-            self.buffer_state.buffer.as_ref().unwrap(),
+            self.buffer_state.as_ref().unwrap().buffer.as_ref().unwrap(),
             self.image_state.image.as_ref().unwrap(),
             Layout::TransferDstOptimal,
             &[
                 BufferImageCopy {
                     buffer_offset: 0,
-                    buffer_width: self.buffer_pitch_size / BYTES_PIXEL as u32,
+                    buffer_width: self.buffer_pitch_size / self.bytes_per_pixel,
                     buffer_height: self.image_dimensions.1 as u32,
                     image_layers:
                     SubresourceLayers {
                         aspects: Aspects::COLOR,
                         level: 0,
-                        layers: 0..1,
+                        layers: 0..self.kind.layers(),
                     },
                     image_offset: Offset { x: 0, y: 0, z: 0 },
                     image_extent:
@@ -240,7 +394,7 @@ impl SampledImageState {
                 ..(Access::SHADER_READ, Layout::ShaderReadOnlyOptimal),
             target: self.image_state.image.as_ref().unwrap(),
             families: None,
-            range: COLOR_RANGE.clone(),
+            range: self.kind.subresource_range(),
         };
         command_buffer.pipeline_barrier(
             PipelineStage::TRANSFER..PipelineStage::FRAGMENT_SHADER,
@@ -257,142 +411,36 @@ impl SampledImageState {
         adapter_state: &AdapterState,
         image: ::image::ImageBuffer<::image::Rgba<u8>, Vec<u8>>,
         usage: buffer::Usage,
-    ) -> SampledImageState
+        sampler_state: Rc<SamplerState>,
+    ) -> RendererResult<SampledImageState>
     {
-        let (buffer_state, dims, row_pitch, stride) = {
-            let (width, height) = image.dimensions();
-            
-            let row_alignment_mask = adapter_state.limits.min_buffer_copy_pitch_alignment as u32 - 1;
-            let stride = 4usize;
-            
-            let row_pitch = (width * stride as u32 + row_alignment_mask) & !row_alignment_mask;
-            let upload_size = (height * row_pitch) as u64;
-            
-            let memory: <B as TB>::Memory;
-            let mut buffer: <B as TB>::Buffer;
-            let size: u64;
-            
-            unsafe {
-                let device = &device_state.borrow().device;
-                let adapter = &adapter_state.adapter;
-                buffer = device.create_buffer(upload_size, usage).unwrap();
-                let mem_reqs = device.get_buffer_requirements(&buffer);
-                
-                let upload_type = adapter_state
-                    .memory_types
-                    .iter()
-                    .enumerate()
-                    .position(|(id, mem_type)| {
-                        mem_reqs.type_mask & (1 << id) != 0
-                            && mem_type.properties.contains(Properties::CPU_VISIBLE)
-                    })
-                    .unwrap()
-                    .into();
-                
-                memory = device.allocate_memory(upload_type, mem_reqs.size).unwrap();
-                device.bind_buffer_memory(&memory, 0, &mut buffer).unwrap();
-                size = mem_reqs.size;
-                
-                // copy image data into staging buffer
-                {
-                    let mut data_target = device
-                        .acquire_mapping_writer::<u8>(&memory, 0..size)
-                        .unwrap();
-                    
-                    for y in 0..height as usize {
-                        let data_source_slice = &(*image)[y * (width as usize) * stride..(y + 1) *
-                            (width as usize) * stride];
-                        let dest_base = y * row_pitch as usize;
-                        
-                        data_target[dest_base..dest_base + data_source_slice.len()]
-                            .copy_from_slice(data_source_slice);
-                    }
-                    
-                    device.release_mapping_writer(data_target).unwrap();
-                }
-            }
-            
-            (
-                BufferState {
-                    memory: Some(memory),
-                    buffer: Some(buffer),
-                    size: Some(size),
-                    device_state: device_state.clone(),
-                    _phantom_data: PhantomData,
-                },
-                (width, height),
-                row_pitch,
-                stride,
-            )
-        };
-        
-        let device = &device_state.borrow().device;
-        
-        let kind = image::Kind::D2(dims.0 as image::Size, dims.1 as image::Size, 1, 1);
-        let mut image = unsafe {
-            device
-                .create_image(
-                    kind,
-                    1,
-                    Format::Rgba8Srgb,
-                    image::Tiling::Optimal,
-                    image::Usage::TRANSFER_DST | image::Usage::SAMPLED,
-                    image::ViewCapabilities::empty(),
-                )
-        }.unwrap();
-        let req = unsafe { device.get_image_requirements(&image) };
-        
-        let device_type = adapter_state
-            .memory_types
-            .iter()
-            .enumerate()
-            .position(|(id, memory_type)| {
-                req.type_mask & (1 << id) != 0
-                    && memory_type.properties.contains(Properties::DEVICE_LOCAL)
-            })
-            .unwrap()
-            .into();
-        
-        let memory = unsafe { device.allocate_memory(device_type, req.size) }.unwrap();
-        
-        unsafe { device.bind_image_memory(&memory, 0, &mut image) }.unwrap();
-        
-        let image_view = unsafe {
-            device
-                .create_image_view(
-                    &image,
-                    image::ViewKind::D2,
-                    Format::Rgba8Srgb,
-                    Swizzle::NO,
-                    COLOR_RANGE.clone(),
-                )
-        }.unwrap();
+        let (width, height) = image.dimensions();
+        let (buffer_state, row_pitch) = BufferState::new_texture_buffer(
+            device_state.clone(),
+            adapter_state,
+            width,
+            height,
+            BYTES_PIXEL,
+            image.into_raw(),
+            usage,
+        )?;
         
-        let sampler = unsafe {
-            device
-                .create_sampler(
-                    image::SamplerInfo::new(
-                        image::Filter::Nearest,
-                        image::WrapMode::Clamp,
-                    )
-                )
-        }.expect("Can't create sampler");
+        let image_state = ImageState::new(
+            device_state.clone(),
+            width,
+            height,
+            Format::Rgba8Srgb,
+        )?;
         
-        SampledImageState {
-            device_state: device_state.clone(),
-            image_state: ImageState {
-                device_state: device_state.clone(),
-                image: Some(image),
-                memory: Some(memory),
-                image_view: Some(image_view),
-            },
-            buffer_state,
-            image_dimensions: dims,
+        Ok(SampledImageState {
+            device_state,
+            image_state,
+            buffer_state: Some(buffer_state),
+            image_dimensions: (width, height),
             buffer_pitch_size: row_pitch,
-            sampler_state: SamplerState {
-                device_state: device_state.clone(),
-                sampler: Some(sampler),
-            },
-        }
+            bytes_per_pixel: BYTES_PIXEL,
+            kind: SampledImageKind::D2,
+            sampler_state,
+        })
     }
-}
\ No newline at end of file
+}
@@ -7,4 +7,15 @@ pub mod shader_module;
 pub mod buffer;
 pub mod descriptor;
 pub mod image;
-pub mod adapter;
\ No newline at end of file
+pub mod adapter;
+pub mod sampler;
+pub mod allocator;
+pub mod uniform;
+pub mod error;
+pub mod dynamic_buffer;
+pub mod frame;
+pub mod offscreen;
+pub mod fullscreen;
+pub mod render_graph;
+pub mod reflection;
+pub mod tonemap;
\ No newline at end of file
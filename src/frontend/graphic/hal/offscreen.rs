@@ -0,0 +1,204 @@
+use std::{
+    cell::RefCell,
+    iter,
+    rc::Rc,
+};
+
+use crate::frontend::graphic::constants::COLOR_RANGE;
+
+use super::{
+    buffer::BufferState,
+    device::DeviceState,
+    error::{
+        RendererError,
+        RendererResult,
+    },
+    image::ImageState,
+    prelude::*,
+};
+
+/// a color image and, if the render pass has one, a depth image with a framebuffer over them,
+/// the color image is copied back to host memory once rendered
+pub struct OffscreenTargetState {
+    device_state: Rc<RefCell<DeviceState>>,
+    pub frame_buffer: Option<<B as TB>::Framebuffer>,
+    // single sampled color image holding the result of the render pass
+    pub color_image_state: Option<ImageState>,
+    // multisampled color target resolved to color_image_state, none without multisampling
+    pub msaa_color_image_state: Option<ImageState>,
+    pub depth_image_state: Option<ImageState>,
+    pub extent: Extent,
+    pub color_format: Format,
+}
+
+impl OffscreenTargetState {
+    /// render_pass must have been created with the same formats and sample count,
+    /// color_usage is what the color image is used for besides being rendered to
+    pub fn new(
+        device_state: Rc<RefCell<DeviceState>>,
+        render_pass: &<B as TB>::RenderPass,
+        samples: image::NumSamples,
+        color_format: Format,
+        color_usage: image::Usage,
+        depth_format: Option<Format>,
+        extent: Extent2D,
+    ) -> RendererResult<OffscreenTargetState> {
+        let extent = extent.to_extent();
+        let kind = |samples| image::Kind::D2(
+            extent.width as image::Size,
+            extent.height as image::Size,
+            1,
+            samples,
+        );
+        
+        // whatever has been created when something fails is destroyed by dropping offscreen_target_state
+        let mut offscreen_target_state = OffscreenTargetState {
+            device_state: device_state.clone(),
+            frame_buffer: None,
+            color_image_state: None,
+            msaa_color_image_state: None,
+            depth_image_state: None,
+            extent,
+            color_format,
+        };
+        
+        offscreen_target_state.color_image_state = Some(ImageState::new_with_description(
+            device_state.clone(),
+            kind(1),
+            color_format,
+            image::Usage::COLOR_ATTACHMENT | color_usage,
+            ViewCapabilities::empty(),
+            image::ViewKind::D2,
+            COLOR_RANGE.clone(),
+        )?);
+        if samples > 1 {
+            offscreen_target_state.msaa_color_image_state = Some(ImageState::new_with_description(
+                device_state.clone(),
+                kind(samples),
+                color_format,
+                image::Usage::COLOR_ATTACHMENT | image::Usage::TRANSIENT_ATTACHMENT,
+                ViewCapabilities::empty(),
+                image::ViewKind::D2,
+                COLOR_RANGE.clone(),
+            )?);
+        }
+        if let Some(depth_format) = depth_format {
+            offscreen_target_state.depth_image_state = Some(ImageState::new_with_description(
+                device_state.clone(),
+                kind(samples),
+                depth_format,
+                image::Usage::DEPTH_STENCIL_ATTACHMENT,
+                ViewCapabilities::empty(),
+                image::ViewKind::D2,
+                image::SubresourceRange {
+                    aspects: depth_format.surface_desc().aspects,
+                    levels: 0..1,
+                    layers: 0..1,
+                },
+            )?);
+        }
+        
+        let frame_buffer = {
+            let view = |image_state: &Option<ImageState>| {
+                image_state.as_ref().map(|image_state| image_state.image_view.as_ref().unwrap())
+            };
+            let color_image_view = view(&offscreen_target_state.color_image_state).unwrap();
+            let depth_image_view = view(&offscreen_target_state.depth_image_state);
+            // in the order of the attachments of RenderPassState,
+            // which a render pass without depth such as the last post-processing pass shares
+            let attachments: Vec<_> = match view(&offscreen_target_state.msaa_color_image_state) {
+                Some(msaa_color_image_view) => iter::once(msaa_color_image_view)
+                    .chain(depth_image_view)
+                    .chain(iter::once(color_image_view))
+                    .collect(),
+                None => iter::once(color_image_view).chain(depth_image_view).collect(),
+            };
+            unsafe {
+                device_state.borrow().device.create_framebuffer(
+                    render_pass,
+                    attachments,
+                    extent,
+                )
+            }.map_err(|error| RendererError::creation("offscreen framebuffer", error))?
+        };
+        offscreen_target_state.frame_buffer = Some(frame_buffer);
+        
+        Ok(offscreen_target_state)
+    }
+    
+    pub fn color_image_view(&self) -> &<B as TB>::ImageView {
+        self.color_image_state.as_ref().unwrap().image_view.as_ref().unwrap()
+    }
+    
+    /// size in bytes of the color image read back, rows are tightly packed
+    pub fn readback_size(&self) -> u64 {
+        let bytes_per_pixel = (self.color_format.surface_desc().bits / 8) as u64;
+        self.extent.width as u64 * self.extent.height as u64 * bytes_per_pixel
+    }
+    
+    /// record the copy of the rendered color image into readback_buffer,
+    /// to be recorded after the render pass and waited on before the buffer is read,
+    /// the color image has to have been created with TRANSFER_SRC
+    pub unsafe fn record_readback(
+        &self,
+        command_buffer: &mut CommandBuffer<B, Graphics, OneShot>,
+        readback_buffer: &BufferState<u8>,
+    ) {
+        let color_image = self.color_image_state.as_ref().unwrap().image.as_ref().unwrap();
+        
+        // the render pass leaves the image in TransferSrcOptimal,
+        // its writes still have to be made visible to the copy
+        command_buffer.pipeline_barrier(
+            PipelineStage::COLOR_ATTACHMENT_OUTPUT..PipelineStage::TRANSFER,
+            Dependencies::empty(),
+            &[
+                Barrier::Image {
+                    states: (Access::COLOR_ATTACHMENT_WRITE, Layout::TransferSrcOptimal)
+                        ..(Access::TRANSFER_READ, Layout::TransferSrcOptimal),
+                    target: color_image,
+                    families: None,
+                    range: COLOR_RANGE.clone(),
+                }
+            ],
+        );
+        
+        command_buffer.copy_image_to_buffer(
+            color_image,
+            Layout::TransferSrcOptimal,
+            readback_buffer.buffer.as_ref().unwrap(),
+            &[
+                BufferImageCopy {
+                    buffer_offset: 0,
+                    buffer_width: self.extent.width,
+                    buffer_height: self.extent.height,
+                    image_layers: SubresourceLayers {
+                        aspects: Aspects::COLOR,
+                        level: 0,
+                        layers: 0..1,
+                    },
+                    image_offset: Offset { x: 0, y: 0, z: 0 },
+                    image_extent: self.extent,
+                }
+            ],
+        );
+        
+        command_buffer.pipeline_barrier(
+            PipelineStage::TRANSFER..PipelineStage::HOST,
+            Dependencies::empty(),
+            &[
+                Barrier::AllBuffers(buffer::Access::TRANSFER_WRITE..buffer::Access::HOST_READ)
+            ],
+        );
+    }
+}
+
+impl Drop for OffscreenTargetState {
+    fn drop(&mut self) {
+        let device = &self.device_state.borrow().device;
+        unsafe {
+            if let Some(frame_buffer) = self.frame_buffer.take() {
+                device.destroy_framebuffer(frame_buffer);
+            }
+        }
+    }
+}
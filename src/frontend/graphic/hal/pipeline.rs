@@ -4,34 +4,149 @@ use std::{
 };
 use std::borrow::Borrow;
 use std::iter;
+use std::mem;
 
-use gfs::{GemFileSystem, ReadFile};
+use gfs::GemFileSystem;
 
 use crate::frontend::graphic::data_type::*;
 
 use super::{
     descriptor::DescriptorState,
     device::DeviceState,
+    error::{
+        RendererError,
+        RendererResult,
+    },
     prelude::*,
+    reflection::{
+        PipelineReflection,
+        ShaderReflection,
+    },
     render_pass::RenderPassState,
-    shader_module::ShaderModuleState,
+    shader_module::{
+        ShaderCache,
+        ShaderModuleState,
+    },
 };
 
+/// the set holding the uniform blocks of object.vert and object.frag
+pub const UNIFORM_SET: u32 = 0;
+/// the binding of VertUniformBlock in UNIFORM_SET, written at a dynamic offset every frame
+pub const VERT_UNIFORM_BINDING: u32 = 0;
+/// the binding of FragUniformBlock in UNIFORM_SET
+pub const FRAG_UNIFORM_BINDING: u32 = 1;
+
+/// the SPIR-V under res/ of the variants of object.vert and object.frag a pipeline is made of
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectShaderPaths {
+    pub vertex: String,
+    pub fragment: String,
+}
+
+impl ObjectShaderPaths {
+    pub fn contains(&self, spirv_path: &str) -> bool {
+        spirv_path == self.vertex || spirv_path == self.fragment
+    }
+    
+    fn pipeline_path(&self) -> String {
+        format!("{} + {}", self.vertex, self.fragment)
+    }
+}
+
 pub struct ObjectPso {
     device_state: Rc<RefCell<DeviceState>>,
     pub pipeline: Option<<B as TB>::GraphicsPipeline>,
     pub pipeline_layout: Option<<B as TB>::PipelineLayout>,
     pub pipeline_cache: Option<<B as TB>::PipelineCache>,
+    // the stages the ObjectPushConstants of a draw are visible to, as declared by the shaders
+    pub push_constant_stages: ShaderStageFlags,
 }
 
 impl ObjectPso {
     pub fn new(
         device_state: Rc<RefCell<DeviceState>>,
         render_pass: &<B as TB>::RenderPass,
+        samples: image::NumSamples,
         descriptor_states: Vec<&DescriptorState>,
         gfs: &mut GemFileSystem,
-    ) -> Self
+        shader_cache: &ShaderCache,
+        shader_paths: &ObjectShaderPaths,
+    ) -> RendererResult<Self>
+    {
+        let (vertex_spirv, fragment_spirv) = ObjectPso::read_spirv(gfs, shader_cache, shader_paths)?;
+        ObjectPso::from_spirv(
+            device_state,
+            render_pass,
+            samples,
+            descriptor_states,
+            shader_paths,
+            &vertex_spirv,
+            &fragment_spirv,
+        )
+    }
+    
+    /// the SPIR-V of the shaders at shader_paths
+    pub fn read_spirv(
+        gfs: &mut GemFileSystem,
+        shader_cache: &ShaderCache,
+        shader_paths: &ObjectShaderPaths,
+    ) -> RendererResult<(Box<[u8]>, Box<[u8]>)> {
+        Ok((
+            shader_cache.read(gfs, &shader_paths.vertex)?,
+            shader_cache.read(gfs, &shader_paths.fragment)?,
+        ))
+    }
+    
+    /// the descriptor sets, push constants and vertex inputs the two stages declare
+    pub fn reflect(
+        shader_paths: &ObjectShaderPaths,
+        vertex_spirv: &[u8],
+        fragment_spirv: &[u8],
+    ) -> RendererResult<PipelineReflection> {
+        PipelineReflection::new(
+            &shader_paths.pipeline_path(),
+            &[
+                ShaderReflection::new(&shader_paths.vertex, vertex_spirv)?,
+                ShaderReflection::new(&shader_paths.fragment, fragment_spirv)?,
+            ],
+        )
+    }
+    
+    /// build the pipeline from the SPIR-V of the shaders at shader_paths
+    /// already in memory, so that a broken shader can be rejected at runtime
+    pub fn from_spirv(
+        device_state: Rc<RefCell<DeviceState>>,
+        render_pass: &<B as TB>::RenderPass,
+        samples: image::NumSamples,
+        descriptor_states: Vec<&DescriptorState>,
+        shader_paths: &ObjectShaderPaths,
+        vertex_spirv: &[u8],
+        fragment_spirv: &[u8],
+    ) -> RendererResult<Self>
     {
+        // a shader reading anything the descriptor sets or the vertex buffer do not have
+        // is rejected before any pipeline is made of it
+        let reflection = ObjectPso::reflect(shader_paths, vertex_spirv, fragment_spirv)?;
+        reflection.check_descriptor_states(&descriptor_states)?;
+        let attributes = reflection.vertex_attributes(0, &Vertex::elements())?;
+        let push_constant_stages = match reflection.push_constants() {
+            Some((stages, size)) if size as usize <= mem::size_of::<ObjectPushConstants>() => stages,
+            Some((_, size)) => return Err(RendererError::Shader {
+                path: shader_paths.pipeline_path(),
+                reason: format!(
+                    "the push constant block of {} bytes does not fit ObjectPushConstants",
+                    size,
+                ),
+            }),
+            None => ShaderStageFlags::empty(),
+        };
+        
+        let vertex_shader_module =
+            ShaderModuleState::new(device_state.clone(), &shader_paths.vertex, vertex_spirv)?;
+        
+        let fragment_shader_module =
+            ShaderModuleState::new(device_state.clone(), &shader_paths.fragment, fragment_spirv)?;
+        
         let descriptor_set_layouts: Vec<&<B as TB>::DescriptorSetLayout> =
             descriptor_states.into_iter().filter(|descriptor_state|
                 descriptor_state.descriptor_set_layout.is_some()).map(
@@ -40,27 +155,23 @@ impl ObjectPso {
                 }).collect();
         
         
+        // per-draw data goes through push constants so that every object
+        // can share the pipeline and the descriptor sets,
+        // the whole of ObjectPushConstants is pushed even if the block is smaller
+        let push_constants_size = mem::size_of::<ObjectPushConstants>();
+        assert!(push_constants_size <= MAX_PUSH_CONSTANTS_SIZE);
+        let push_constants = if push_constant_stages.is_empty() {
+            None
+        } else {
+            Some((push_constant_stages, 0..(push_constants_size / 4) as u32))
+        };
         let pipeline_layout = unsafe {
             let device = &device_state.borrow_mut().device;
             device.create_pipeline_layout(
                 descriptor_set_layouts,
-                &[],
+                push_constants,
             )
-        }.unwrap();
-    
-        let vertex_shader_module = {
-            let spirv = gfs
-                .read_file("shaders/gen/object.vert.spv".to_string())
-                .expect("Cannot load shader");
-            ShaderModuleState::new(device_state.clone(), spirv)
-        };
-    
-        let fragment_shader_module = {
-            let spirv = gfs
-                .read_file("shaders/gen/object.frag.spv".to_string())
-                .expect("Cannot load shader");
-            ShaderModuleState::new(device_state.clone(), spirv)
-        };
+        }.map_err(|error| RendererError::creation("object pipeline layout", error))?;
         
         let pipeline = unsafe {
             let device = &device_state.borrow_mut().device;
@@ -115,46 +226,7 @@ impl ObjectPso {
                 }
             );
             
-            pipeline_desc.attributes.push(
-                AttributeDesc {
-                    location: 0,
-                    binding: 0,
-                    element: Element {
-                        format: Format::Rgb32Sfloat,
-                        offset: 0,
-                    },
-                }
-            );
-            pipeline_desc.attributes.push(
-                AttributeDesc {
-                    location: 1,
-                    binding: 0,
-                    element: Element {
-                        format: Format::Rgb32Sfloat,
-                        offset: 12,
-                    },
-                }
-            );
-            pipeline_desc.attributes.push(
-                AttributeDesc {
-                    location: 2,
-                    binding: 0,
-                    element: Element {
-                        format: Format::Rgb32Sfloat,
-                        offset: 24,
-                    },
-                }
-            );
-            pipeline_desc.attributes.push(
-                AttributeDesc {
-                    location: 3,
-                    binding: 0,
-                    element: Element {
-                        format: Format::Rg32Sfloat,
-                        offset: 36,
-                    },
-                }
-            );
+            pipeline_desc.attributes = attributes;
     
             pipeline_desc.depth_stencil = DepthStencilDesc {
                 depth: DepthTest::On {
@@ -164,16 +236,41 @@ impl ObjectPso {
                 depth_bounds: false,
                 stencil: StencilTest::default(),
             };
+            
+            // has to match the sample count of the attachments of the render pass
+            if samples > 1 {
+                pipeline_desc.multisampling = Some(pso::Multisampling {
+                    rasterization_samples: samples,
+                    sample_shading: None,
+                    sample_mask: !0,
+                    alpha_coverage: false,
+                    alpha_to_one: false,
+                });
+            }
     
             device.create_graphics_pipeline(&pipeline_desc, None)
-        }.unwrap();
+        };
         
-        ObjectPso {
+        let pipeline = match pipeline {
+            Ok(pipeline) => pipeline,
+            Err(error) => {
+                unsafe {
+                    device_state.borrow().device.destroy_pipeline_layout(pipeline_layout);
+                }
+                return Err(RendererError::Shader {
+                    path: shader_paths.pipeline_path(),
+                    reason: format!("cannot create graphics pipeline: {:?}", error),
+                });
+            }
+        };
+        
+        Ok(ObjectPso {
             device_state,
             pipeline: Some(pipeline),
             pipeline_layout: Some(pipeline_layout),
             pipeline_cache: None,
-        }
+            push_constant_stages,
+        })
     }
 }
 
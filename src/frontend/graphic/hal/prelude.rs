@@ -6,6 +6,7 @@ pub use gfx_hal::{
     Backend as TB,
     buffer,
     command::{
+        BufferCopy,
         BufferImageCopy,
         ClearColor,
         ClearDepthStencil,
@@ -17,7 +18,7 @@ pub use gfx_hal::{
     CommandPool,
     DescriptorPool,
     Device,
-    format::{Aspects, ChannelType, Format, Swizzle},
+    format::{Aspects, ChannelType, Format, ImageFeature, Swizzle},
     FrameSync,
     Graphics,
     image::{
@@ -34,6 +35,7 @@ pub use gfx_hal::{
     },
     PhysicalDevice,
     pool::CommandPoolCreateFlags,
+    PresentMode,
     Primitive,
     pso::{self,
           AttributeDesc, BlendState, ColorBlendDesc, ColorMask, Comparison, DepthStencilDesc,
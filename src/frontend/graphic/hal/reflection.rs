@@ -0,0 +1,603 @@
+use std::collections::HashMap;
+
+use spirv_cross::{
+    ErrorCode,
+    glsl,
+    spirv::{
+        self,
+        Decoration,
+        ExecutionModel,
+        Resource,
+    },
+};
+
+use super::{
+    descriptor::DescriptorState,
+    error::{
+        RendererError,
+        RendererResult,
+    },
+    prelude::*,
+};
+
+const SPIRV_MAGIC: u32 = 0x0723_0203;
+
+// the opcodes of the SPIR-V specification the types of vertex inputs are read from,
+// which spirv_cross does not tell the component count of
+const OP_TYPE_INT: u32 = 21;
+const OP_TYPE_FLOAT: u32 = 22;
+const OP_TYPE_VECTOR: u32 = 23;
+
+/// a descriptor a shader reads
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReflectedBinding {
+    pub set: u32,
+    pub binding: u32,
+    pub ty: DescriptorType,
+    // more than 1 for arrays of descriptors
+    pub count: usize,
+}
+
+/// an attribute a vertex shader reads
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReflectedVertexInput {
+    pub location: u32,
+    pub format: Format,
+    // the name of the variable in the shader, empty if it was stripped
+    pub name: String,
+}
+
+/// what a single shader stage declares about its interface
+#[derive(Debug, Clone)]
+pub struct ShaderReflection {
+    pub stage: ShaderStageFlags,
+    pub bindings: Vec<ReflectedBinding>,
+    // in bytes, 0 if the stage has no push constant block
+    pub push_constants_size: u32,
+    // sorted by location, empty unless stage is VERTEX
+    pub vertex_inputs: Vec<ReflectedVertexInput>,
+}
+
+impl ShaderReflection {
+    /// read the interface of the one entry point of spirv, path only names the shader in errors
+    pub fn new(path: &str, spirv: &[u8]) -> RendererResult<ShaderReflection> {
+        spirv_words(spirv)
+            .and_then(|words| reflect(&words))
+            .map_err(|reason| RendererError::Shader {
+                path: path.to_string(),
+                reason: format!("cannot reflect SPIR-V: {}", reason),
+            })
+    }
+}
+
+/// the interface of the stages of a pipeline together,
+/// from which its descriptor set layouts, push constant range and vertex attributes are made
+#[derive(Debug, Clone)]
+pub struct PipelineReflection {
+    // names the pipeline in errors
+    path: String,
+    // every binding once, with the stages reading it
+    bindings: Vec<(ReflectedBinding, ShaderStageFlags)>,
+    // the stages declaring a push constant block and the size of the largest one in bytes
+    push_constants: Option<(ShaderStageFlags, u32)>,
+    vertex_inputs: Vec<ReflectedVertexInput>,
+}
+
+impl PipelineReflection {
+    /// path only names the pipeline in errors,
+    /// the stages have to agree on the type of every binding they share
+    pub fn new(
+        path: &str,
+        shader_reflections: &[ShaderReflection],
+    ) -> RendererResult<PipelineReflection> {
+        let mut bindings: Vec<(ReflectedBinding, ShaderStageFlags)> = vec![];
+        let mut push_constants: Option<(ShaderStageFlags, u32)> = None;
+        let mut vertex_inputs = vec![];
+        for shader_reflection in shader_reflections {
+            for binding in &shader_reflection.bindings {
+                let existing = bindings
+                    .iter_mut()
+                    .find(|(existing, _)| existing.set == binding.set && existing.binding == binding.binding);
+                match existing {
+                    Some((existing, stages)) => {
+                        if existing != binding {
+                            return Err(RendererError::Shader {
+                                path: path.to_string(),
+                                reason: format!(
+                                    "set {} binding {} is a {:?} in one stage and a {:?} in another",
+                                    binding.set,
+                                    binding.binding,
+                                    existing.ty,
+                                    binding.ty,
+                                ),
+                            });
+                        }
+                        *stages |= shader_reflection.stage;
+                    }
+                    None => bindings.push((binding.clone(), shader_reflection.stage)),
+                }
+            }
+            if shader_reflection.push_constants_size > 0 {
+                push_constants = Some(match push_constants {
+                    Some((stages, size)) => (
+                        stages | shader_reflection.stage,
+                        size.max(shader_reflection.push_constants_size),
+                    ),
+                    None => (shader_reflection.stage, shader_reflection.push_constants_size),
+                });
+            }
+            vertex_inputs.extend(shader_reflection.vertex_inputs.iter().cloned());
+        }
+        bindings.sort_by_key(|(binding, _)| (binding.set, binding.binding));
+        Ok(PipelineReflection {
+            path: path.to_string(),
+            bindings,
+            push_constants,
+            vertex_inputs,
+        })
+    }
+    
+    /// the bindings of a layout for set, the uniform buffers at dynamic_bindings
+    /// are made dynamic since that cannot be told from the shader
+    pub fn set_layout_bindings(
+        &self,
+        set: u32,
+        dynamic_bindings: &[u32],
+    ) -> Vec<DescriptorSetLayoutBinding> {
+        self.bindings
+            .iter()
+            .filter(|(binding, _)| binding.set == set)
+            .map(|(binding, stages)| DescriptorSetLayoutBinding {
+                binding: binding.binding,
+                ty: match binding.ty {
+                    DescriptorType::UniformBuffer if dynamic_bindings.contains(&binding.binding) =>
+                        DescriptorType::UniformBufferDynamic,
+                    DescriptorType::StorageBuffer if dynamic_bindings.contains(&binding.binding) =>
+                        DescriptorType::StorageBufferDynamic,
+                    ty => ty,
+                },
+                count: binding.count,
+                stage_flags: *stages,
+                immutable_samplers: false,
+            })
+            .collect()
+    }
+    
+    /// the stages declaring a push constant block and the size of the largest one in bytes
+    pub fn push_constants(&self) -> Option<(ShaderStageFlags, u32)> {
+        self.push_constants
+    }
+    
+    /// the attributes of the vertex inputs read from vertex buffer binding,
+    /// elements are the fields of a vertex with the location each is read from,
+    /// it is an error for the shader to read a location the vertex does not have
+    /// or to read it as another format
+    pub fn vertex_attributes(
+        &self,
+        binding: u32,
+        elements: &[(u32, Element<Format>)],
+    ) -> RendererResult<Vec<AttributeDesc>> {
+        self.vertex_inputs
+            .iter()
+            .map(|input| {
+                let element = elements
+                    .iter()
+                    .find(|(location, _)| *location == input.location)
+                    .map(|(_, element)| element)
+                    .ok_or_else(|| RendererError::Shader {
+                        path: self.path.clone(),
+                        reason: format!(
+                            "reads {} from location {} but the vertex has no field there",
+                            input.name,
+                            input.location,
+                        ),
+                    })?;
+                if element.format != input.format {
+                    return Err(RendererError::Shader {
+                        path: self.path.clone(),
+                        reason: format!(
+                            "reads {} from location {} as {:?} but the vertex has {:?} there",
+                            input.name,
+                            input.location,
+                            input.format,
+                            element.format,
+                        ),
+                    });
+                }
+                Ok(AttributeDesc {
+                    location: input.location,
+                    binding,
+                    element: *element,
+                })
+            })
+            .collect()
+    }
+    
+    /// check that descriptor_states[set] is a layout every binding of set can be read through,
+    /// so that a pipeline reloaded at runtime still fits the descriptor sets already written
+    pub fn check_descriptor_states(&self, descriptor_states: &[&DescriptorState]) -> RendererResult<()> {
+        for (binding, stages) in &self.bindings {
+            let layout_binding = descriptor_states
+                .get(binding.set as usize)
+                .and_then(|descriptor_state| descriptor_state
+                    .bindings()
+                    .iter()
+                    .find(|layout_binding| layout_binding.binding == binding.binding));
+            let fits = match layout_binding {
+                Some(layout_binding) => {
+                    let same_type = match (binding.ty, layout_binding.ty) {
+                        (DescriptorType::UniformBuffer, DescriptorType::UniformBufferDynamic) |
+                        (DescriptorType::StorageBuffer, DescriptorType::StorageBufferDynamic) => true,
+                        (ty, layout_ty) => ty == layout_ty,
+                    };
+                    same_type &&
+                        layout_binding.count >= binding.count &&
+                        layout_binding.stage_flags.contains(*stages)
+                }
+                None => false,
+            };
+            if !fits {
+                return Err(RendererError::Shader {
+                    path: self.path.clone(),
+                    reason: format!(
+                        "set {} binding {} is read as {} {:?} by {:?}, which its layout does not provide",
+                        binding.set,
+                        binding.binding,
+                        binding.count,
+                        binding.ty,
+                        stages,
+                    ),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// the words of a SPIR-V module in the byte order of the host
+fn spirv_words(spirv: &[u8]) -> Result<Vec<u32>, String> {
+    if spirv.len() % 4 != 0 {
+        return Err(format!("{} bytes is not a whole number of words", spirv.len()));
+    }
+    let mut words: Vec<u32> = spirv
+        .chunks(4)
+        .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .collect();
+    match words.first() {
+        Some(&SPIRV_MAGIC) => (),
+        Some(magic) if magic.swap_bytes() == SPIRV_MAGIC => {
+            for word in words.iter_mut() {
+                *word = word.swap_bytes();
+            }
+        }
+        _ => return Err("missing the SPIR-V magic number".to_string()),
+    }
+    if words.len() < 5 {
+        return Err("truncated header".to_string());
+    }
+    Ok(words)
+}
+
+fn reflect(words: &[u32]) -> Result<ShaderReflection, String> {
+    let module = spirv::Module::from_words(words);
+    let ast = spirv::Ast::<glsl::Target>::parse(&module).map_err(cross_error)?;
+    
+    let entry_points = ast.get_entry_points().map_err(cross_error)?;
+    let stage = match entry_points.as_slice() {
+        [entry_point] => match entry_point.execution_model {
+            ExecutionModel::Vertex => ShaderStageFlags::VERTEX,
+            ExecutionModel::TessellationControl => ShaderStageFlags::HULL,
+            ExecutionModel::TessellationEvaluation => ShaderStageFlags::DOMAIN,
+            ExecutionModel::Geometry => ShaderStageFlags::GEOMETRY,
+            ExecutionModel::Fragment => ShaderStageFlags::FRAGMENT,
+            ExecutionModel::GlCompute => ShaderStageFlags::COMPUTE,
+            ExecutionModel::Kernel => return Err("unsupported execution model".to_string()),
+        },
+        [] => return Err("no entry point".to_string()),
+        _ => return Err("more than one entry point".to_string()),
+    };
+    
+    let resources = ast.get_shader_resources().map_err(cross_error)?;
+    let descriptor_resources = [
+        (&resources.uniform_buffers, DescriptorType::UniformBuffer),
+        (&resources.storage_buffers, DescriptorType::StorageBuffer),
+        (&resources.sampled_images, DescriptorType::CombinedImageSampler),
+        (&resources.separate_images, DescriptorType::SampledImage),
+        (&resources.separate_samplers, DescriptorType::Sampler),
+        (&resources.storage_images, DescriptorType::StorageImage),
+        (&resources.subpass_inputs, DescriptorType::InputAttachment),
+    ];
+    let mut bindings = vec![];
+    for (resources, ty) in descriptor_resources.iter() {
+        for resource in resources.iter() {
+            bindings.push(ReflectedBinding {
+                set: ast.get_decoration(resource.id, Decoration::DescriptorSet).map_err(cross_error)?,
+                binding: ast.get_decoration(resource.id, Decoration::Binding).map_err(cross_error)?,
+                ty: *ty,
+                count: descriptor_count(&ast, resource)?,
+            });
+        }
+    }
+    
+    let mut push_constants_size = 0;
+    for resource in resources.push_constant_buffers.iter() {
+        let size = ast.get_declared_struct_size(resource.base_type_id).map_err(cross_error)?;
+        push_constants_size = push_constants_size.max(size);
+    }
+    
+    // built-ins such as gl_VertexIndex are not among the stage inputs
+    let mut vertex_inputs = vec![];
+    if stage == ShaderStageFlags::VERTEX {
+        let scalar_types = ScalarTypes::parse(words)?;
+        for input in resources.stage_inputs.iter() {
+            vertex_inputs.push(ReflectedVertexInput {
+                location: ast.get_decoration(input.id, Decoration::Location).map_err(cross_error)?,
+                format: scalar_types.vertex_format(input.base_type_id)?,
+                name: input.name.clone(),
+            });
+        }
+    }
+    
+    bindings.sort_by_key(|binding| (binding.set, binding.binding));
+    vertex_inputs.sort_by_key(|input| input.location);
+    Ok(ShaderReflection {
+        stage,
+        bindings,
+        push_constants_size,
+        vertex_inputs,
+    })
+}
+
+/// the number of descriptors bound for resource, the product of its array lengths
+fn descriptor_count(ast: &spirv::Ast<glsl::Target>, resource: &Resource) -> Result<usize, String> {
+    let array = match ast.get_type(resource.type_id).map_err(cross_error)? {
+        spirv::Type::Struct { array, .. } |
+        spirv::Type::Image { array } |
+        spirv::Type::SampledImage { array } |
+        spirv::Type::Sampler { array } => array,
+        ty => return Err(format!(
+            "{} of type {:?} cannot be bound through a descriptor",
+            resource.name,
+            ty,
+        )),
+    };
+    // unsized arrays have a length of 0
+    if array.contains(&0) {
+        return Err(format!("{} is an array of unknown length", resource.name));
+    }
+    Ok(array.iter().product::<u32>() as usize)
+}
+
+fn cross_error(error: ErrorCode) -> String {
+    match error {
+        ErrorCode::Unhandled => "spirv_cross cannot handle the module".to_string(),
+        ErrorCode::CompilationError(message) => message,
+    }
+}
+
+/// the scalar and vector types of a module, spirv_cross only tells their component type
+enum ScalarType {
+    Int { width: u32, signed: bool },
+    Float { width: u32 },
+    Vector { component: u32, count: u32 },
+}
+
+struct ScalarTypes {
+    types: HashMap<u32, ScalarType>,
+}
+
+impl ScalarTypes {
+    fn parse(words: &[u32]) -> Result<ScalarTypes, String> {
+        let mut types = HashMap::new();
+        let mut position = 5;
+        while position < words.len() {
+            let word_count = (words[position] >> 16) as usize;
+            let opcode = words[position] & 0xffff;
+            if word_count == 0 || position + word_count > words.len() {
+                return Err(format!("malformed instruction at word {}", position));
+            }
+            let operands = &words[position + 1..position + word_count];
+            let operand = |index: usize| operands
+                .get(index)
+                .cloned()
+                .ok_or_else(|| format!("instruction {} at word {} is too short", opcode, position));
+            match opcode {
+                OP_TYPE_INT => {
+                    types.insert(operand(0)?, ScalarType::Int {
+                        width: operand(1)?,
+                        signed: operand(2)? != 0,
+                    });
+                }
+                OP_TYPE_FLOAT => {
+                    types.insert(operand(0)?, ScalarType::Float { width: operand(1)? });
+                }
+                OP_TYPE_VECTOR => {
+                    types.insert(operand(0)?, ScalarType::Vector {
+                        component: operand(1)?,
+                        count: operand(2)?,
+                    });
+                }
+                _ => (),
+            }
+            position += word_count;
+        }
+        Ok(ScalarTypes {
+            types,
+        })
+    }
+    
+    fn vertex_format(&self, type_id: u32) -> Result<Format, String> {
+        let (component, count) = match self.types.get(&type_id) {
+            Some(ScalarType::Vector { component, count }) => (*component, *count),
+            _ => (type_id, 1),
+        };
+        let format = match (self.types.get(&component), count) {
+            (Some(ScalarType::Float { width: 32 }), 1) => Format::R32Sfloat,
+            (Some(ScalarType::Float { width: 32 }), 2) => Format::Rg32Sfloat,
+            (Some(ScalarType::Float { width: 32 }), 3) => Format::Rgb32Sfloat,
+            (Some(ScalarType::Float { width: 32 }), 4) => Format::Rgba32Sfloat,
+            (Some(ScalarType::Int { width: 32, signed: true }), 1) => Format::R32Sint,
+            (Some(ScalarType::Int { width: 32, signed: true }), 2) => Format::Rg32Sint,
+            (Some(ScalarType::Int { width: 32, signed: true }), 3) => Format::Rgb32Sint,
+            (Some(ScalarType::Int { width: 32, signed: true }), 4) => Format::Rgba32Sint,
+            (Some(ScalarType::Int { width: 32, signed: false }), 1) => Format::R32Uint,
+            (Some(ScalarType::Int { width: 32, signed: false }), 2) => Format::Rg32Uint,
+            (Some(ScalarType::Int { width: 32, signed: false }), 3) => Format::Rgb32Uint,
+            (Some(ScalarType::Int { width: 32, signed: false }), 4) => Format::Rgba32Uint,
+            _ => return Err(format!("vertex input type {} is not a 32 bit scalar or vector", type_id)),
+        };
+        Ok(format)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        mem,
+    };
+    
+    use crate::frontend::graphic::data_type::{
+        ObjectPushConstants,
+        Vertex,
+    };
+    use crate::frontend::graphic::hal::pipeline::{
+        FRAG_UNIFORM_BINDING,
+        UNIFORM_SET,
+        VERT_UNIFORM_BINDING,
+    };
+    use crate::frontend::graphic::shader_variant::{
+        compiled_path,
+        OBJECT_FRAGMENT_SOURCE_PATH,
+        OBJECT_VERTEX_SOURCE_PATH,
+    };
+    
+    use super::*;
+    
+    const ALL_MAPS: &[&str] = &["HAS_NORMALMAP", "HAS_BASECOLORMAP", "HAS_SPECULARMAP"];
+    
+    // build.rs compiles the shaders under res/ before the tests are built
+    fn reflect_shader(path: &str) -> ShaderReflection {
+        let spirv = fs::read(format!("res/{}", path)).unwrap();
+        ShaderReflection::new(path, &spirv).unwrap()
+    }
+    
+    fn reflect_object_vertex() -> ShaderReflection {
+        reflect_shader(&compiled_path(OBJECT_VERTEX_SOURCE_PATH, &[]))
+    }
+    
+    fn reflect_object_fragment(maps: &[&str]) -> ShaderReflection {
+        let defines: Vec<String> = maps.iter().map(|define| define.to_string()).collect();
+        reflect_shader(&compiled_path(OBJECT_FRAGMENT_SOURCE_PATH, &defines))
+    }
+    
+    fn binding(set: u32, binding: u32, ty: DescriptorType) -> ReflectedBinding {
+        ReflectedBinding {
+            set,
+            binding,
+            ty,
+            count: 1,
+        }
+    }
+    
+    #[test]
+    fn object_vertex_shader() {
+        let reflection = reflect_object_vertex();
+        assert_eq!(reflection.stage, ShaderStageFlags::VERTEX);
+        assert_eq!(
+            reflection.bindings,
+            vec![binding(UNIFORM_SET, VERT_UNIFORM_BINDING, DescriptorType::UniformBuffer)]
+        );
+        // mat4, mat3 with a column stride of 16 and uint
+        assert_eq!(reflection.push_constants_size, 64 + 48 + 4);
+        let inputs: Vec<_> = reflection.vertex_inputs
+            .iter()
+            .map(|input| (input.location, input.format, input.name.as_str()))
+            .collect();
+        assert_eq!(inputs, vec![
+            (0, Format::Rgb32Sfloat, "vPosition"),
+            (1, Format::Rgb32Sfloat, "vNormal"),
+            (2, Format::Rgb32Sfloat, "vTangent"),
+            (3, Format::Rg32Sfloat, "vTexCoord"),
+        ]);
+    }
+    
+    #[test]
+    fn object_fragment_shader() {
+        let reflection = reflect_object_fragment(ALL_MAPS);
+        assert_eq!(reflection.stage, ShaderStageFlags::FRAGMENT);
+        assert_eq!(reflection.bindings, vec![
+            binding(UNIFORM_SET, FRAG_UNIFORM_BINDING, DescriptorType::UniformBuffer),
+            binding(1, 0, DescriptorType::CombinedImageSampler),
+            binding(2, 0, DescriptorType::CombinedImageSampler),
+            binding(3, 0, DescriptorType::CombinedImageSampler),
+        ]);
+        assert_eq!(reflection.push_constants_size, 0);
+        assert!(reflection.vertex_inputs.is_empty());
+    }
+    
+    #[test]
+    fn object_fragment_shader_reads_only_the_maps_of_its_variant() {
+        let reflection = reflect_object_fragment(&[]);
+        assert_eq!(reflection.bindings, vec![
+            binding(UNIFORM_SET, FRAG_UNIFORM_BINDING, DescriptorType::UniformBuffer),
+        ]);
+        
+        let reflection = reflect_object_fragment(&["HAS_BASECOLORMAP"]);
+        assert_eq!(reflection.bindings, vec![
+            binding(UNIFORM_SET, FRAG_UNIFORM_BINDING, DescriptorType::UniformBuffer),
+            binding(2, 0, DescriptorType::CombinedImageSampler),
+        ]);
+    }
+    
+    #[test]
+    fn object_pipeline_layout() {
+        let reflection = PipelineReflection::new(
+            "object",
+            &[reflect_object_vertex(), reflect_object_fragment(ALL_MAPS)],
+        ).unwrap();
+        
+        let uniform_bindings: Vec<_> = reflection
+            .set_layout_bindings(UNIFORM_SET, &[VERT_UNIFORM_BINDING])
+            .into_iter()
+            .map(|binding| (binding.binding, binding.ty, binding.stage_flags))
+            .collect();
+        assert_eq!(uniform_bindings, vec![
+            (VERT_UNIFORM_BINDING, DescriptorType::UniformBufferDynamic, ShaderStageFlags::VERTEX),
+            (FRAG_UNIFORM_BINDING, DescriptorType::UniformBuffer, ShaderStageFlags::FRAGMENT),
+        ]);
+        
+        let (stages, size) = reflection.push_constants().unwrap();
+        assert_eq!(stages, ShaderStageFlags::VERTEX);
+        assert!(size as usize <= mem::size_of::<ObjectPushConstants>());
+    }
+    
+    #[test]
+    fn object_vertex_inputs_match_vertex_fields() {
+        let reflection = PipelineReflection::new(
+            "object",
+            &[reflect_object_vertex()],
+        ).unwrap();
+        let elements = Vertex::elements();
+        let attributes = reflection.vertex_attributes(0, &elements).unwrap();
+        assert_eq!(attributes.len(), elements.len());
+        for (attribute, (location, element)) in attributes.iter().zip(elements.iter()) {
+            assert_eq!(attribute.location, *location);
+            assert_eq!(attribute.element, *element);
+        }
+        
+        // a vertex without texture coordinates cannot be drawn with object.vert
+        let without_texture: Vec<_> = elements
+            .iter()
+            .cloned()
+            .filter(|(location, _)| *location != Vertex::TEXTURE_LOCATION)
+            .collect();
+        assert!(reflection.vertex_attributes(0, &without_texture).is_err());
+    }
+    
+    #[test]
+    fn rejects_what_is_not_spirv() {
+        assert!(ShaderReflection::new("empty", &[]).is_err());
+        assert!(ShaderReflection::new("text", b"#version 450").is_err());
+    }
+}
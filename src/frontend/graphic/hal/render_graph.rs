@@ -0,0 +1,749 @@
+use std::{
+    cell::RefCell,
+    iter,
+    rc::Rc,
+};
+
+use crate::frontend::graphic::constants::COLOR_RANGE;
+
+use super::{
+    device::DeviceState,
+    error::{
+        RendererError,
+        RendererResult,
+    },
+    image::ImageState,
+    prelude::*,
+};
+
+/// an image written by one pass of a render graph and sampled by any number of later ones
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ResourceId(usize);
+
+/// a pass added to a render graph, handed back when it is its turn to be recorded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PassId(usize);
+
+/// an image only needed while a frame is recorded, created by the graph,
+/// which backs resources whose passes do not overlap with the same image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransientDesc {
+    pub format: Format,
+    pub samples: image::NumSamples,
+    // the image is the size of the output halved this many times
+    pub downscale: u32,
+}
+
+/// the attachments a pass renders to, in the order of the attachments of RenderPassState
+/// so that pipelines created for a RenderPassState of the same formats can be used in the pass,
+/// and the images its fragment shaders sample
+pub struct PassDesc {
+    pub sampled: Vec<ResourceId>,
+    pub color: ResourceId,
+    pub depth: Option<ResourceId>,
+    // the single sampled image a multisampled color attachment is resolved to
+    pub resolve: Option<ResourceId>,
+}
+
+impl PassDesc {
+    fn written(&self) -> impl Iterator<Item = ResourceId> {
+        iter::once(self.color).chain(self.depth).chain(self.resolve)
+    }
+}
+
+enum ResourceDecl {
+    Transient(TransientDesc),
+    // the image the frame ends up in, given when recording
+    Output,
+}
+
+/// the passes of a frame and the resources they use, compiled by RenderGraphState::new
+pub struct RenderGraphBuilder {
+    resources: Vec<ResourceDecl>,
+    passes: Vec<PassDesc>,
+    output_format: Format,
+    output_layout: Layout,
+}
+
+impl RenderGraphBuilder {
+    /// output_layout is the layout the output image is left in
+    pub fn new(output_format: Format, output_layout: Layout) -> RenderGraphBuilder {
+        RenderGraphBuilder {
+            resources: vec![ResourceDecl::Output],
+            passes: vec![],
+            output_format,
+            output_layout,
+        }
+    }
+    
+    /// the image the frame ends up in, the pass writing it cannot have any other attachment
+    pub fn output(&self) -> ResourceId {
+        ResourceId(0)
+    }
+    
+    pub fn create_transient(&mut self, transient_desc: TransientDesc) -> ResourceId {
+        self.resources.push(ResourceDecl::Transient(transient_desc));
+        ResourceId(self.resources.len() - 1)
+    }
+    
+    /// passes are recorded after the passes writing what they sample
+    /// and otherwise in the order they are added in,
+    /// passes the output does not depend on are left out
+    pub fn add_pass(&mut self, pass_desc: PassDesc) -> PassId {
+        self.passes.push(pass_desc);
+        PassId(self.passes.len() - 1)
+    }
+    
+    /// the passes to record, in order
+    fn order(&self) -> RendererResult<Vec<usize>> {
+        // every resource is written by exactly one pass
+        let mut writers: Vec<Option<usize>> = vec![None; self.resources.len()];
+        for (pass_index, pass) in self.passes.iter().enumerate() {
+            for resource in pass.written() {
+                if writers[resource.0].is_some() {
+                    return Err(graph_error(format!("{:?} is written by more than one pass", resource)));
+                }
+                writers[resource.0] = Some(pass_index);
+            }
+        }
+        let dependencies = self.passes
+            .iter()
+            .map(|pass| pass.sampled
+                .iter()
+                .map(|resource| writers[resource.0]
+                    .ok_or_else(|| graph_error(format!("{:?} is sampled but never written", resource))))
+                .collect::<RendererResult<Vec<_>>>())
+            .collect::<RendererResult<Vec<_>>>()?;
+        
+        let output_writer = writers[self.output().0]
+            .ok_or_else(|| graph_error("nothing is written to the output"))?;
+        let mut needed = vec![false; self.passes.len()];
+        let mut unvisited = vec![output_writer];
+        while let Some(pass_index) = unvisited.pop() {
+            if !needed[pass_index] {
+                needed[pass_index] = true;
+                unvisited.extend(dependencies[pass_index].iter().cloned());
+            }
+        }
+        
+        let mut ordered = vec![false; self.passes.len()];
+        let mut order = vec![];
+        let needed_count = needed.iter().filter(|needed| **needed).count();
+        while order.len() < needed_count {
+            let next = (0..self.passes.len()).find(|pass_index| {
+                needed[*pass_index] && !ordered[*pass_index] &&
+                    dependencies[*pass_index].iter().all(|dependency| ordered[*dependency])
+            });
+            match next {
+                Some(pass_index) => {
+                    ordered[pass_index] = true;
+                    order.push(pass_index);
+                }
+                None => return Err(graph_error("the passes depend on each other in a cycle")),
+            }
+        }
+        Ok(order)
+    }
+    
+    fn transient_desc(&self, resource: ResourceId) -> Option<TransientDesc> {
+        match self.resources[resource.0] {
+            ResourceDecl::Transient(transient_desc) => Some(transient_desc),
+            ResourceDecl::Output => None,
+        }
+    }
+    
+    /// the images backing the transient resources of the passes in order,
+    /// a resource takes the image of one whose last use has passed
+    /// if it is created with the same description
+    fn plan_images(&self, order: &[usize]) -> RendererResult<ImagePlan> {
+        let resource_count = self.resources.len();
+        
+        // the position in order of the last pass using every resource
+        // and what every transient resource is used as
+        let mut last_uses = vec![0; resource_count];
+        let mut sampled = vec![false; resource_count];
+        let mut depth = vec![false; resource_count];
+        for (position, pass) in order.iter().map(|pass_index| &self.passes[*pass_index]).enumerate() {
+            for resource in pass.written().chain(pass.sampled.iter().cloned()) {
+                last_uses[resource.0] = position;
+            }
+            for resource in pass.sampled.iter() {
+                sampled[resource.0] = true;
+            }
+            if let Some(resource) = pass.depth {
+                depth[resource.0] = true;
+            }
+        }
+        let usage = |resource: ResourceId| {
+            let attachment_usage = if depth[resource.0] {
+                image::Usage::DEPTH_STENCIL_ATTACHMENT
+            } else {
+                image::Usage::COLOR_ATTACHMENT
+            };
+            if sampled[resource.0] {
+                attachment_usage | image::Usage::SAMPLED
+            } else {
+                attachment_usage | image::Usage::TRANSIENT_ATTACHMENT
+            }
+        };
+        
+        let mut image_descs: Vec<(TransientDesc, image::Usage)> = vec![];
+        let mut free_images: Vec<usize> = vec![];
+        let mut resource_images: Vec<Option<usize>> = vec![None; resource_count];
+        for (position, pass) in order.iter().map(|pass_index| &self.passes[*pass_index]).enumerate() {
+            if pass.sampled.contains(&self.output()) {
+                return Err(graph_error("the output cannot be sampled"));
+            }
+            for resource in pass.written() {
+                let transient_desc = match self.transient_desc(resource) {
+                    Some(transient_desc) => transient_desc,
+                    None => continue,
+                };
+                if sampled[resource.0] && transient_desc.samples > 1 {
+                    return Err(graph_error(format!("{:?} is multisampled and cannot be sampled", resource)));
+                }
+                let image_desc = (transient_desc, usage(resource));
+                let free_image = free_images
+                    .iter()
+                    .position(|image_index| image_descs[*image_index] == image_desc);
+                resource_images[resource.0] = Some(match free_image {
+                    Some(free_index) => free_images.remove(free_index),
+                    None => {
+                        image_descs.push(image_desc);
+                        image_descs.len() - 1
+                    }
+                });
+            }
+            for resource in pass.written().chain(pass.sampled.iter().cloned()) {
+                if last_uses[resource.0] == position {
+                    free_images.extend(resource_images[resource.0]);
+                }
+            }
+        }
+        
+        Ok(ImagePlan {
+            sampled,
+            image_descs,
+            resource_images,
+        })
+    }
+}
+
+/// the images a RenderGraphState creates for the transient resources
+struct ImagePlan {
+    // whether every resource is sampled by a pass
+    sampled: Vec<bool>,
+    // the description and usage of every image
+    image_descs: Vec<(TransientDesc, image::Usage)>,
+    // the image backing every resource, none for the output and for the resources of passes left out
+    resource_images: Vec<Option<usize>>,
+}
+
+/// what a pass is recorded with, it begins and ends the render pass itself
+pub struct PassContext<'a> {
+    pub render_pass: &'a <B as TB>::RenderPass,
+    pub frame_buffer: &'a <B as TB>::Framebuffer,
+    pub viewport: Viewport,
+    pub clear_values: &'a [ClearValue],
+}
+
+struct CompiledPass {
+    id: PassId,
+    render_pass: Option<<B as TB>::RenderPass>,
+    // none for the pass writing the output, whose frame buffer is given when recording
+    frame_buffer: Option<<B as TB>::Framebuffer>,
+    extent: Extent2D,
+    clear_values: Vec<ClearValue>,
+    // the images written by earlier passes and sampled by this one
+    sampled_images: Vec<usize>,
+}
+
+/// render passes, frame buffers and transient images derived from a RenderGraphBuilder,
+/// each pass clears what it renders to and leaves every image it writes in the layout
+/// of its next use, images sampled by a pass get a barrier right before it
+pub struct RenderGraphState {
+    device_state: Rc<RefCell<DeviceState>>,
+    // in the order they are recorded in
+    passes: Vec<CompiledPass>,
+    // the image backing every transient resource,
+    // none for the output and for the resources of passes left out
+    resource_images: Vec<Option<usize>>,
+    image_states: Vec<ImageState>,
+}
+
+impl RenderGraphState {
+    pub fn new(
+        device_state: Rc<RefCell<DeviceState>>,
+        builder: RenderGraphBuilder,
+        output_extent: Extent2D,
+    ) -> RendererResult<RenderGraphState> {
+        let order = builder.order()?;
+        let ImagePlan { sampled, image_descs, resource_images } = builder.plan_images(&order)?;
+        
+        // whatever has been created when something fails is destroyed by dropping render_graph_state
+        let mut render_graph_state = RenderGraphState {
+            device_state: device_state.clone(),
+            passes: vec![],
+            resource_images,
+            image_states: vec![],
+        };
+        
+        for (transient_desc, usage) in image_descs {
+            let extent = scaled_extent(output_extent, transient_desc.downscale);
+            let aspects = transient_desc.format.surface_desc().aspects;
+            render_graph_state.image_states.push(ImageState::new_with_description(
+                device_state.clone(),
+                image::Kind::D2(
+                    extent.width as image::Size,
+                    extent.height as image::Size,
+                    1,
+                    transient_desc.samples,
+                ),
+                transient_desc.format,
+                usage,
+                ViewCapabilities::empty(),
+                image::ViewKind::D2,
+                SubresourceRange {
+                    aspects,
+                    levels: 0..1,
+                    layers: 0..1,
+                },
+            )?);
+        }
+        
+        for pass_index in order {
+            let pass = &builder.passes[pass_index];
+            
+            let writes_output = pass.color == builder.output();
+            if writes_output && (pass.depth.is_some() || pass.resolve.is_some()) {
+                return Err(graph_error("the pass writing the output cannot have other attachments"));
+            }
+            let (format, samples, downscale) = match builder.transient_desc(pass.color) {
+                Some(transient_desc) =>
+                    (transient_desc.format, transient_desc.samples, transient_desc.downscale),
+                None => (builder.output_format, 1, 0),
+            };
+            let attachment_matches = |resource: Option<ResourceId>, expected_samples| {
+                resource
+                    .and_then(|resource| builder.transient_desc(resource))
+                    .map_or(true, |transient_desc| {
+                        transient_desc.samples == expected_samples && transient_desc.downscale == downscale
+                    })
+            };
+            if !attachment_matches(pass.depth, samples) || !attachment_matches(pass.resolve, 1) {
+                return Err(graph_error(format!(
+                    "the attachments of {:?} differ in size or sample count",
+                    PassId(pass_index)
+                )));
+            }
+            
+            // what is not sampled later is not kept once the pass is done
+            let attachment = |resource: ResourceId, load_op, attachment_layout| {
+                let (format, samples, kept, final_layout) = match builder.transient_desc(resource) {
+                    Some(transient_desc) if sampled[resource.0] =>
+                        (transient_desc.format, transient_desc.samples, true, Layout::ShaderReadOnlyOptimal),
+                    Some(transient_desc) =>
+                        (transient_desc.format, transient_desc.samples, false, attachment_layout),
+                    None => (builder.output_format, 1, true, builder.output_layout),
+                };
+                Attachment {
+                    format: Some(format),
+                    samples,
+                    ops: AttachmentOps::new(
+                        load_op,
+                        if kept {
+                            AttachmentStoreOp::Store
+                        } else {
+                            AttachmentStoreOp::DontCare
+                        },
+                    ),
+                    stencil_ops: AttachmentOps::DONT_CARE,
+                    layouts: Layout::Undefined..final_layout,
+                }
+            };
+            let attachments: Vec<_> = iter::once(
+                attachment(pass.color, AttachmentLoadOp::Clear, Layout::ColorAttachmentOptimal)
+            )
+                .chain(pass.depth.map(|resource| attachment(
+                    resource,
+                    AttachmentLoadOp::Clear,
+                    Layout::DepthStencilAttachmentOptimal,
+                )))
+                .chain(pass.resolve.map(|resource| attachment(
+                    resource,
+                    AttachmentLoadOp::DontCare,
+                    Layout::ColorAttachmentOptimal,
+                )))
+                .collect();
+            
+            let render_pass = unsafe {
+                // the resolve attachment is the last one, if there is one
+                let resolves = [(attachments.len() - 1, Layout::ColorAttachmentOptimal)];
+                let resolves: &[(usize, Layout)] = if pass.resolve.is_some() {
+                    &resolves
+                } else {
+                    &[]
+                };
+                let depth_stencil = (1, Layout::DepthStencilAttachmentOptimal);
+                let subpass = SubpassDesc {
+                    colors: &[(0, Layout::ColorAttachmentOptimal)],
+                    depth_stencil: pass.depth.map(|_| &depth_stencil),
+                    inputs: &[],
+                    resolves,
+                    preserves: &[],
+                };
+                
+                // whatever used the images before, including passes sampling an image
+                // that now backs another resource, is done before the attachments are written
+                let (stages, accesses) = if pass.depth.is_some() {
+                    (
+                        (PipelineStage::COLOR_ATTACHMENT_OUTPUT | PipelineStage::LATE_FRAGMENT_TESTS)
+                            ..(PipelineStage::COLOR_ATTACHMENT_OUTPUT | PipelineStage::EARLY_FRAGMENT_TESTS),
+                        Access::COLOR_ATTACHMENT_READ | Access::COLOR_ATTACHMENT_WRITE |
+                            Access::DEPTH_STENCIL_ATTACHMENT_READ | Access::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                    )
+                } else {
+                    (
+                        PipelineStage::COLOR_ATTACHMENT_OUTPUT..PipelineStage::COLOR_ATTACHMENT_OUTPUT,
+                        Access::COLOR_ATTACHMENT_READ | Access::COLOR_ATTACHMENT_WRITE,
+                    )
+                };
+                let dependency = SubpassDependency {
+                    passes: SubpassRef::External..SubpassRef::Pass(0),
+                    stages,
+                    accesses: Access::empty()..accesses,
+                };
+                
+                device_state.borrow().device.create_render_pass(
+                    attachments,
+                    &[subpass],
+                    &[dependency],
+                )
+            }.map_err(|error| RendererError::creation("render graph pass", error))?;
+            
+            let clear_values = iter::once(ClearValue::Color(ClearColor::Float([0.0, 0.0, 0.0, 1.0])))
+                .chain(pass.depth.map(|_| ClearValue::DepthStencil(ClearDepthStencil(1.0, 0))))
+                // ignored since the resolve attachment is never cleared
+                .chain(pass.resolve.map(|_| ClearValue::Color(ClearColor::Float([0.0, 0.0, 0.0, 1.0]))))
+                .collect();
+            let mut sampled_images: Vec<usize> = pass.sampled
+                .iter()
+                .map(|resource| render_graph_state.resource_images[resource.0].unwrap())
+                .collect();
+            sampled_images.sort();
+            sampled_images.dedup();
+            let extent = scaled_extent(output_extent, downscale);
+            render_graph_state.passes.push(CompiledPass {
+                id: PassId(pass_index),
+                render_pass: Some(render_pass),
+                frame_buffer: None,
+                extent,
+                clear_values,
+                sampled_images,
+            });
+            
+            if !writes_output {
+                let frame_buffer = {
+                    let compiled_pass = render_graph_state.passes.last().unwrap();
+                    let image_views = pass
+                        .written()
+                        .map(|resource| render_graph_state.image_view(resource));
+                    unsafe {
+                        device_state.borrow().device.create_framebuffer(
+                            compiled_pass.render_pass.as_ref().unwrap(),
+                            image_views,
+                            extent.to_extent(),
+                        )
+                    }.map_err(|error| RendererError::creation("render graph framebuffer", error))?
+                };
+                render_graph_state.passes.last_mut().unwrap().frame_buffer = Some(frame_buffer);
+            }
+        }
+        
+        Ok(render_graph_state)
+    }
+    
+    /// what the frame buffers over the output images have to be created with
+    pub fn output_render_pass(&self) -> &<B as TB>::RenderPass {
+        self.passes
+            .iter()
+            .find(|pass| pass.frame_buffer.is_none())
+            .and_then(|pass| pass.render_pass.as_ref())
+            .unwrap()
+    }
+    
+    /// the image backing a transient resource, for the descriptor sets of the passes sampling it
+    pub fn image_view(&self, resource: ResourceId) -> &<B as TB>::ImageView {
+        let image_index = self.resource_images[resource.0]
+            .expect("the resource is not backed by an image of the graph");
+        self.image_states[image_index].image_view.as_ref().unwrap()
+    }
+    
+    /// record every pass in order with record_pass, the last one on output_frame_buffer
+    pub unsafe fn record<F>(
+        &self,
+        command_buffer: &mut CommandBuffer<B, Graphics, OneShot>,
+        output_frame_buffer: &<B as TB>::Framebuffer,
+        mut record_pass: F,
+    ) where
+        F: FnMut(PassId, &mut CommandBuffer<B, Graphics, OneShot>, &PassContext<'_>),
+    {
+        for pass in self.passes.iter() {
+            if !pass.sampled_images.is_empty() {
+                let barriers: Vec<_> = pass.sampled_images
+                    .iter()
+                    .map(|image_index| Barrier::Image {
+                        states: (Access::COLOR_ATTACHMENT_WRITE, Layout::ShaderReadOnlyOptimal)
+                            ..(Access::SHADER_READ, Layout::ShaderReadOnlyOptimal),
+                        target: self.image_states[*image_index].image.as_ref().unwrap(),
+                        families: None,
+                        range: COLOR_RANGE.clone(),
+                    })
+                    .collect();
+                command_buffer.pipeline_barrier(
+                    PipelineStage::COLOR_ATTACHMENT_OUTPUT..PipelineStage::FRAGMENT_SHADER,
+                    Dependencies::empty(),
+                    barriers,
+                );
+            }
+            
+            let context = PassContext {
+                render_pass: pass.render_pass.as_ref().unwrap(),
+                frame_buffer: pass.frame_buffer.as_ref().unwrap_or(output_frame_buffer),
+                viewport: Viewport {
+                    rect: Rect {
+                        x: 0,
+                        y: 0,
+                        w: pass.extent.width as i16,
+                        h: pass.extent.height as i16,
+                    },
+                    depth: 0.0..1.0,
+                },
+                clear_values: &pass.clear_values,
+            };
+            record_pass(pass.id, command_buffer, &context);
+        }
+    }
+}
+
+impl Drop for RenderGraphState {
+    fn drop(&mut self) {
+        let device = &self.device_state.borrow().device;
+        unsafe {
+            for pass in self.passes.iter_mut() {
+                if let Some(frame_buffer) = pass.frame_buffer.take() {
+                    device.destroy_framebuffer(frame_buffer);
+                }
+                if let Some(render_pass) = pass.render_pass.take() {
+                    device.destroy_render_pass(render_pass);
+                }
+            }
+        }
+    }
+}
+
+/// extent halved downscale times, but never below one texel
+pub fn scaled_extent(extent: Extent2D, downscale: u32) -> Extent2D {
+    Extent2D {
+        width: extent.width.checked_shr(downscale).unwrap_or(0).max(1),
+        height: extent.height.checked_shr(downscale).unwrap_or(0).max(1),
+    }
+}
+
+fn graph_error<S: Into<String>>(reason: S) -> RendererError {
+    RendererError::RenderGraph {
+        reason: reason.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    const COLOR: TransientDesc = TransientDesc {
+        format: Format::Rgba16Sfloat,
+        samples: 1,
+        downscale: 0,
+    };
+    
+    fn pass(sampled: Vec<ResourceId>, color: ResourceId) -> PassDesc {
+        PassDesc {
+            sampled,
+            color,
+            depth: None,
+            resolve: None,
+        }
+    }
+    
+    fn new_builder() -> RenderGraphBuilder {
+        RenderGraphBuilder::new(Format::Bgra8Srgb, Layout::Present)
+    }
+    
+    #[test]
+    fn passes_are_ordered_after_what_they_sample() {
+        let mut builder = new_builder();
+        let first = builder.create_transient(COLOR);
+        let second = builder.create_transient(COLOR);
+        let output = builder.output();
+        builder.add_pass(pass(vec![second], output));
+        builder.add_pass(pass(vec![first], second));
+        builder.add_pass(pass(vec![], first));
+        assert_eq!(builder.order().unwrap(), vec![2, 1, 0]);
+    }
+    
+    #[test]
+    fn independent_passes_keep_the_order_they_are_added_in() {
+        let mut builder = new_builder();
+        let first = builder.create_transient(COLOR);
+        let second = builder.create_transient(COLOR);
+        let output = builder.output();
+        builder.add_pass(pass(vec![], second));
+        builder.add_pass(pass(vec![], first));
+        builder.add_pass(pass(vec![first, second], output));
+        assert_eq!(builder.order().unwrap(), vec![0, 1, 2]);
+    }
+    
+    #[test]
+    fn passes_the_output_does_not_depend_on_are_left_out() {
+        let mut builder = new_builder();
+        let used = builder.create_transient(COLOR);
+        let unused = builder.create_transient(COLOR);
+        let unused_sink = builder.create_transient(COLOR);
+        let output = builder.output();
+        builder.add_pass(pass(vec![], unused));
+        builder.add_pass(pass(vec![], used));
+        builder.add_pass(pass(vec![unused], unused_sink));
+        builder.add_pass(pass(vec![used], output));
+        assert_eq!(builder.order().unwrap(), vec![1, 3]);
+        
+        // and their resources are not backed by an image
+        let plan = builder.plan_images(&builder.order().unwrap()).unwrap();
+        assert_eq!(plan.image_descs.len(), 1);
+        assert!(plan.resource_images[used.0].is_some());
+        assert!(plan.resource_images[unused.0].is_none());
+    }
+    
+    #[test]
+    fn invalid_graphs_are_rejected() {
+        // nothing written to the output
+        let mut builder = new_builder();
+        let transient = builder.create_transient(COLOR);
+        builder.add_pass(pass(vec![], transient));
+        assert!(builder.order().is_err());
+        
+        // a resource written twice
+        let mut builder = new_builder();
+        let output = builder.output();
+        builder.add_pass(pass(vec![], output));
+        builder.add_pass(pass(vec![], output));
+        assert!(builder.order().is_err());
+        
+        // a resource sampled but never written
+        let mut builder = new_builder();
+        let transient = builder.create_transient(COLOR);
+        let output = builder.output();
+        builder.add_pass(pass(vec![transient], output));
+        assert!(builder.order().is_err());
+        
+        // a cycle
+        let mut builder = new_builder();
+        let first = builder.create_transient(COLOR);
+        let second = builder.create_transient(COLOR);
+        let output = builder.output();
+        builder.add_pass(pass(vec![second], first));
+        builder.add_pass(pass(vec![first], second));
+        builder.add_pass(pass(vec![second], output));
+        assert!(builder.order().is_err());
+    }
+    
+    #[test]
+    fn resources_share_an_image_once_the_last_use_has_passed() {
+        let mut builder = new_builder();
+        let first = builder.create_transient(COLOR);
+        let second = builder.create_transient(COLOR);
+        let third = builder.create_transient(COLOR);
+        let output = builder.output();
+        builder.add_pass(pass(vec![], first));
+        builder.add_pass(pass(vec![first], second));
+        builder.add_pass(pass(vec![second], third));
+        builder.add_pass(pass(vec![third], output));
+        let plan = builder.plan_images(&builder.order().unwrap()).unwrap();
+        assert_eq!(plan.image_descs.len(), 2);
+        assert_eq!(plan.resource_images[first.0], plan.resource_images[third.0]);
+        assert_ne!(plan.resource_images[first.0], plan.resource_images[second.0]);
+        assert_eq!(plan.resource_images[output.0], None);
+    }
+    
+    #[test]
+    fn resources_in_use_at_the_same_time_do_not_share_an_image() {
+        let mut builder = new_builder();
+        let first = builder.create_transient(COLOR);
+        let second = builder.create_transient(COLOR);
+        let output = builder.output();
+        builder.add_pass(pass(vec![], first));
+        builder.add_pass(pass(vec![], second));
+        builder.add_pass(pass(vec![first, second], output));
+        let plan = builder.plan_images(&builder.order().unwrap()).unwrap();
+        assert_eq!(plan.image_descs.len(), 2);
+        assert_ne!(plan.resource_images[first.0], plan.resource_images[second.0]);
+    }
+    
+    #[test]
+    fn resources_of_different_descriptions_do_not_share_an_image() {
+        let mut builder = new_builder();
+        let first = builder.create_transient(COLOR);
+        let second = builder.create_transient(COLOR);
+        let third = builder.create_transient(TransientDesc {
+            downscale: 1,
+            ..COLOR
+        });
+        let depth = builder.create_transient(TransientDesc {
+            format: Format::D32Sfloat,
+            downscale: 1,
+            ..COLOR
+        });
+        let output = builder.output();
+        builder.add_pass(pass(vec![], first));
+        builder.add_pass(pass(vec![first], second));
+        // the image of first is free by now but third is smaller,
+        // and the depth attachment is never sampled, so it is transient unlike third
+        builder.add_pass(PassDesc {
+            depth: Some(depth),
+            ..pass(vec![second], third)
+        });
+        builder.add_pass(pass(vec![third], output));
+        let plan = builder.plan_images(&builder.order().unwrap()).unwrap();
+        assert_eq!(plan.image_descs.len(), 4);
+        assert_eq!(plan.resource_images[third.0], Some(2));
+        assert_eq!(
+            plan.image_descs[2].1,
+            image::Usage::COLOR_ATTACHMENT | image::Usage::SAMPLED,
+        );
+        assert_eq!(
+            plan.image_descs[3].1,
+            image::Usage::DEPTH_STENCIL_ATTACHMENT | image::Usage::TRANSIENT_ATTACHMENT,
+        );
+        assert!(plan.sampled[third.0]);
+        assert!(!plan.sampled[depth.0]);
+    }
+    
+    #[test]
+    fn sampling_the_output_or_a_multisampled_resource_is_rejected() {
+        let mut builder = new_builder();
+        let output = builder.output();
+        let transient = builder.create_transient(COLOR);
+        builder.add_pass(pass(vec![], output));
+        builder.add_pass(pass(vec![output], transient));
+        assert!(builder.plan_images(&[0, 1]).is_err());
+        
+        let mut builder = new_builder();
+        let multisampled = builder.create_transient(TransientDesc {
+            samples: 4,
+            ..COLOR
+        });
+        let output = builder.output();
+        builder.add_pass(pass(vec![], multisampled));
+        builder.add_pass(pass(vec![multisampled], output));
+        assert!(builder.plan_images(&builder.order().unwrap()).is_err());
+    }
+}
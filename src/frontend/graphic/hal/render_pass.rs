@@ -5,43 +5,89 @@ use std::{
 
 use super::{
     device::DeviceState,
+    error::{
+        RendererError,
+        RendererResult,
+    },
     prelude::*,
-    swapchain::SwapchainState,
 };
 
+/// the attachments are the color target, the depth target if there is one and,
+/// when multisampled, the single sampled image the color target is resolved to
 pub struct RenderPassState {
     device_state: Rc<RefCell<DeviceState>>,
     pub render_pass: Option<<B as TB>::RenderPass>,
+    // samples per pixel of the color and depth targets, 1 renders to the output image directly
+    pub samples: image::NumSamples,
+    // none for a pass without depth testing, such as a fullscreen pass
+    pub depth_format: Option<Format>,
 }
 
 impl RenderPassState {
-    pub fn new(device_state: Rc<RefCell<DeviceState>>, swapchain_state: &SwapchainState) -> Self {
+    /// output_layout is the layout the single sampled color image is left in,
+    /// which is the resolve attachment when multisampling
+    pub fn new_with_formats(
+        device_state: Rc<RefCell<DeviceState>>,
+        color_format: Format,
+        depth_format: Option<Format>,
+        samples: image::NumSamples,
+        output_layout: Layout,
+    ) -> RendererResult<Self> {
         let render_pass =
             unsafe {
                 let color_attachment = Attachment {
-                    format: Some(swapchain_state.color_format),
-                    samples: 1,
+                    format: Some(color_format),
+                    samples,
                     ops: AttachmentOps::new(
                         AttachmentLoadOp::Clear,
-                        AttachmentStoreOp::Store,
+                        // only the resolved image is kept when multisampling
+                        if samples > 1 {
+                            AttachmentStoreOp::DontCare
+                        } else {
+                            AttachmentStoreOp::Store
+                        },
                     ),
                     stencil_ops: AttachmentOps::DONT_CARE,
-                    layouts: Layout::Undefined..Layout::ColorAttachmentOptimal,
+                    layouts: if samples > 1 {
+                        Layout::Undefined..Layout::ColorAttachmentOptimal
+                    } else {
+                        Layout::Undefined..output_layout
+                    },
                 };
-    
-                let depth_attachment = Attachment {
-                    format: Some(swapchain_state.depth_format),
-                    samples: 1,
+                
+                let depth_attachment = depth_format.map(|depth_format| Attachment {
+                    format: Some(depth_format),
+                    samples,
                     ops: AttachmentOps::new(AttachmentLoadOp::Clear, AttachmentStoreOp::DontCare),
                     stencil_ops: AttachmentOps::DONT_CARE,
                     layouts: Layout::Undefined..Layout::DepthStencilAttachmentOptimal,
+                });
+                
+                let resolve_attachment = Attachment {
+                    format: Some(color_format),
+                    samples: 1,
+                    ops: AttachmentOps::new(
+                        AttachmentLoadOp::DontCare,
+                        AttachmentStoreOp::Store,
+                    ),
+                    stencil_ops: AttachmentOps::DONT_CARE,
+                    layouts: Layout::Undefined..output_layout,
                 };
                 
+                // the resolve attachment comes after the depth attachment, if there is one
+                let resolve_index = if depth_attachment.is_some() { 2 } else { 1 };
+                let resolves = [(resolve_index, Layout::ColorAttachmentOptimal)];
+                let resolves: &[(usize, Layout)] = if samples > 1 {
+                    &resolves
+                } else {
+                    &[]
+                };
+                let depth_stencil = (1, Layout::DepthStencilAttachmentOptimal);
                 let subpass = SubpassDesc {
                     colors: &[(0, Layout::ColorAttachmentOptimal)],
-                    depth_stencil: Some(&(1, Layout::DepthStencilAttachmentOptimal)),
+                    depth_stencil: depth_attachment.as_ref().map(|_| &depth_stencil),
                     inputs: &[],
-                    resolves: &[],
+                    resolves,
                     preserves: &[],
                 };
                 
@@ -53,18 +99,51 @@ impl RenderPassState {
                         ..(Access::COLOR_ATTACHMENT_READ | Access::COLOR_ATTACHMENT_WRITE),
                 };
     
+                let mut attachments = vec![color_attachment];
+                attachments.extend(depth_attachment);
+                if samples > 1 {
+                    attachments.push(resolve_attachment);
+                }
                 device_state.borrow().device.create_render_pass(
-                    &[color_attachment, depth_attachment],
+                    attachments,
                     &[subpass],
                     &[dependency],
                 )
-            }.unwrap();
+            }.map_err(|error| RendererError::creation("render pass", error))?;
         
-        RenderPassState {
+        Ok(RenderPassState {
             render_pass: Some(render_pass),
             device_state,
+            samples,
+            depth_format,
+        })
+    }
+    
+    /// a clear value for every attachment,
+    /// the one of the resolve attachment is ignored since it is never cleared
+    pub fn clear_values(&self) -> Vec<ClearValue> {
+        let mut clear_values = vec![
+            ClearValue::Color(ClearColor::Float([0.0, 0.0, 0.0, 1.0])),
+        ];
+        if self.depth_format.is_some() {
+            clear_values.push(ClearValue::DepthStencil(ClearDepthStencil(1.0, 0)));
         }
+        if self.samples > 1 {
+            clear_values.push(ClearValue::Color(ClearColor::Float([0.0, 0.0, 0.0, 1.0])));
+        }
+        clear_values
+    }
+}
+
+/// the highest sample count up to requested that the adapter supports
+/// for both color and depth attachments
+pub fn select_sample_count(limits: &Limits, requested: image::NumSamples) -> image::NumSamples {
+    let supported = limits.framebuffer_color_samples_count & limits.framebuffer_depth_samples_count;
+    let mut samples = requested.max(1).next_power_of_two();
+    while samples > 1 && supported & samples == 0 {
+        samples /= 2;
     }
+    samples
 }
 
 impl Drop for RenderPassState {
@@ -0,0 +1,186 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    hash::{Hash, Hasher},
+    rc::Rc,
+};
+
+use super::{
+    device::DeviceState,
+    error::RendererResult,
+    image::SamplerState,
+    prelude::*,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+pub enum SamplerWrap {
+    Repeat,
+    Mirror,
+    Clamp,
+    Border,
+}
+
+impl SamplerWrap {
+    fn to_wrap_mode(&self) -> WrapMode {
+        match self {
+            SamplerWrap::Repeat => WrapMode::Tile,
+            SamplerWrap::Mirror => WrapMode::Mirror,
+            SamplerWrap::Clamp => WrapMode::Clamp,
+            SamplerWrap::Border => WrapMode::Border,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+pub enum SamplerFilter {
+    Nearest,
+    Linear,
+}
+
+impl SamplerFilter {
+    fn to_filter(&self) -> Filter {
+        match self {
+            SamplerFilter::Nearest => Filter::Nearest,
+            SamplerFilter::Linear => Filter::Linear,
+        }
+    }
+}
+
+/// a description of a sampler that can be read from a sidecar config
+/// and used as the key of a SamplerCache
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default)]
+pub struct SamplerDesc {
+    /// wrap mode along u, v and w
+    pub wrap: (SamplerWrap, SamplerWrap, SamplerWrap),
+    pub min_filter: SamplerFilter,
+    pub mag_filter: SamplerFilter,
+    pub mip_filter: SamplerFilter,
+    pub lod_bias: f32,
+    /// maximum anisotropy, 1 turns anisotropic filtering off
+    pub anisotropy: u8,
+    /// packed RGBA8 color used by SamplerWrap::Border
+    pub border: u32,
+}
+
+impl Default for SamplerDesc {
+    fn default() -> SamplerDesc {
+        SamplerDesc {
+            wrap: (SamplerWrap::Repeat, SamplerWrap::Repeat, SamplerWrap::Repeat),
+            min_filter: SamplerFilter::Linear,
+            mag_filter: SamplerFilter::Linear,
+            mip_filter: SamplerFilter::Linear,
+            lod_bias: 0.0,
+            anisotropy: 1,
+            border: 0,
+        }
+    }
+}
+
+impl SamplerDesc {
+    pub fn with_wrap(mut self, wrap: SamplerWrap) -> SamplerDesc {
+        self.wrap = (wrap, wrap, wrap);
+        self
+    }
+
+    pub fn with_filter(mut self, filter: SamplerFilter) -> SamplerDesc {
+        self.min_filter = filter;
+        self.mag_filter = filter;
+        self.mip_filter = filter;
+        self
+    }
+
+    /// the SamplerInfo to create this sampler with,
+    /// anisotropy is clamped to what the adapter supports
+    pub fn to_sampler_info(&self, limits: &Limits) -> SamplerInfo {
+        let mut sampler_info = SamplerInfo::new(
+            self.min_filter.to_filter(),
+            self.wrap.0.to_wrap_mode(),
+        );
+        sampler_info.mag_filter = self.mag_filter.to_filter();
+        sampler_info.mip_filter = self.mip_filter.to_filter();
+        sampler_info.wrap_mode = (
+            self.wrap.0.to_wrap_mode(),
+            self.wrap.1.to_wrap_mode(),
+            self.wrap.2.to_wrap_mode(),
+        );
+        sampler_info.lod_bias = self.lod_bias.into();
+        sampler_info.border = image::PackedColor(self.border);
+        let anisotropy = (self.anisotropy as f32).min(limits.max_sampler_anisotropy) as u8;
+        sampler_info.anisotropic = if anisotropy > 1 {
+            image::Anisotropic::On(anisotropy)
+        } else {
+            image::Anisotropic::Off
+        };
+        sampler_info
+    }
+
+    fn key(&self) -> (
+        (SamplerWrap, SamplerWrap, SamplerWrap),
+        SamplerFilter,
+        SamplerFilter,
+        SamplerFilter,
+        u32,
+        u8,
+        u32,
+    ) {
+        (
+            self.wrap,
+            self.min_filter,
+            self.mag_filter,
+            self.mip_filter,
+            self.lod_bias.to_bits(),
+            self.anisotropy,
+            self.border,
+        )
+    }
+}
+
+impl PartialEq for SamplerDesc {
+    fn eq(&self, other: &SamplerDesc) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for SamplerDesc {}
+
+impl Hash for SamplerDesc {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state);
+    }
+}
+
+/// shares one sampler between every texture asking for an identical SamplerDesc
+pub struct SamplerCache {
+    device_state: Rc<RefCell<DeviceState>>,
+    limits: Limits,
+    samplers: HashMap<SamplerDesc, Rc<SamplerState>>,
+}
+
+impl SamplerCache {
+    pub fn new(device_state: Rc<RefCell<DeviceState>>, limits: Limits) -> SamplerCache {
+        SamplerCache {
+            device_state,
+            limits,
+            samplers: HashMap::new(),
+        }
+    }
+
+    pub fn get(&mut self, sampler_desc: SamplerDesc) -> RendererResult<Rc<SamplerState>> {
+        if let Some(sampler_state) = self.samplers.get(&sampler_desc) {
+            return Ok(sampler_state.clone());
+        }
+        let sampler_state = Rc::new(SamplerState::new(
+            self.device_state.clone(),
+            sampler_desc.to_sampler_info(&self.limits),
+        )?);
+        println!("[INFO][Sampler Created]{:?}", sampler_desc);
+        self.samplers.insert(sampler_desc, sampler_state.clone());
+        Ok(sampler_state)
+    }
+
+    /// drop the samplers no texture refers to anymore
+    pub fn collect_unused(&mut self) {
+        self.samplers.retain(|_, sampler_state| Rc::strong_count(sampler_state) > 1);
+    }
+}
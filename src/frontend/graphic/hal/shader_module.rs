@@ -1,10 +1,17 @@
 use std::{
     cell::RefCell,
+    collections::HashMap,
     rc::Rc,
 };
 
+use gfs::{GemFileSystem, ReadFile};
+
 use super::{
     device::DeviceState,
+    error::{
+        RendererError,
+        RendererResult,
+    },
     prelude::*,
 };
 
@@ -15,18 +22,23 @@ pub struct ShaderModuleState {
 }
 
 impl ShaderModuleState {
+    /// path only names the shader in errors
     pub fn new(
         device_state: Rc<RefCell<DeviceState>>,
-        spirv: &Box<[u8]>,
-    ) -> ShaderModuleState {
+        path: &str,
+        spirv: &[u8],
+    ) -> RendererResult<ShaderModuleState> {
         let module = unsafe {
             let device = &device_state.borrow().device;
-            device.create_shader_module(spirv.as_ref())
-        }.unwrap();
-        ShaderModuleState {
+            device.create_shader_module(spirv)
+        }.map_err(|error| RendererError::Shader {
+            path: path.to_string(),
+            reason: format!("{:?}", error),
+        })?;
+        Ok(ShaderModuleState {
             device_state,
             module: Some(module),
-        }
+        })
     }
 }
 
@@ -43,3 +55,39 @@ impl Drop for ShaderModuleState {
         }
     }
 }
+
+/// SPIR-V compiled at runtime, read instead of the files under res/ at the same paths,
+/// which GemFileSystem would otherwise keep giving the content it first read of
+pub struct ShaderCache {
+    compiled: HashMap<String, Box<[u8]>>,
+}
+
+impl ShaderCache {
+    pub fn new() -> ShaderCache {
+        ShaderCache {
+            compiled: HashMap::new(),
+        }
+    }
+    
+    /// the SPIR-V at path under res/, or what has been compiled for it since
+    pub fn read(&self, gfs: &mut GemFileSystem, path: &str) -> RendererResult<Box<[u8]>> {
+        if let Some(spirv) = self.compiled.get(path) {
+            return Ok(spirv.clone());
+        }
+        gfs
+            .read_file(path.to_string())
+            .map(|spirv| spirv.clone())
+            .map_err(|error| RendererError::file(path, format!("{:?}", error)))
+    }
+    
+    /// read spirv for path from now on, returning what was compiled for it before
+    pub fn insert(&mut self, path: &str, spirv: Box<[u8]>) -> Option<Box<[u8]>> {
+        self.compiled.insert(path.to_string(), spirv)
+    }
+    
+    /// read path from res/ again
+    pub fn remove(&mut self, path: &str) -> Option<Box<[u8]>> {
+        self.compiled.remove(path)
+    }
+}
+
@@ -3,14 +3,58 @@ use std::{
     rc::Rc,
 };
 
-use crate::frontend::graphic::constants::COLOR_RANGE;
-use crate::frontend::graphic::hal::render_pass::RenderPassState;
+use crate::frontend::graphic::constants::{
+    COLOR_RANGE,
+    DEPTH_STENCIL_REQUIRED,
+};
 
 use super::{
     device::DeviceState,
+    error::{
+        RendererError,
+        RendererResult,
+    },
     prelude::*,
 };
 use super::adapter::AdapterState;
+use super::buffer::BufferState;
+
+/// how frames are handed to the display, the surface may not support the mode asked for,
+/// in which case the closest supported one is used
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PresentModePolicy {
+    /// wait for vertical blank, never tears, always supported
+    Vsync,
+    /// replace the queued frame with the newest one, no tearing and low latency
+    Mailbox,
+    /// present right away and tear, for benchmarking
+    Immediate,
+    /// wait for vertical blank unless the frame is late, then present right away
+    Relaxed,
+}
+
+impl PresentModePolicy {
+    /// the present modes to try in order, FIFO is last since every surface supports it
+    fn preferences(&self) -> &'static [PresentMode] {
+        match self {
+            PresentModePolicy::Vsync => &[PresentMode::Fifo],
+            PresentModePolicy::Mailbox => &[PresentMode::Mailbox, PresentMode::Fifo],
+            PresentModePolicy::Immediate =>
+                &[PresentMode::Immediate, PresentMode::Mailbox, PresentMode::Fifo],
+            PresentModePolicy::Relaxed => &[PresentMode::Relaxed, PresentMode::Fifo],
+        }
+    }
+    
+    /// the policy after this one, to cycle through them at runtime
+    pub fn next(&self) -> PresentModePolicy {
+        match self {
+            PresentModePolicy::Vsync => PresentModePolicy::Mailbox,
+            PresentModePolicy::Mailbox => PresentModePolicy::Immediate,
+            PresentModePolicy::Immediate => PresentModePolicy::Relaxed,
+            PresentModePolicy::Relaxed => PresentModePolicy::Vsync,
+        }
+    }
+}
 
 pub struct SwapchainState {
     pub device_state: Rc<RefCell<DeviceState>>,
@@ -19,6 +63,9 @@ pub struct SwapchainState {
     pub extent: Extent,
     pub color_format: Format,
     pub depth_format: Format,
+    pub present_mode: PresentMode,
+    // whether the images can be copied from, which screenshots need
+    pub readable: bool,
 }
 
 impl SwapchainState {
@@ -27,19 +74,29 @@ impl SwapchainState {
         adapter: &AdapterState,
         surface: &mut <B as TB>::Surface,
         extent: Extent2D,
-    ) -> SwapchainState {
-        let (caps, formats, _present_modes) =
+        present_mode_policy: PresentModePolicy,
+    ) -> RendererResult<SwapchainState> {
+        let (caps, formats, present_modes) =
             surface.compatibility(&adapter.adapter.physical_device);
         println!("[INFO][Formats]{:?}", formats);
     
         let color_format = select_surface_color_format(formats);
         println!("[INFO][Chosen Surface Format] {:?}", color_format);
     
-        let depth_format = Format::D32SfloatS8Uint;
+        let depth_format = select_depth_format(adapter, DEPTH_STENCIL_REQUIRED)?;
         println!("[INFO][Chosen Depth Format] {:?}", depth_format);
         
-        let swap_config =
-            SwapchainConfig::from_caps(&caps, color_format, extent);
+        println!("[INFO][Present Modes]{:?}", present_modes);
+        let present_mode = select_present_mode(present_mode_policy, &present_modes);
+        println!("[INFO][Chosen Present Mode] {:?} for {:?}", present_mode, present_mode_policy);
+        
+        let readable = caps.usage.contains(image::Usage::TRANSFER_SRC);
+        let mut swap_config =
+            SwapchainConfig::from_caps(&caps, color_format, extent)
+                .with_mode(present_mode);
+        if readable {
+            swap_config.image_usage |= image::Usage::TRANSFER_SRC;
+        }
         
         let extent = swap_config.extent.to_extent();
     
@@ -53,16 +110,20 @@ impl SwapchainState {
                         swap_config,
                         None,
                     )
-            }.expect("Can't create swapchain");
+            }.map_err(|error| RendererError::Swapchain {
+                reason: format!("{:?} for {:?} at {:?}", error, color_format, extent),
+            })?;
         
-        SwapchainState {
+        Ok(SwapchainState {
             swapchain: Some(swapchain),
             backbuffer: Some(backbuffer),
             device_state,
             extent,
             color_format,
             depth_format,
-        }
+            present_mode,
+            readable,
+        })
     }
 }
 
@@ -77,213 +138,151 @@ impl Drop for SwapchainState {
     }
 }
 
+/// a framebuffer for every swapchain image, the synchronization of the frames
+/// rendering to them lives in FrameContextsState
 pub struct FrameBufferState {
-    pub command_pools: Option<Vec<CommandPool<B, Graphics>>>,
     pub frame_buffers: Option<Vec<<B as TB>::Framebuffer>>,
-    pub frame_buffer_fences: Option<Vec<<B as TB>::Fence>>,
     pub frame_images: Option<Vec<<B as TB>::Image>>,
     pub frame_image_views: Option<Vec<<B as TB>::ImageView>>,
-    pub acquire_semaphores: Option<Vec<<B as TB>::Semaphore>>,
-    pub present_semaphores: Option<Vec<<B as TB>::Semaphore>>,
-    pub depth_image: Option<<B as TB>::Image>,
-    pub depth_image_memory: Option<<B as TB>::Memory>,
-    pub depth_image_view: Option<<B as TB>::ImageView>,
-    pub current_index: usize,
-    pub last_index: usize,
     pub device_state: Rc<RefCell<DeviceState>>,
 }
 
 impl FrameBufferState {
+    /// render_pass has the swapchain image as its only attachment,
+    /// the scene itself is rendered to an HDR target and tonemapped into it
     pub fn new(
         device_state: Rc<RefCell<DeviceState>>,
-        adapter_state: &AdapterState,
-        render_pass: &RenderPassState,
+        render_pass: &<B as TB>::RenderPass,
         swapchain: &mut SwapchainState,
-    ) -> Self {
+    ) -> RendererResult<Self> {
         let extent = Extent {
             width: swapchain.extent.width as _,
             height: swapchain.extent.height as _,
             depth: 1,
         };
-    
-    
-        let (depth_image, depth_image_memory, depth_image_view) = unsafe {
-            let device = &device_state.borrow().device;
-        
-            let kind =
-                image::Kind::D2(extent.width as image::Size, extent.height as
-                    image::Size, 1, 1);
         
-            let mut depth_image = device
-                .create_image(
-                    kind,
-                    1,
-                    swapchain.depth_format,
-                    image::Tiling::Optimal,
-                    image::Usage::DEPTH_STENCIL_ATTACHMENT,
-                    ViewCapabilities::empty(),
-                )
-                .expect("Failed to create unbound depth image");
-        
-            let image_req = device.get_image_requirements(&depth_image);
-        
-            let device_type = adapter_state.memory_types
-                                           .iter()
-                                           .enumerate()
-                                           .position(|(id, memory_type)| {
-                                               image_req.type_mask & (1 << id) != 0
-                                                   && memory_type.properties.contains(Properties::DEVICE_LOCAL)
-                                           })
-                                           .unwrap()
-                                           .into();
-        
-            let depth_image_memory = device
-                .allocate_memory(device_type, image_req.size)
-                .expect("Failed to allocate depth image");
-        
-            device
-                .bind_image_memory(&depth_image_memory, 0, &mut depth_image)
-                .expect("Failed to bind depth image");
-        
-            let depth_image_view = device
-                .create_image_view(
-                    &depth_image,
-                    image::ViewKind::D2,
-                    swapchain.depth_format,
-                    Swizzle::NO,
-                    image::SubresourceRange {
-                        aspects: Aspects::DEPTH | Aspects::STENCIL,
-                        levels: 0..1,
-                        layers: 0..1,
-                    },
-                )
-                .expect("Failed to create image view");
-        
-            (depth_image, depth_image_memory, depth_image_view)
+        // whatever has been created when something fails is destroyed by dropping frame_buffer_state
+        let mut frame_buffer_state = FrameBufferState {
+            frame_images: Some(vec![]),
+            frame_image_views: Some(vec![]),
+            frame_buffers: Some(vec![]),
+            device_state: device_state.clone(),
         };
         
-        let (frame_images, frame_image_views, frame_buffers) = {
+        {
             let device = &device_state.borrow().device;
-    
-    
+            
             match swapchain.backbuffer.take().unwrap() {
                 Backbuffer::Images(frame_images) => {
-                    let frame_image_views =
-                        frame_images
-                            .iter()
-                            .map(|image| {
-                                let frame_image_view = unsafe {
-                                    device.create_image_view(
-                                        &image,
-                                        ViewKind::D2,
-                                        swapchain.color_format,
-                                        Swizzle::NO,
-                                        COLOR_RANGE.clone(),
-                                    )
-                                }.unwrap();
-                                frame_image_view
-                            }).collect::<Vec<_>>();
-    
-                    let frame_buffers = frame_image_views.iter().map(
-                        |image_view| {
-                            unsafe {
-                                device.create_framebuffer(
-                                    render_pass.render_pass.as_ref().unwrap(),
-                                    vec![image_view, &depth_image_view],
-                                    extent,
-                                )
-                            }.unwrap()
-                        }
-                    ).collect::<Vec<_>>();
-                    ;
-                    (frame_images, frame_image_views, frame_buffers)
-                }
-                Backbuffer::Framebuffer(fbo) =>
-                    (Vec::new(), Vec::new(), vec![fbo])
-            }
-        };
-        
-        let iter_count = if frame_images.len() != 0 {
-            frame_images.len()
-        } else {
-            1 // GL can have zero
-        };
-        
-        let mut fences: Vec<<B as TB>::Fence> = vec![];
-        let mut command_pools: Vec<CommandPool<B, Graphics>> = vec![];
-        let mut acquire_semaphores: Vec<<B as TB>::Semaphore> = vec![];
-        let mut present_semaphores: Vec<<B as TB>::Semaphore> = vec![];
-        {
-            let device = &device_state.borrow().device;
-            for _ in 0..iter_count {
-                fences.push(device.create_fence(true).unwrap());
-                command_pools.push(
-                    unsafe {
-                        device
-                            .create_command_pool_typed(
-                                &device_state.borrow().queue_group,
-                                CommandPoolCreateFlags::empty(),
+                    for image in frame_images.iter() {
+                        let frame_image_view = unsafe {
+                            device.create_image_view(
+                                &image,
+                                ViewKind::D2,
+                                swapchain.color_format,
+                                Swizzle::NO,
+                                COLOR_RANGE.clone(),
                             )
-                    }.expect("Can't create command pool"),
-                );
-                
-                acquire_semaphores.push(device.create_semaphore().unwrap());
-                present_semaphores.push(device.create_semaphore().unwrap());
+                        }.map_err(|error| RendererError::creation("swapchain image view", error))?;
+                        frame_buffer_state.frame_image_views.as_mut().unwrap().push(frame_image_view);
+                    }
+                    
+                    for image_view in frame_buffer_state.frame_image_views.as_ref().unwrap() {
+                        let frame_buffer = unsafe {
+                            device.create_framebuffer(
+                                render_pass,
+                                Some(image_view),
+                                extent,
+                            )
+                        }.map_err(|error| RendererError::creation("framebuffer", error))?;
+                        frame_buffer_state.frame_buffers.as_mut().unwrap().push(frame_buffer);
+                    }
+                    frame_buffer_state.frame_images = Some(frame_images);
+                }
+                Backbuffer::Framebuffer(fbo) => {
+                    frame_buffer_state.frame_images = None;
+                    frame_buffer_state.frame_buffers = Some(vec![fbo]);
+                }
             }
         }
         
-        
-        FrameBufferState {
-            frame_images: Some(frame_images),
-            frame_image_views: Some(frame_image_views),
-            frame_buffers: Some(frame_buffers),
-            frame_buffer_fences: Some(fences),
-            command_pools: Some(command_pools),
-            present_semaphores: Some(present_semaphores),
-            depth_image: Some(depth_image),
-            depth_image_memory: Some(depth_image_memory),
-            acquire_semaphores: Some(acquire_semaphores),
-            device_state,
-            current_index: 0,
-            last_index: 0,
-            depth_image_view: Some(depth_image_view)
-        }
+        Ok(frame_buffer_state)
     }
     
-    pub fn increment_current_semaphores_index(&mut self) {
-        let num_of_acquire_semaphores = self.acquire_semaphores.as_ref().unwrap().len();
-        self.current_index += 1;
-        if self.current_index >= num_of_acquire_semaphores {
-            self.current_index = 0
-        }
+    pub fn frame_buffer(&self, frame_index: SwapImageIndex) -> &<B as TB>::Framebuffer {
+        &self.frame_buffers.as_ref().unwrap()[frame_index as usize]
+    }
+    
+    /// whether the swapchain gave images, a backbuffer that is a framebuffer has none to copy from
+    pub fn has_images(&self) -> bool {
+        self.frame_images.as_ref().map_or(false, |frame_images| !frame_images.is_empty())
     }
     
-    pub fn get_frame_data(
-        &mut self,
-        frame_index: usize,
-        semaphore_index: usize,
-    ) -> (
-        (
-            &mut <B as TB>::Fence,
-            &mut <B as TB>::Framebuffer,
-            &mut CommandPool<B, Graphics>
-        ),
-        (
-            &mut <B as TB>::Semaphore,
-            &mut <B as TB>::Semaphore
-        ),
+    /// record the copy of a presentable image rendered in this command buffer into readback_buffer,
+    /// the image goes back to the Present layout afterwards, only to be called if has_images
+    pub unsafe fn record_readback(
+        &self,
+        command_buffer: &mut CommandBuffer<B, Graphics, OneShot>,
+        frame_index: SwapImageIndex,
+        extent: Extent,
+        readback_buffer: &BufferState<u8>,
     ) {
-        (
-            (
-                &mut self.frame_buffer_fences.as_mut().unwrap()[frame_index],
-                &mut self.frame_buffers.as_mut().unwrap()[frame_index],
-                &mut self.command_pools.as_mut().unwrap()[frame_index],
-            ),
-            (
-                &mut self.acquire_semaphores.as_mut().unwrap()[semaphore_index],
-                &mut self.present_semaphores.as_mut().unwrap()[semaphore_index],
-            )
-        )
+        let frame_image = &self.frame_images.as_ref().unwrap()[frame_index as usize];
+        
+        command_buffer.pipeline_barrier(
+            PipelineStage::COLOR_ATTACHMENT_OUTPUT..PipelineStage::TRANSFER,
+            Dependencies::empty(),
+            &[
+                Barrier::Image {
+                    states: (Access::COLOR_ATTACHMENT_WRITE, Layout::Present)
+                        ..(Access::TRANSFER_READ, Layout::TransferSrcOptimal),
+                    target: frame_image,
+                    families: None,
+                    range: COLOR_RANGE.clone(),
+                }
+            ],
+        );
+        
+        command_buffer.copy_image_to_buffer(
+            frame_image,
+            Layout::TransferSrcOptimal,
+            readback_buffer.buffer.as_ref().unwrap(),
+            &[
+                BufferImageCopy {
+                    buffer_offset: 0,
+                    buffer_width: extent.width,
+                    buffer_height: extent.height,
+                    image_layers: SubresourceLayers {
+                        aspects: Aspects::COLOR,
+                        level: 0,
+                        layers: 0..1,
+                    },
+                    image_offset: Offset { x: 0, y: 0, z: 0 },
+                    image_extent: extent,
+                }
+            ],
+        );
+        
+        command_buffer.pipeline_barrier(
+            PipelineStage::TRANSFER..PipelineStage::BOTTOM_OF_PIPE,
+            Dependencies::empty(),
+            &[
+                Barrier::Image {
+                    states: (Access::TRANSFER_READ, Layout::TransferSrcOptimal)
+                        ..(Access::empty(), Layout::Present),
+                    target: frame_image,
+                    families: None,
+                    range: COLOR_RANGE.clone(),
+                }
+            ],
+        );
+        command_buffer.pipeline_barrier(
+            PipelineStage::TRANSFER..PipelineStage::HOST,
+            Dependencies::empty(),
+            &[
+                Barrier::AllBuffers(buffer::Access::TRANSFER_WRITE..buffer::Access::HOST_READ)
+            ],
+        );
     }
 }
 
@@ -292,30 +291,6 @@ impl Drop for FrameBufferState {
         let device = &self.device_state.borrow().device;
     
         unsafe {
-            if let Some(frame_buffer_fences) =
-            self.frame_buffer_fences.take() {
-                for fence in frame_buffer_fences {
-                    device.destroy_fence(fence);
-                }
-            }
-            if let Some(command_pools) =
-            self.command_pools.take() {
-                for command_pool in command_pools {
-                    device.destroy_command_pool(command_pool.into_raw());
-                }
-            }
-            if let Some(acquire_semaphores) =
-            self.acquire_semaphores.take() {
-                for acquire_semaphore in acquire_semaphores {
-                    device.destroy_semaphore(acquire_semaphore);
-                }
-            }
-            if let Some(present_semaphores) =
-            self.present_semaphores.take() {
-                for present_semaphore in present_semaphores {
-                    device.destroy_semaphore(present_semaphore);
-                }
-            }
             if let Some(frame_buffers) =
             self.frame_buffers.take() {
                 for framebuffer in frame_buffers {
@@ -341,4 +316,48 @@ pub fn select_surface_color_format(formats: Option<Vec<Format>>) -> Format {
             .map(|format| *format)
             .unwrap_or(formats[0])
     })
-}
\ No newline at end of file
+}
+
+/// depth formats in order of preference, the precision of a float depth buffer first
+const DEPTH_FORMATS: [Format; 4] = [
+    Format::D32Sfloat,
+    Format::D32SfloatS8Uint,
+    Format::D24UnormS8Uint,
+    Format::D16Unorm,
+];
+
+/// the first of DEPTH_FORMATS the adapter can use as an optimal tiling depth attachment,
+/// leaving out the ones without a stencil aspect if stencil is required
+pub fn select_depth_format(adapter: &AdapterState, stencil: bool) -> RendererResult<Format> {
+    let candidates: Vec<Format> = DEPTH_FORMATS
+        .iter()
+        .filter(|format| !stencil || format.surface_desc().aspects.contains(Aspects::STENCIL))
+        .map(|format| *format)
+        .collect();
+    candidates
+        .iter()
+        .find(|format| {
+            adapter.adapter.physical_device
+                .format_properties(Some(**format))
+                .optimal_tiling
+                .contains(ImageFeature::DEPTH_STENCIL_ATTACHMENT)
+        })
+        .map(|format| *format)
+        .ok_or_else(|| RendererError::UnsupportedFormat {
+            resource: "depth attachment".to_string(),
+            candidates: candidates.clone(),
+        })
+}
+
+/// the first present mode preferred by the policy that the surface supports
+pub fn select_present_mode(
+    present_mode_policy: PresentModePolicy,
+    present_modes: &[PresentMode],
+) -> PresentMode {
+    present_mode_policy
+        .preferences()
+        .iter()
+        .find(|present_mode| present_modes.contains(present_mode))
+        .map(|present_mode| *present_mode)
+        .unwrap_or(PresentMode::Fifo)
+}
@@ -0,0 +1,51 @@
+use crate::frontend::graphic::data_type::TonemapPushConstants;
+
+/// the fragment shader of the fullscreen pass tonemapping the HDR color of the scene
+pub const TONEMAP_FRAGMENT_SHADER_PATH: &str = "shaders/gen/tonemap.frag.spv";
+
+/// the curve HDR colors are brought into the displayable range with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TonemapOperator {
+    /// c / (1 + c), never clips but washes out bright colors
+    Reinhard,
+    /// the filmic curve of the ACES reference rendering transform, with a toe and a shoulder
+    AcesFilmic,
+    /// only scales by the exposure, for outputs that keep HDR colors
+    Linear,
+}
+
+impl TonemapOperator {
+    /// the operator after this one, to cycle through them at runtime
+    pub fn next(&self) -> TonemapOperator {
+        match self {
+            TonemapOperator::Reinhard => TonemapOperator::AcesFilmic,
+            // Linear clips on a display, it is not cycled to
+            TonemapOperator::AcesFilmic | TonemapOperator::Linear => TonemapOperator::Reinhard,
+        }
+    }
+    
+    /// has to match the OPERATOR_* constants in tonemap.frag
+    fn shader_index(&self) -> u32 {
+        match self {
+            TonemapOperator::Reinhard => 0,
+            TonemapOperator::AcesFilmic => 1,
+            TonemapOperator::Linear => 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TonemapSettings {
+    pub operator: TonemapOperator,
+    // in stops, the HDR color is scaled by 2^exposure before the curve
+    pub exposure: f32,
+}
+
+impl TonemapSettings {
+    pub fn push_constants(&self) -> TonemapPushConstants {
+        TonemapPushConstants {
+            exposure: self.exposure.exp2(),
+            operator: self.operator.shader_index(),
+        }
+    }
+}
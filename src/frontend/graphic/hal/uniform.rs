@@ -0,0 +1,78 @@
+use std::{
+    cell::RefCell,
+    marker::PhantomData,
+    mem,
+    rc::Rc,
+};
+
+use super::{
+    allocator::AllocationStrategy,
+    buffer::{
+        as_bytes,
+        BufferState,
+    },
+    device::DeviceState,
+    error::RendererResult,
+    prelude::*,
+};
+
+/// one uniform block per frame in flight packed into a single host visible buffer,
+/// bound as a UniformBufferDynamic so each frame selects its slot with a dynamic offset
+/// and the CPU never writes a block that an unfinished frame is still reading
+pub struct UniformRingState<I> {
+    buffer_state: BufferState<u8>,
+    // size of I rounded up to minUniformBufferOffsetAlignment
+    slot_size: u64,
+    slot_count: usize,
+    _phantom_data: PhantomData<I>,
+}
+
+impl<I: Sized + Copy> UniformRingState<I> {
+    pub fn new(
+        device_state: Rc<RefCell<DeviceState>>,
+        limits: &Limits,
+        slot_count: usize,
+    ) -> RendererResult<UniformRingState<I>> {
+        let alignment = (limits.min_uniform_buffer_offset_alignment as u64).max(1);
+        let slot_size =
+            (mem::size_of::<I>() as u64 + alignment - 1) / alignment * alignment;
+        
+        let buffer_state = BufferState::new_empty(
+            device_state,
+            slot_size * slot_count as u64,
+            buffer::Usage::UNIFORM,
+            Properties::CPU_VISIBLE,
+            AllocationStrategy::General,
+        )?;
+        
+        Ok(UniformRingState {
+            buffer_state,
+            slot_size,
+            slot_count,
+            _phantom_data: PhantomData,
+        })
+    }
+    
+    pub fn slot_count(&self) -> usize {
+        self.slot_count
+    }
+    
+    /// the caller must have waited on the fence of the frame that last used slot
+    pub fn write(&mut self, slot: usize, item: I) -> RendererResult<()> {
+        assert!(slot < self.slot_count, "uniform ring slot out of range");
+        self.buffer_state.write_bytes(self.slot_size * slot as u64, as_bytes(&[item]))
+    }
+    
+    /// the dynamic offset to bind the descriptor with for slot
+    pub fn dynamic_offset(&self, slot: usize) -> u32 {
+        (self.slot_size * slot as u64) as u32
+    }
+    
+    /// a descriptor covering one slot, the dynamic offset picks which
+    pub fn descriptor(&self) -> Descriptor<B> {
+        Descriptor::Buffer(
+            self.buffer_state.buffer.as_ref().unwrap(),
+            Some(0)..Some(mem::size_of::<I>() as u64),
+        )
+    }
+}
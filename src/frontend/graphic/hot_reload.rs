@@ -0,0 +1,116 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{
+        Path,
+        PathBuf,
+    },
+    time::SystemTime,
+};
+
+use time::Duration;
+
+use super::{
+    hal::error::{
+        RendererError,
+        RendererResult,
+    },
+    texture_registry::TextureHandle,
+};
+
+/// what has to be rebuilt when a watched file changes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchedAsset {
+    Texture(TextureHandle),
+    Mesh,
+    Shader,
+    PostProcessConfig,
+}
+
+struct WatchedFile {
+    modified: Option<SystemTime>,
+    asset: WatchedAsset,
+}
+
+/// polls the modification time of files under the root the GemFileSystem is created with
+///
+/// this goes to std::fs under that root instead of through gfs, which only offers
+/// read_file and keeps giving the content it first read of a path,
+/// neither modification times nor fresh content of a changed file can be had from it
+pub struct FileWatcher {
+    root: PathBuf,
+    files: HashMap<String, WatchedFile>,
+    poll_interval: Duration,
+    last_poll: time::Tm,
+}
+
+impl FileWatcher {
+    pub fn new(root: PathBuf, poll_interval: Duration) -> FileWatcher {
+        FileWatcher {
+            root,
+            files: HashMap::new(),
+            poll_interval,
+            last_poll: time::now(),
+        }
+    }
+    
+    /// the directory watched paths are relative to, the root of the GemFileSystem
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+    
+    /// start watching a path relative to the root,
+    /// watching a path again only replaces what it is associated with
+    pub fn watch(&mut self, path: &str, asset: WatchedAsset) {
+        let modified = self.modified(path);
+        self.files.insert(
+            path.to_string(),
+            WatchedFile {
+                modified,
+                asset,
+            },
+        );
+    }
+    
+    pub fn unwatch(&mut self, path: &str) {
+        self.files.remove(path);
+    }
+    
+    /// the files modified since the last poll,
+    /// returns nothing if called again before the poll interval has passed
+    pub fn poll(&mut self) -> Vec<(String, WatchedAsset)> {
+        if time::now() - self.last_poll < self.poll_interval {
+            return vec![];
+        }
+        self.last_poll = time::now();
+        
+        let root = &self.root;
+        let mut changed = vec![];
+        for (path, watched_file) in self.files.iter_mut() {
+            let modified = fs::metadata(root.join(path))
+                .and_then(|metadata| metadata.modified())
+                .ok();
+            // a file being written may vanish for a moment, wait for it to come back
+            if modified.is_some() && modified != watched_file.modified {
+                watched_file.modified = modified;
+                changed.push((path.clone(), watched_file.asset));
+            }
+        }
+        changed
+    }
+    
+    /// read the current content of a watched file from disk,
+    /// GemFileSystem keeps the content of files it has read before so it cannot be used here
+    pub fn read(&self, path: &str) -> RendererResult<Box<[u8]>> {
+        let full_path = self.root.join(path);
+        fs::read(&full_path)
+            .map(|bytes| bytes.into_boxed_slice())
+            .map_err(|error| RendererError::file(&full_path.display().to_string(), error))
+    }
+    
+    fn modified(&self, path: &str) -> Option<SystemTime> {
+        fs::metadata(self.root.join(path))
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    }
+}
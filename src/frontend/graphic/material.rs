@@ -0,0 +1,158 @@
+use std::str;
+
+use super::hal::sampler::{SamplerDesc, SamplerWrap};
+
+/// options given in front of the file name of a texture map statement in a MTL file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextureOptions {
+    /// -clamp on|off
+    pub clamp: bool,
+    /// -o u v w, offset of the texture coordinates
+    pub offset: [f32; 3],
+    /// -s u v w, scale of the texture coordinates
+    pub scale: [f32; 3],
+}
+
+impl Default for TextureOptions {
+    fn default() -> TextureOptions {
+        TextureOptions {
+            clamp: false,
+            offset: [0.0, 0.0, 0.0],
+            scale: [1.0, 1.0, 1.0],
+        }
+    }
+}
+
+impl TextureOptions {
+    /// the sampler these options ask for,
+    /// clamped textures must not repeat whatever their scale is
+    pub fn sampler_desc(&self) -> SamplerDesc {
+        let wrap = if self.clamp {
+            SamplerWrap::Clamp
+        } else {
+            SamplerWrap::Repeat
+        };
+        SamplerDesc::default().with_wrap(wrap)
+    }
+    
+    /// the scale in xy and the offset in zw the texture coordinates of the mesh are transformed
+    /// with before sampling, w of -o and -s is left out since every map is a 2D texture,
+    /// vertices_from_obj flips v so the transform of v is flipped to match
+    pub fn uv_transform(&self) -> [f32; 4] {
+        [
+            self.scale[0],
+            self.scale[1],
+            self.offset[0],
+            1.0 - self.scale[1] - self.offset[1],
+        ]
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextureMap {
+    /// path under res/
+    pub path: String,
+    pub options: TextureOptions,
+}
+
+/// the texture maps of the first material in a MTL file
+#[derive(Debug, Clone, Default)]
+pub struct MaterialMaps {
+    pub name: String,
+    pub diffuse: Option<TextureMap>,
+    pub specular: Option<TextureMap>,
+    pub normal: Option<TextureMap>,
+}
+
+impl MaterialMaps {
+    /// parse a MTL file, texture paths are made relative to res/ by prefixing them with directory
+    pub fn parse(mtl_file: &[u8], directory: &str) -> Result<MaterialMaps, String> {
+        let source = str::from_utf8(mtl_file)
+            .map_err(|error| format!("MTL file is not utf-8: {}", error))?;
+        let mut maps = MaterialMaps::default();
+        for (line_number, line) in source.lines().enumerate() {
+            let mut tokens = line.split_whitespace();
+            let statement = match tokens.next() {
+                Some(statement) => statement,
+                None => continue,
+            };
+            let arguments = tokens.collect::<Vec<_>>();
+            let texture_map = || parse_texture_map(&arguments, directory)
+                .map_err(|error| format!("line {}: {}", line_number + 1, error));
+            match statement {
+                "newmtl" => {
+                    // only the first material is used for now
+                    if !maps.name.is_empty() {
+                        break;
+                    }
+                    maps.name = arguments.join(" ");
+                }
+                "map_Kd" => maps.diffuse = Some(texture_map()?),
+                "map_Ks" => maps.specular = Some(texture_map()?),
+                "map_Kn" | "map_bump" | "bump" | "norm" => maps.normal = Some(texture_map()?),
+                _ => (),
+            }
+        }
+        Ok(maps)
+    }
+}
+
+fn parse_texture_map(arguments: &[&str], directory: &str) -> Result<TextureMap, String> {
+    let mut options = TextureOptions::default();
+    let mut index = 0;
+    while index < arguments.len() && arguments[index].starts_with('-') {
+        let option = arguments[index];
+        index += 1;
+        match option {
+            "-clamp" => {
+                options.clamp = match arguments.get(index) {
+                    Some(&"on") => true,
+                    Some(&"off") => false,
+                    _ => return Err("-clamp expects on or off".to_string()),
+                };
+                index += 1;
+            }
+            "-o" | "-s" => {
+                let (mut values, default) = if option == "-o" {
+                    ([0.0; 3], 0.0)
+                } else {
+                    ([1.0; 3], 1.0)
+                };
+                // v and w are optional
+                for component in 0..3 {
+                    match arguments.get(index).and_then(|value| value.parse::<f32>().ok()) {
+                        Some(value) => {
+                            values[component] = value;
+                            index += 1;
+                        }
+                        None if component > 0 => values[component] = default,
+                        None => return Err(format!("{} expects at least one number", option)),
+                    }
+                }
+                if option == "-o" {
+                    options.offset = values;
+                } else {
+                    options.scale = values;
+                }
+            }
+            // options taking one argument that are accepted but not used
+            "-blendu" | "-blendv" | "-cc" | "-texres" | "-imfchan" | "-bm" => index += 1,
+            "-mm" => index += 2,
+            "-t" => index += 3,
+            _ => return Err(format!("unknown texture option {}", option)),
+        }
+    }
+    if index >= arguments.len() {
+        return Err("texture map without a file name".to_string());
+    }
+    let file_name = arguments[index..].join(" ");
+    let path = if directory.is_empty() {
+        file_name
+    } else {
+        format!("{}/{}", directory, file_name)
+    };
+    Ok(TextureMap {
+        path,
+        options,
+    })
+}
@@ -0,0 +1,69 @@
+use std::io::Cursor;
+
+use obj::{
+    IndexTuple,
+    Obj,
+};
+
+use super::data_type::Vertex;
+
+/// flatten every polygon of a wavefront obj file into a triangle list,
+/// faces with more than five vertices and faces without normal or texture
+/// coordinates are reported as errors instead of being skipped
+pub fn vertices_from_obj(model_file: &[u8]) -> Result<Vec<Vertex>, String> {
+    let obj: Obj<'_, Vec<IndexTuple>> = Obj::load_buf(&mut Cursor::new(model_file))
+        .map_err(|error| format!("cannot parse obj: {:?}", error))?;
+    
+    let mut vertices: Vec<Vertex> = vec![];
+    for object in obj.objects {
+        for group in object.groups {
+            for polys in group.polys {
+                let mut vertex_for_this_face: Vec<Vertex> = vec![];
+                for face in polys {
+                    let texture_index = face.1
+                        .ok_or_else(|| "face without texture coordinate".to_string())?;
+                    let normal_index = face.2
+                        .ok_or_else(|| "face without normal".to_string())?;
+                    vertex_for_this_face.push(Vertex {
+                        position: obj.position[face.0],
+                        normal: obj.normal[normal_index],
+                        tangent: [0.0, 0.0, 0.0],
+                        texture:
+                        [
+                            obj.texture[texture_index][0],
+                            1.0 - obj.texture[texture_index][1]
+                        ],
+                    });
+                }
+                match vertex_for_this_face.len() {
+                    3 => {
+                        vertices.push(vertex_for_this_face[0]);
+                        vertices.push(vertex_for_this_face[1]);
+                        vertices.push(vertex_for_this_face[2]);
+                    }
+                    4 => {
+                        vertices.push(vertex_for_this_face[0]);
+                        vertices.push(vertex_for_this_face[1]);
+                        vertices.push(vertex_for_this_face[2]);
+                        vertices.push(vertex_for_this_face[0]);
+                        vertices.push(vertex_for_this_face[2]);
+                        vertices.push(vertex_for_this_face[3]);
+                    }
+                    5 => {
+                        vertices.push(vertex_for_this_face[0]);
+                        vertices.push(vertex_for_this_face[1]);
+                        vertices.push(vertex_for_this_face[2]);
+                        vertices.push(vertex_for_this_face[0]);
+                        vertices.push(vertex_for_this_face[2]);
+                        vertices.push(vertex_for_this_face[4]);
+                        vertices.push(vertex_for_this_face[2]);
+                        vertices.push(vertex_for_this_face[4]);
+                        vertices.push(vertex_for_this_face[3]);
+                    }
+                    count => return Err(format!("face with {} vertices", count)),
+                };
+            }
+        }
+    }
+    Ok(vertices)
+}
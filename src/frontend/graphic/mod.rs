@@ -3,4 +3,14 @@ pub mod constants;
 pub mod window;
 //pub mod render_system;
 pub mod hal;
-pub mod data_type;
\ No newline at end of file
+pub mod data_type;
+pub mod environment;
+pub mod texture_registry;
+pub mod hot_reload;
+pub mod mesh;
+pub mod material;
+pub mod capture;
+pub mod post_process;
+pub mod shader_compiler;
+pub mod shader_preprocessor;
+pub mod shader_variant;
\ No newline at end of file
@@ -0,0 +1,542 @@
+use std::{
+    cell::RefCell,
+    mem,
+    rc::Rc,
+    str,
+};
+
+use gfs::GemFileSystem;
+
+use crate::frontend::graphic::data_type::TonemapPushConstants;
+use crate::frontend::graphic::hal::{
+    adapter::AdapterState,
+    device::DeviceState,
+    error::RendererResult,
+    fullscreen::{
+        FullscreenPassDesc,
+        FullscreenPassState,
+    },
+    image::SamplerState,
+    prelude::*,
+    render_graph::{
+        PassContext,
+        PassDesc,
+        PassId,
+        RenderGraphBuilder,
+        RenderGraphState,
+        ResourceId,
+        TransientDesc,
+        scaled_extent,
+    },
+    sampler::{
+        SamplerCache,
+        SamplerDesc,
+        SamplerFilter,
+        SamplerWrap,
+    },
+    shader_module::ShaderCache,
+    tonemap::{
+        TONEMAP_FRAGMENT_SHADER_PATH,
+        TonemapOperator,
+        TonemapSettings,
+    },
+};
+
+use super::constants::{
+    MAX_BLOOM_LEVELS,
+    POST_PROCESS_FORMAT,
+};
+use super::material::TextureOptions;
+use super::texture_registry::{
+    TextureHandle,
+    TextureRegistry,
+};
+
+pub const FXAA_FRAGMENT_SHADER_PATH: &str = "shaders/gen/fxaa.frag.spv";
+pub const BLOOM_PREFILTER_FRAGMENT_SHADER_PATH: &str = "shaders/gen/bloom_prefilter.frag.spv";
+pub const BLOOM_DOWNSAMPLE_FRAGMENT_SHADER_PATH: &str = "shaders/gen/bloom_downsample.frag.spv";
+pub const BLOOM_UPSAMPLE_FRAGMENT_SHADER_PATH: &str = "shaders/gen/bloom_upsample.frag.spv";
+pub const BLOOM_COMPOSITE_FRAGMENT_SHADER_PATH: &str = "shaders/gen/bloom_composite.frag.spv";
+pub const VIGNETTE_FRAGMENT_SHADER_PATH: &str = "shaders/gen/vignette.frag.spv";
+pub const COLOR_GRADING_FRAGMENT_SHADER_PATH: &str = "shaders/gen/color_grading.frag.spv";
+pub const SHARPEN_FRAGMENT_SHADER_PATH: &str = "shaders/gen/sharpen.frag.spv";
+
+/// a fullscreen pass of the chain, the parameters are documented in its shader under res/shaders
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub enum PostEffect {
+    /// brings the HDR color into the displayable range with the settings of the renderer,
+    /// effects before it work on HDR colors and effects after it on displayable ones
+    Tonemap,
+    Fxaa {
+        span_max: f32,
+        reduce_mul: f32,
+        reduce_min: f32,
+    },
+    /// adds a blurred copy of what is brighter than threshold,
+    /// levels is the number of times, at most MAX_BLOOM_LEVELS, the bright parts are halved in size before being blurred back up
+    Bloom {
+        threshold: f32,
+        intensity: f32,
+        levels: usize,
+    },
+    Vignette {
+        intensity: f32,
+        radius: f32,
+        softness: f32,
+    },
+    /// lut is the path under res/ of a size * size by size strip of size by size slices
+    ColorGrading {
+        lut: String,
+        size: u32,
+        intensity: f32,
+    },
+    Sharpen {
+        strength: f32,
+    },
+}
+
+/// the effects applied to the scene, in order, read from a RON file
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct PostProcessConfig {
+    pub effects: Vec<PostEffect>,
+}
+
+impl PostProcessConfig {
+    /// parse a RON encoded config, Tonemap is put first if it is not in the list
+    pub fn parse(config_file: &[u8]) -> Result<PostProcessConfig, String> {
+        let source = str::from_utf8(config_file)
+            .map_err(|error| format!("post-processing config is not utf-8: {}", error))?;
+        let mut config = ron::de::from_str::<PostProcessConfig>(source)
+            .map_err(|error| error.to_string())?;
+        
+        match config.effects.iter().filter(|effect| **effect == PostEffect::Tonemap).count() {
+            0 => config.effects.insert(0, PostEffect::Tonemap),
+            1 => (),
+            _ => return Err("Tonemap can only be applied once".to_string()),
+        }
+        for effect in config.effects.iter() {
+            match effect {
+                PostEffect::Bloom { levels: 0, .. } =>
+                    return Err("Bloom needs at least one level".to_string()),
+                PostEffect::Bloom { levels, .. } if *levels > MAX_BLOOM_LEVELS =>
+                    return Err(format!("Bloom cannot have more than {} levels", MAX_BLOOM_LEVELS)),
+                PostEffect::ColorGrading { size, .. } if *size < 2 =>
+                    return Err("the LUT of ColorGrading needs at least 2 texels along every axis".to_string()),
+                _ => (),
+            }
+        }
+        Ok(config)
+    }
+    
+    /// the effects up to and including Tonemap, those that work on HDR colors
+    pub fn up_to_tonemap(&self) -> PostProcessConfig {
+        let tonemap_index = self.effects
+            .iter()
+            .position(|effect| *effect == PostEffect::Tonemap)
+            .unwrap_or(self.effects.len());
+        PostProcessConfig {
+            effects: self.effects[..(tonemap_index + 1).min(self.effects.len())].to_vec(),
+        }
+    }
+}
+
+/// the image the last pass of the chain writes to, the output of the render graph
+#[derive(Debug, Clone, Copy)]
+pub struct PostProcessOutput {
+    pub format: Format,
+    // the layout the image is left in
+    pub layout: Layout,
+    pub extent: Extent2D,
+    // the image keeps linear HDR colors, only the effects up to Tonemap are applied
+    // and Tonemap only scales by the exposure
+    pub hdr: bool,
+}
+
+enum PassPushConstants {
+    // known once the size of the targets is
+    Fixed(Vec<u32>),
+    // follows the tonemap settings given to every record
+    Tonemap,
+}
+
+/// a pass of the chain before anything has been created for it
+struct PassPlan {
+    fragment_shader_path: &'static str,
+    inputs: Vec<ResourceId>,
+    output: ResourceId,
+    push_constants: PassPushConstants,
+    // path of the LUT of a color grading pass
+    lut: Option<String>,
+}
+
+struct PostProcessPass {
+    pass_id: PassId,
+    fullscreen_pass_state: FullscreenPassState,
+    inputs: Vec<ResourceId>,
+    push_constants: PassPushConstants,
+    // the texture bound at set 1 of a color grading pass
+    lut: Option<TextureHandle>,
+}
+
+/// the fullscreen passes turning the HDR color of the scene into the output image,
+/// added to a render graph which creates the images passed between them
+pub struct PostProcessChainState {
+    passes: Vec<PostProcessPass>,
+    // see PostProcessOutput
+    hdr: bool,
+}
+
+impl PostProcessChainState {
+    /// create the passes of config and add them to render_graph, the first reading scene
+    /// and the last writing the output of render_graph,
+    /// the color grading LUTs are loaded into texture_registry and have to be uploaded
+    /// with flush_uploads before the first record
+    pub fn new(
+        device_state: Rc<RefCell<DeviceState>>,
+        config: &PostProcessConfig,
+        render_graph: &mut RenderGraphBuilder,
+        scene: ResourceId,
+        output: PostProcessOutput,
+        gfs: &mut GemFileSystem,
+        shader_cache: &ShaderCache,
+        adapter_state: &AdapterState,
+        sampler_cache: &mut SamplerCache,
+        texture_registry: &mut TextureRegistry,
+    ) -> RendererResult<PostProcessChainState> {
+        let plans = if output.hdr {
+            plan_passes(&config.up_to_tonemap(), render_graph, scene, output.extent)
+        } else {
+            plan_passes(config, render_graph, scene, output.extent)
+        };
+        
+        let mut post_process_chain_state = PostProcessChainState {
+            passes: vec![],
+            hdr: output.hdr,
+        };
+        
+        let sampler_state = sampler_cache.get(
+            SamplerDesc::default()
+                .with_wrap(SamplerWrap::Clamp)
+                .with_filter(SamplerFilter::Linear)
+        )?;
+        for plan in plans {
+            let added = post_process_chain_state.add_pass(
+                device_state.clone(),
+                plan,
+                render_graph,
+                output,
+                &sampler_state,
+                gfs,
+                shader_cache,
+                adapter_state,
+                sampler_cache,
+                texture_registry,
+            );
+            // the LUTs of the passes added so far are not released by dropping the chain
+            if let Err(error) = added {
+                post_process_chain_state.release_luts(texture_registry);
+                return Err(error);
+            }
+        }
+        
+        Ok(post_process_chain_state)
+    }
+    
+    /// create the pass of plan and load its LUT, which is only done once nothing else can fail
+    fn add_pass(
+        &mut self,
+        device_state: Rc<RefCell<DeviceState>>,
+        plan: PassPlan,
+        render_graph: &mut RenderGraphBuilder,
+        output: PostProcessOutput,
+        sampler_state: &Rc<SamplerState>,
+        gfs: &mut GemFileSystem,
+        shader_cache: &ShaderCache,
+        adapter_state: &AdapterState,
+        sampler_cache: &mut SamplerCache,
+        texture_registry: &mut TextureRegistry,
+    ) -> RendererResult<()> {
+        let (output_format, output_layout) = if plan.output == render_graph.output() {
+            (output.format, output.layout)
+        } else {
+            (POST_PROCESS_FORMAT, Layout::ShaderReadOnlyOptimal)
+        };
+        let push_constants_size = match &plan.push_constants {
+            PassPushConstants::Fixed(words) => words.len() * mem::size_of::<u32>(),
+            PassPushConstants::Tonemap => mem::size_of::<TonemapPushConstants>(),
+        };
+        let fullscreen_pass_state = FullscreenPassState::new(
+            device_state,
+            &FullscreenPassDesc {
+                fragment_shader_path: plan.fragment_shader_path,
+                input_count: plan.inputs.len(),
+                extra_descriptor_states: if plan.lut.is_some() {
+                    vec![texture_registry.descriptor_state()]
+                } else {
+                    vec![]
+                },
+                push_constants_size,
+                output_format,
+                output_layout,
+            },
+            sampler_state.clone(),
+            gfs,
+            shader_cache,
+        )?;
+        
+        let lut = match plan.lut {
+            Some(path) => Some(texture_registry.load(
+                gfs,
+                adapter_state,
+                sampler_cache,
+                &path,
+                TextureOptions {
+                    clamp: true,
+                    ..Default::default()
+                },
+            )?),
+            None => None,
+        };
+        let pass_id = render_graph.add_pass(PassDesc {
+            sampled: plan.inputs.clone(),
+            color: plan.output,
+            depth: None,
+            resolve: None,
+        });
+        self.passes.push(PostProcessPass {
+            pass_id,
+            fullscreen_pass_state,
+            inputs: plan.inputs,
+            push_constants: plan.push_constants,
+            lut,
+        });
+        Ok(())
+    }
+    
+    /// point the passes at the images render_graph_state created for what they read,
+    /// to be done whenever the graph the chain was added to is compiled
+    pub fn write_inputs(&self, render_graph_state: &RenderGraphState) {
+        for pass in self.passes.iter() {
+            let input_image_views: Vec<_> = pass.inputs
+                .iter()
+                .map(|input| render_graph_state.image_view(*input))
+                .collect();
+            pass.fullscreen_pass_state.write_inputs(&input_image_views);
+        }
+    }
+    
+    /// the LUTs of the chain, to watch them for changes
+    pub fn luts(&self) -> Vec<TextureHandle> {
+        self.passes.iter().filter_map(|pass| pass.lut).collect()
+    }
+    
+    /// give back the LUTs loaded by new, to be called before the chain is dropped
+    pub fn release_luts(&mut self, texture_registry: &mut TextureRegistry) {
+        for pass in self.passes.iter_mut() {
+            if let Some(lut) = pass.lut.take() {
+                texture_registry.release(lut);
+            }
+        }
+    }
+    
+    /// record the pass of the chain the render graph has come to
+    pub unsafe fn record_pass(
+        &self,
+        pass_id: PassId,
+        command_buffer: &mut CommandBuffer<B, Graphics, OneShot>,
+        context: &PassContext<'_>,
+        tonemap_settings: TonemapSettings,
+        texture_registry: &TextureRegistry,
+    ) {
+        let pass = self.passes
+            .iter()
+            .find(|pass| pass.pass_id == pass_id)
+            .expect("not a pass of the post-processing chain");
+        let extra_descriptor_sets: Vec<_> = pass.lut
+            .iter()
+            .map(|lut| texture_registry.descriptor_set(*lut))
+            .collect();
+        let tonemap_push_constants = if self.hdr {
+            TonemapSettings {
+                operator: TonemapOperator::Linear,
+                ..tonemap_settings
+            }.push_constants()
+        } else {
+            tonemap_settings.push_constants()
+        };
+        let push_constants = match &pass.push_constants {
+            PassPushConstants::Fixed(words) => words.as_slice(),
+            PassPushConstants::Tonemap => tonemap_push_constants.as_words(),
+        };
+        pass.fullscreen_pass_state.record(
+            command_buffer,
+            context,
+            &extra_descriptor_sets,
+            push_constants,
+        );
+    }
+}
+
+/// the passes for the effects of config, every pass but the last writes to a new transient
+/// of render_graph, which are given the same images wherever their passes do not overlap
+fn plan_passes(
+    config: &PostProcessConfig,
+    render_graph: &mut RenderGraphBuilder,
+    scene: ResourceId,
+    extent: Extent2D,
+) -> Vec<PassPlan> {
+    let mut plans = vec![];
+    let mut current = scene;
+    
+    for (effect_index, effect) in config.effects.iter().enumerate() {
+        let output = if effect_index + 1 == config.effects.len() {
+            render_graph.output()
+        } else {
+            render_graph.create_transient(post_process_transient(0))
+        };
+        
+        let single_pass = |
+            fragment_shader_path: &'static str,
+            push_constants: PassPushConstants,
+            lut: Option<String>,
+        | PassPlan {
+            fragment_shader_path,
+            inputs: vec![current],
+            output,
+            push_constants,
+            lut,
+        };
+        match effect {
+            PostEffect::Tonemap => {
+                plans.push(single_pass(TONEMAP_FRAGMENT_SHADER_PATH, PassPushConstants::Tonemap, None));
+            }
+            PostEffect::Fxaa { span_max, reduce_mul, reduce_min } => {
+                let push_constants = texel_size(extent)
+                    .iter()
+                    .chain(&[*span_max, *reduce_mul, *reduce_min])
+                    .map(|value| value.to_bits())
+                    .collect();
+                plans.push(single_pass(
+                    FXAA_FRAGMENT_SHADER_PATH,
+                    PassPushConstants::Fixed(push_constants),
+                    None,
+                ));
+            }
+            PostEffect::Bloom { threshold, intensity, levels } => {
+                // every level of the pyramid is half the size of the one above it
+                let down_levels: Vec<_> = (1..=*levels as u32)
+                    .map(|downscale| {
+                        let level = render_graph.create_transient(post_process_transient(downscale));
+                        (level, downscale)
+                    })
+                    .collect();
+                
+                let prefilter_push_constants = texel_size(extent)
+                    .iter()
+                    .chain(&[*threshold])
+                    .map(|value| value.to_bits())
+                    .collect();
+                plans.push(PassPlan {
+                    fragment_shader_path: BLOOM_PREFILTER_FRAGMENT_SHADER_PATH,
+                    inputs: vec![current],
+                    output: down_levels[0].0,
+                    push_constants: PassPushConstants::Fixed(prefilter_push_constants),
+                    lut: None,
+                });
+                for level in down_levels.windows(2) {
+                    plans.push(PassPlan {
+                        fragment_shader_path: BLOOM_DOWNSAMPLE_FRAGMENT_SHADER_PATH,
+                        inputs: vec![level[0].0],
+                        output: level[1].0,
+                        push_constants: PassPushConstants::Fixed(
+                            texel_size(scaled_extent(extent, level[0].1))
+                                .iter()
+                                .map(|value| value.to_bits())
+                                .collect()
+                        ),
+                        lut: None,
+                    });
+                }
+                
+                // each level is added to the blurred one below it, from the smallest up
+                let mut smaller_level = *down_levels.last().unwrap();
+                for (level, downscale) in down_levels.iter().rev().skip(1) {
+                    let up_level = render_graph.create_transient(post_process_transient(*downscale));
+                    plans.push(PassPlan {
+                        fragment_shader_path: BLOOM_UPSAMPLE_FRAGMENT_SHADER_PATH,
+                        inputs: vec![*level, smaller_level.0],
+                        output: up_level,
+                        push_constants: PassPushConstants::Fixed(
+                            texel_size(scaled_extent(extent, smaller_level.1))
+                                .iter()
+                                .map(|value| value.to_bits())
+                                .collect()
+                        ),
+                        lut: None,
+                    });
+                    smaller_level = (up_level, *downscale);
+                }
+                
+                plans.push(PassPlan {
+                    fragment_shader_path: BLOOM_COMPOSITE_FRAGMENT_SHADER_PATH,
+                    inputs: vec![current, smaller_level.0],
+                    output,
+                    push_constants: PassPushConstants::Fixed(vec![intensity.to_bits()]),
+                    lut: None,
+                });
+            }
+            PostEffect::Vignette { intensity, radius, softness } => {
+                let aspect_ratio = extent.width as f32 / extent.height as f32;
+                let push_constants = [*intensity, *radius, *softness, aspect_ratio]
+                    .iter()
+                    .map(|value| value.to_bits())
+                    .collect();
+                plans.push(single_pass(
+                    VIGNETTE_FRAGMENT_SHADER_PATH,
+                    PassPushConstants::Fixed(push_constants),
+                    None,
+                ));
+            }
+            PostEffect::ColorGrading { lut, size, intensity } => {
+                let push_constants = [*size as f32, *intensity]
+                    .iter()
+                    .map(|value| value.to_bits())
+                    .collect();
+                plans.push(single_pass(
+                    COLOR_GRADING_FRAGMENT_SHADER_PATH,
+                    PassPushConstants::Fixed(push_constants),
+                    Some(lut.clone()),
+                ));
+            }
+            PostEffect::Sharpen { strength } => {
+                let push_constants = texel_size(extent)
+                    .iter()
+                    .chain(&[*strength])
+                    .map(|value| value.to_bits())
+                    .collect();
+                plans.push(single_pass(
+                    SHARPEN_FRAGMENT_SHADER_PATH,
+                    PassPushConstants::Fixed(push_constants),
+                    None,
+                ));
+            }
+        }
+        
+        current = output;
+    }
+    plans
+}
+
+/// an image passed between the passes of the chain
+fn post_process_transient(downscale: u32) -> TransientDesc {
+    TransientDesc {
+        format: POST_PROCESS_FORMAT,
+        samples: 1,
+        downscale,
+    }
+}
+
+/// the size of a texel in texture coordinates
+fn texel_size(extent: Extent2D) -> [f32; 2] {
+    [1.0 / extent.width as f32, 1.0 / extent.height as f32]
+}
@@ -1,6 +1,7 @@
 use std::{
     cell::RefCell,
     iter,
+    mem,
     rc::Rc,
 };
 use std::io::BufReader;
@@ -16,6 +17,7 @@ use obj::{
     IndexTuple,
     Obj,
 };
+use time::Duration;
 
 use crate::{
     frontend::{
@@ -23,19 +25,96 @@ use crate::{
             hal::{
                 image::SampledImageState,
                 adapter::AdapterState,
-                render_pass::RenderPassState,
-                pipeline::ObjectPso,
+                render_pass::{
+                    RenderPassState,
+                    select_sample_count,
+                },
+                pipeline::{
+                    ObjectPso,
+                    ObjectShaderPaths,
+                    UNIFORM_SET,
+                    VERT_UNIFORM_BINDING,
+                    FRAG_UNIFORM_BINDING,
+                },
                 swapchain::{SwapchainState,
-                            FrameBufferState},
+                            FrameBufferState,
+                            PresentModePolicy,
+                            select_depth_format},
+                offscreen::OffscreenTargetState,
+                shader_module::ShaderCache,
+                render_graph::{
+                    PassContext,
+                    PassDesc,
+                    PassId,
+                    RenderGraphBuilder,
+                    RenderGraphState,
+                    TransientDesc,
+                },
+                tonemap::TonemapSettings,
                 device::DeviceState,
+                frame::{
+                    FrameContext,
+                    FrameContextsState,
+                },
+                allocator::MemoryReport,
+                error::{
+                    RendererError,
+                    RendererResult,
+                },
                 prelude::*,
                 descriptor::{
                     DescriptorState,
                     DescriptorPoolState,
+                    descriptor_ranges,
                 },
                 buffer::BufferState,
+                dynamic_buffer::DynamicBufferState,
+                uniform::UniformRingState,
+                sampler::{
+                    SamplerCache,
+                    SamplerDesc,
+                    SamplerFilter,
+                    SamplerWrap,
+                },
             },
             data_type::*,
+            environment::{
+                HdrImage,
+                equirectangular_to_cubemap,
+            },
+            texture_registry::{
+                TextureHandle,
+                TextureRegistry,
+            },
+            hot_reload::{
+                FileWatcher,
+                WatchedAsset,
+            },
+            mesh::vertices_from_obj,
+            shader_compiler,
+            shader_variant::{
+                self,
+                MaterialFeatures,
+                MeshAttributes,
+                ShaderVariants,
+                OBJECT_FRAGMENT_SOURCE_PATH,
+                OBJECT_VERTEX_SOURCE_PATH,
+            },
+            capture::{
+                OffscreenImage,
+                OffscreenOutput,
+                PendingScreenshot,
+                ScreenshotThread,
+            },
+            post_process::{
+                PostProcessChainState,
+                PostProcessConfig,
+                PostProcessOutput,
+            },
+            material::{
+                MaterialMaps,
+                TextureMap,
+            },
         }
     },
     lib::{
@@ -57,196 +136,230 @@ pub struct RendererState {
     pub adapter_state: AdapterState,
     // flag
     pub rebuild_swapchain: bool,
-    
+    // applied the next time the swapchain is built
+    present_mode_policy: PresentModePolicy,
+    tonemap_settings: TonemapSettings,
+    // kept to create the chain again when the swapchain is rebuilt
+    post_process_config: PostProcessConfig,
+    // the next frame is copied to a screenshot
+    screenshot_requested: bool,
+    // threads encoding screenshots, joined once done or before the renderer goes away
+    screenshot_threads: Vec<ScreenshotThread>,
+
     // The Following are the ones implemented Drop
     // since they own part of memory on device
     // and dropping them need to call device.destory_*
     // Thus the order of dropping matters,
     // which is reflected as the order of members here!
+    // screenshots waiting for the fence of their frame
+    pending_screenshots: Vec<PendingScreenshot>,
     vertex_buffer: BufferState<Vertex>,
-    vert_uniform_buffer: BufferState<VertUniformBlock>,
+    // one slot per frame in flight, selected with a dynamic offset
+    vert_uniform_ring: UniformRingState<VertUniformBlock>,
     frag_uniform_buffer: BufferState<FragUniformBlock>,
     //indices_buffer: BufferState<u32>,
 
     uniform_descriptor_state: DescriptorState,
-    normal_descriptor_state: DescriptorState,
-    diffuse_descriptor_state: DescriptorState,
-    specular_descriptor_state: DescriptorState,
-    
-    normal_descriptor_pool_state: DescriptorPoolState,
-    diffuse_descriptor_pool_state: DescriptorPoolState,
-    specular_descriptor_pool_state: DescriptorPoolState,
     uniform_descriptor_pool_state: DescriptorPoolState,
     
-    normal_image_state: SampledImageState,
-    diffuse_image_state: SampledImageState,
-    specular_image_state: SampledImageState,
+    // none for a map the material does not have
+    normal_texture: Option<TextureHandle>,
+    diffuse_texture: Option<TextureHandle>,
+    specular_texture: Option<TextureHandle>,
+    texture_registry: TextureRegistry,
+    sampler_cache: SamplerCache,
+    // cube image converted from an equirectangular environment map, if one has been loaded
+    environment_image_state: Option<SampledImageState>,
     
     gfs: GemFileSystem,
+    // SPIR-V of the shaders compiled since startup, read before the files built into res/
+    shader_cache: ShaderCache,
+    // the defines every shader source has been compiled with by build.rs
+    shader_variants: ShaderVariants,
+    // the vertex and fragment shader sources under res/, compiled again when what they include changes
+    shader_source_paths: Vec<String>,
+    file_watcher: FileWatcher,
+    // the variants of object.vert and object.frag for the maps the material of the model has
+    object_shader_paths: ObjectShaderPaths,
     object_pso: ObjectPso,
+    // turns the HDR color of the scene into the swapchain image or offscreen_target_state
+    post_process_chain_state: PostProcessChainState,
+    // the passes of a frame and the images passed between them, sized like the output
+    render_graph_state: RenderGraphState,
+    // the pass of render_graph_state rendering the scene
+    scene_pass: PassId,
+    // object_pso is created for it, the scene pass of render_graph_state is compatible with it
     render_pass_state: RenderPassState,
     swapchain_state: Option<SwapchainState>,
-    frame_buffer_state: FrameBufferState,
+    frame_buffer_state: Option<FrameBufferState>,
+    // what a headless renderer tonemaps to instead of the swapchain
+    offscreen_target_state: Option<OffscreenTargetState>,
+    // what render_offscreen reads back, unused with a surface
+    offscreen_output: OffscreenOutput,
+    // fences, semaphores and command pools of the frames in flight
+    frame_contexts_state: FrameContextsState,
     device_state: Rc<RefCell<DeviceState>>,
     // Instance should be drop
     // after there is no struct with device memory left
     instance: backend::Instance,
-    surface: <B as TB>::Surface,
+    // none for a headless renderer
+    surface: Option<<B as TB>::Surface>,
 }
 
 impl RendererState {
+    /// a renderer presenting to the window
     pub fn new(
         window_state: &WindowState,
         render_size: Extent2D,
-    ) -> RendererState {
+    ) -> RendererResult<RendererState> {
         let window = &window_state.window;
         let instance = backend::Instance::create(INSTANCE_NAME, 1);
-        let mut surface = instance.create_surface(&window);
-        
-        let adapter_state = AdapterState::new(&mut instance.enumerate_adapters());
+        let surface = instance.create_surface(&window);
+        RendererState::new_with_surface(
+            instance,
+            Some(surface),
+            render_size,
+            OffscreenOutput::Display,
+        )
+    }
+    
+    /// a renderer without any window or surface,
+    /// which renders render_size images of output with render_offscreen
+    pub fn new_headless(
+        render_size: Extent2D,
+        output: OffscreenOutput,
+    ) -> RendererResult<RendererState> {
+        let instance = backend::Instance::create(INSTANCE_NAME, 1);
+        RendererState::new_with_surface(instance, None, render_size, output)
+    }
     
+    fn new_with_surface(
+        instance: backend::Instance,
+        mut surface: Option<<B as TB>::Surface>,
+        render_size: Extent2D,
+        offscreen_output: OffscreenOutput,
+    ) -> RendererResult<RendererState> {
+        let adapter_state = AdapterState::new(&mut instance.enumerate_adapters())?;
+        
         let device_state = Rc::new(
             RefCell::new(
-                DeviceState::new(&adapter_state, &surface)
+                DeviceState::new(&adapter_state, surface.as_ref())?
             )
         );
     
         let root = util::application_root::application_root_dir().unwrap();
     
         dbg!(root.clone());
+        // gfs and the file watcher share the root, every path under res/ names the same file for both
+        let res_root = root.join(&"res");
         let mut gfs = GemFileSystem::new(
-            root.join(&"res")
-        );
-    
-    
-        let mut swapchain_state = SwapchainState::new(
-            device_state.clone(),
-            &adapter_state,
-            &mut surface,
-            render_size,
+            res_root.clone()
         );
-    
-        let render_pass_state = RenderPassState::new(
-            device_state.clone(),
-            &swapchain_state,
+        let mut file_watcher = FileWatcher::new(
+            res_root,
+            Duration::milliseconds(HOT_RELOAD_POLL_INTERVAL_MS),
         );
+        let shader_cache = ShaderCache::new();
+        let shader_variants = ShaderVariants::load(&mut gfs)?;
     
-        let frame_buffer_state = FrameBufferState::new(
-            device_state.clone(),
-            &adapter_state,
-            &render_pass_state,
-            &mut swapchain_state,
-        );
     
-        let mut uniform_descriptor_pool_state = DescriptorPoolState::new(
-            device_state.clone(),
-            &[
-                DescriptorRangeDesc {
-                    ty: DescriptorType::UniformBuffer,
-                    count: 2,
-                }
-            ],
-        );
-        let mut diffuse_descriptor_pool_state = DescriptorPoolState::new(
-            device_state.clone(),
-            &[
-                DescriptorRangeDesc {
-                    ty: DescriptorType::CombinedImageSampler,
-                    count: 1,
-                },
-            ],
-        );
-        let mut normal_descriptor_pool_state = DescriptorPoolState::new(
-            device_state.clone(),
-            &[
-                DescriptorRangeDesc {
-                    ty: DescriptorType::CombinedImageSampler,
-                    count: 1,
-                },
-            ],
-        );
-        let mut specular_descriptor_pool_state = DescriptorPoolState::new(
-            device_state.clone(),
-            &[
-                DescriptorRangeDesc {
-                    ty: DescriptorType::CombinedImageSampler,
-                    count: 1,
-                },
-            ],
-        );
-        let mut uniform_descriptor_state = DescriptorState::new(
+        let present_mode_policy = DEFAULT_PRESENT_MODE_POLICY;
+        let samples = select_sample_count(&adapter_state.limits, MSAA_SAMPLES);
+        println!("[INFO][Chosen Sample Count] {}", samples);
+        
+        let mut sampler_cache = SamplerCache::new(
             device_state.clone(),
-            &[
-                DescriptorSetLayoutBinding {
-                    binding: 0,
-                    ty: DescriptorType::UniformBuffer,
-                    // the following field cannot be 0 or it will panic when allocatting memory
-                    // for the DescriptorSet
-                    count: 1,
-                    stage_flags: ShaderStageFlags::VERTEX,
-                    immutable_samplers: false,
-                },
-                DescriptorSetLayoutBinding {
-                    binding: 1,
-                    ty: DescriptorType::UniformBuffer,
-                    // the following field cannot be 0 or it will panic when allocatting memory
-                    // for the DescriptorSet
-                    count: 1,
-                    stage_flags: ShaderStageFlags::FRAGMENT,
-                    immutable_samplers: false,
-                },
-            ],
-            &[],
+            adapter_state.limits.clone(),
         );
-        let mut normal_descriptor_state = DescriptorState::new(
+        
+        let (mut swapchain_state, depth_format, render_size) = match surface.as_mut() {
+            Some(surface) => {
+                let swapchain_state = SwapchainState::new(
+                    device_state.clone(),
+                    &adapter_state,
+                    surface,
+                    render_size,
+                    present_mode_policy,
+                )?;
+                let depth_format = swapchain_state.depth_format;
+                // the surface may not give the size asked for
+                let render_size = Extent2D {
+                    width: swapchain_state.extent.width,
+                    height: swapchain_state.extent.height,
+                };
+                (Some(swapchain_state), depth_format, render_size)
+            }
+            None => {
+                let depth_format = select_depth_format(&adapter_state, DEPTH_STENCIL_REQUIRED)?;
+                (None, depth_format, render_size)
+            }
+        };
+        
+        // the scene is rendered to an HDR image sampled by the post-processing chain right after
+        let render_pass_state = RenderPassState::new_with_formats(
             device_state.clone(),
-            &[
-                DescriptorSetLayoutBinding {
-                    binding: 0,
-                    ty: DescriptorType::CombinedImageSampler,
-                    count: 1,
-                    stage_flags: ShaderStageFlags::FRAGMENT,
-                    immutable_samplers: false,
-                },
-            ],
-            &[],
-        );
-        let mut diffuse_descriptor_state = DescriptorState::new(
+            HDR_COLOR_FORMAT,
+            Some(depth_format),
+            samples,
+            Layout::ShaderReadOnlyOptimal,
+        )?;
+        
+        let (output_format, output_layout) = match swapchain_state.as_ref() {
+            Some(swapchain_state) => (swapchain_state.color_format, Layout::Present),
+            // the image is read back right after the post-processing chain
+            None => (offscreen_output.format(), Layout::TransferSrcOptimal),
+        };
+        let hdr_output = swapchain_state.is_none() && offscreen_output == OffscreenOutput::Hdr;
+        let mut texture_registry = TextureRegistry::new(
             device_state.clone(),
-            &[
-                DescriptorSetLayoutBinding {
-                    binding: 0,
-                    ty: DescriptorType::CombinedImageSampler,
-                    count: 1,
-                    stage_flags: ShaderStageFlags::FRAGMENT,
-                    immutable_samplers: false,
-                },
-            ],
-            &[],
-        );
-        let mut specular_descriptor_state = DescriptorState::new(
+            TEXTURE_DESCRIPTOR_POOL_SETS,
+        )?;
+        let post_process_config = {
+            let config_file = gfs
+                .read_file(&POST_PROCESS_CONFIG_PATH)
+                .map_err(|error| RendererError::file(POST_PROCESS_CONFIG_PATH, format!("{:?}", error)))?;
+            PostProcessConfig::parse(config_file.as_ref())
+                .map_err(|error| RendererError::asset(POST_PROCESS_CONFIG_PATH, error))?
+        };
+        let (render_graph_state, scene_pass, post_process_chain_state) = RendererState::create_frame_graph(
             device_state.clone(),
-            &[
-                DescriptorSetLayoutBinding {
-                    binding: 0,
-                    ty: DescriptorType::CombinedImageSampler,
-                    count: 1,
-                    stage_flags: ShaderStageFlags::FRAGMENT,
-                    immutable_samplers: false,
-                },
-            ],
-            &[],
-        );
-    
-        uniform_descriptor_state.allocate_descriptor_set(
-            &mut uniform_descriptor_pool_state);
-        normal_descriptor_state.allocate_descriptor_set(
-            &mut normal_descriptor_pool_state);
-        diffuse_descriptor_state.allocate_descriptor_set(
-            &mut diffuse_descriptor_pool_state);
-        specular_descriptor_state.allocate_descriptor_set(
-            &mut specular_descriptor_pool_state);
+            &render_pass_state,
+            &post_process_config,
+            PostProcessOutput {
+                format: output_format,
+                layout: output_layout,
+                extent: render_size,
+                hdr: hdr_output,
+            },
+            &mut gfs,
+            &shader_cache,
+            &adapter_state,
+            &mut sampler_cache,
+            &mut texture_registry,
+        )?;
         
+        let (frame_buffer_state, offscreen_target_state) = match swapchain_state.as_mut() {
+            Some(swapchain_state) => {
+                let frame_buffer_state = FrameBufferState::new(
+                    device_state.clone(),
+                    render_graph_state.output_render_pass(),
+                    swapchain_state,
+                )?;
+                (Some(frame_buffer_state), None)
+            }
+            None => {
+                let offscreen_target_state = OffscreenTargetState::new(
+                    device_state.clone(),
+                    render_graph_state.output_render_pass(),
+                    1,
+                    output_format,
+                    image::Usage::TRANSFER_SRC,
+                    None,
+                    render_size,
+                )?;
+                (None, Some(offscreen_target_state))
+            }
+        };
         let viewport = Viewport {
             rect: Rect {
                 x: 0,
@@ -256,370 +369,669 @@ impl RendererState {
             },
             depth: 0.0..1.0,
         };
-        
-        let model_file = gfs
-            .read_file(&"models/Chest.obj")
-            .unwrap();
-        // need to update crate obj to take a reference
-        let chest_obj: Obj<'_, Vec<IndexTuple>> = Obj::load(
-            model_file.clone()).unwrap();
-        
-        // here is another way to load it by using Cursor
-        //let model_file = gfs.load("models/Chest.obj".to_string()).unwrap();
-        //let object_sec: Obj<Vec<IndexTuple>> = Obj::load_buf(&mut Cursor::new(model_file)).unwrap();
-    
-        let chest_diffuse = gfs
-            .read_file(&"models/Chest-diffuse.tga")
-            .unwrap();
-        let diffuse_image =
-            load(
-                Cursor::new(chest_diffuse),
-                ImageFormat::TGA)
-                .unwrap()
-                .to_rgba();
-        let chest_normal = gfs
-            .read_file(&"models/Chest-normal.tga")
-            .unwrap();
-        let normal_image =
-            load(
-                Cursor::new(chest_normal),
-                ImageFormat::TGA)
-                .unwrap()
-                .to_rgba();
-        let chest_specular = gfs
-            .read_file(&"models/Chest-specular.tga")
-            .unwrap();
-        let specular_image =
-            load(
-                Cursor::new(chest_specular),
-                ImageFormat::TGA)
-                .unwrap()
-                .to_rgba();
-    
-        let mut diffuse_image_state = SampledImageState::new(
+                
+        let frame_contexts_state = FrameContextsState::new(
             device_state.clone(),
-            &adapter_state,
-            diffuse_image,
-            buffer::Usage::TRANSFER_SRC,
+            FRAMES_IN_FLIGHT,
+        )?;
+        
+        let material_maps = {
+            let material_file = gfs
+                .read_file(&MATERIAL_PATH)
+                .map_err(|error| RendererError::file(MATERIAL_PATH, format!("{:?}", error)))?;
+            MaterialMaps::parse(material_file.as_ref(), MATERIAL_DIRECTORY)
+                .map_err(|error| RendererError::asset(MATERIAL_PATH, error))?
+        };
+        let object_shader_paths = RendererState::object_shader_paths(
+            &shader_variants,
+            MaterialFeatures::from_maps(&material_maps),
         );
-    
-        let mut normal_image_state = SampledImageState::new(
-            device_state.clone(),
-            &adapter_state,
-            normal_image,
-            buffer::Usage::TRANSFER_SRC,
+        println!(
+            "[INFO][Object Shader Variant] {} + {}",
+            object_shader_paths.vertex,
+            object_shader_paths.fragment,
         );
-    
-        let mut specular_image_state = SampledImageState::new(
+        
+        // the uniform set is laid out the way object.vert and object.frag declare it,
+        // only which of its blocks is written at a dynamic offset cannot be told from them
+        let object_reflection = {
+            let (vertex_spirv, fragment_spirv) =
+                ObjectPso::read_spirv(&mut gfs, &shader_cache, &object_shader_paths)?;
+            ObjectPso::reflect(&object_shader_paths, &vertex_spirv, &fragment_spirv)?
+        };
+        let uniform_bindings = object_reflection.set_layout_bindings(UNIFORM_SET, &[VERT_UNIFORM_BINDING]);
+        let mut uniform_descriptor_pool_state = DescriptorPoolState::new(
             device_state.clone(),
-            &adapter_state,
-            specular_image,
-            buffer::Usage::TRANSFER_SRC,
-        );
+            &descriptor_ranges(&uniform_bindings),
+        )?;
+        let mut uniform_descriptor_state = DescriptorState::new(
+            device_state.clone(),
+            &uniform_bindings,
+            &[],
+        )?;
         
-        let mut vertices: Vec<Vertex> = vec![];
-        /*
-                for index in 0..chest_obj.position.len() {
-                    let vertex = Vertex {
-                        position: chest_obj.position[index],
-                        normal: chest_obj.normal[index],
-                        tangent: [0.0, 0.0, 0.0],
-                        texture: chest_obj.texture[index],
-                    };
-                    vertices.push(vertex);
-                }
-                
-                let mut indices: Vec<u32> = Vec::new();
-                for object in chest_obj.objects {
-                    for group in object.groups {
-                        for polys in group.polys {
-                            for face in polys.iter() {
-                                indices.push(face.0 as u32);
-                            }
-                        }
-                    }
-                }*/
-        for object in chest_obj.objects {
-            for group in object.groups {
-                for polys in group.polys {
-                    let mut vertex_for_this_face: Vec<Vertex> = vec![];
-                    for face in polys {
-                        vertex_for_this_face.push(Vertex {
-                            position: chest_obj.position[face.0],
-                            normal: chest_obj.normal[face.2.unwrap()],
-                            tangent: [0.0, 0.0, 0.0],
-                            texture:
-                            [
-                                chest_obj.texture[face.1.unwrap()][0],
-                                1.0 - chest_obj.texture[face.1.unwrap()][1]
-                            ],
-                        });
-                    }
-                    match vertex_for_this_face.len() {
-                        3 => {
-                            vertices.push(vertex_for_this_face[0]);
-                            vertices.push(vertex_for_this_face[1]);
-                            vertices.push(vertex_for_this_face[2]);
-                        }
-                        4 => {
-                            vertices.push(vertex_for_this_face[0]);
-                            vertices.push(vertex_for_this_face[1]);
-                            vertices.push(vertex_for_this_face[2]);
-                            vertices.push(vertex_for_this_face[0]);
-                            vertices.push(vertex_for_this_face[2]);
-                            vertices.push(vertex_for_this_face[3]);
-                        }
-                        5 => {
-                            vertices.push(vertex_for_this_face[0]);
-                            vertices.push(vertex_for_this_face[1]);
-                            vertices.push(vertex_for_this_face[2]);
-                            vertices.push(vertex_for_this_face[0]);
-                            vertices.push(vertex_for_this_face[2]);
-                            vertices.push(vertex_for_this_face[4]);
-                            vertices.push(vertex_for_this_face[2]);
-                            vertices.push(vertex_for_this_face[4]);
-                            vertices.push(vertex_for_this_face[3]);
-                        }
-                        _ => panic!(),
-                    };
-                }
-            }
-        }
+        uniform_descriptor_state.allocate_descriptor_set(
+            &mut uniform_descriptor_pool_state)?;
+        
+        let model_file = gfs
+            .read_file(&MODEL_PATH)
+            .map_err(|error| RendererError::file(MODEL_PATH, format!("{:?}", error)))?;
+        let vertices = vertices_from_obj(model_file)
+            .map_err(|error| RendererError::asset(MODEL_PATH, error))?;
+        
+        // the variant of object.frag picked for the material does not read a map it is missing
+        let mut load_texture = |texture_map: Option<TextureMap>| match texture_map {
+            Some(texture_map) => texture_registry.load(
+                &mut gfs,
+                &adapter_state,
+                &mut sampler_cache,
+                &texture_map.path,
+                texture_map.options,
+            ).map(Some),
+            None => Ok(None),
+        };
+        // -o and -s of every map go to object.frag through FragUniformBlock
+        let uv_transform = |texture_map: &Option<TextureMap>| texture_map
+            .as_ref()
+            .map(|texture_map| texture_map.options)
+            .unwrap_or_default()
+            .uv_transform();
+        let normal_uv_transform = uv_transform(&material_maps.normal);
+        let diffuse_uv_transform = uv_transform(&material_maps.diffuse);
+        let specular_uv_transform = uv_transform(&material_maps.specular);
+        let diffuse_texture = load_texture(material_maps.diffuse)?;
+        let normal_texture = load_texture(material_maps.normal)?;
+        let specular_texture = load_texture(material_maps.specular)?;
         
-        let vertex_buffer = BufferState::new_from_items(
+        // static geometry lives in device local memory,
+        // host visible memory is left to the buffers rewritten every frame
+        let vertex_buffer = BufferState::new_device_local(
             device_state.clone(),
-            &adapter_state,
             vertices,
             buffer::Usage::VERTEX,
-        );
-    
+        )?;
+        
         /*        let indices_buffer = BufferState::new_from_items(
                     device_state.clone(),
-                    &adapter_state,
                     indices,
                     buffer::Usage::INDEX,
                 );
                 */
-        let camera = Camera::perspective(
-            cgmath::Point3 {
-                x: 0.0,
-                y: 0.0,
-                z: 0.0,
-            },
-            cgmath::Point3 {
-                x: 2.0,
-                y: -2.0,
-                z: 2.0,
-            },
-        );
-        
-        let light = PointLight {
-            position: cgmath::Point3 {
-                x: 0.0,
-                y: 0.0,
-                z: 2.0,
-            }
-        };
-    
-        let vert_uniform_buffer = BufferState::new_from_items(
+        // every slot is written by paint_frame before the frame using it is submitted
+        let vert_uniform_ring = UniformRingState::new(
             device_state.clone(),
-            &adapter_state,
-            vec![
-                VertUniformBlock {
-                    projection_matrix: camera.projection.into(),
-                    model_view_matrix: camera.view.into(),
-                    normal_matrix: camera.normal.into(),
-                    light_position: light.position.into(),
-                }
-            ],
-            buffer::Usage::UNIFORM,
-        );
+            &adapter_state.limits,
+            frame_contexts_state.frame_count(),
+        )?;
         let frag_uniform_buffer = BufferState::new_from_items(
             device_state.clone(),
-            &adapter_state,
             vec![
                 FragUniformBlock {
-                    ambient_light: [1.0, 1.0, 1.0, 0.0]
+                    ambient_light: [1.0, 1.0, 1.0, 0.0],
+                    normal_uv_transform,
+                    diffuse_uv_transform,
+                    specular_uv_transform,
                 }
             ],
             buffer::Usage::UNIFORM,
+        )?;
+        RendererState::write_uniform_descriptor_set(
+            &device_state.borrow(),
+            &uniform_descriptor_state,
+            &vert_uniform_ring,
+            &frag_uniform_buffer,
         );
-        unsafe {
-            let device = &device_state.borrow().device;
-            device.write_descriptor_sets(
-                vec![
-                    DescriptorSetWrite {
-                        set: uniform_descriptor_state.descriptor_set.as_ref().unwrap(),
-                        binding: 0,
-                        array_offset: 0,
-                        descriptors: &[
-                            Descriptor::Buffer(
-                                vert_uniform_buffer.buffer.as_ref().unwrap(),
-                                None..None,
-                            )
-                        ],
-                    },
-                    DescriptorSetWrite {
-                        set: uniform_descriptor_state.descriptor_set.as_ref().unwrap(),
-                        binding: 1,
-                        array_offset: 0,
-                        descriptors: &[
-                            Descriptor::Buffer(
-                                frag_uniform_buffer.buffer.as_ref().unwrap(),
-                                None..None,
-                            )
-                        ],
-                    }
-                ]
-            );
-        }
-        // order matters here!!
-        normal_image_state.register_descriptor(
-            &normal_descriptor_state
-        );
-        diffuse_image_state.register_descriptor(
-            &diffuse_descriptor_state
-        );
-        specular_image_state.register_descriptor(
-            &specular_descriptor_state
-        );
-    
+        // every texture set shares the layout of the registry
+        // and the order of them matters here!!
         let object_pso = ObjectPso::new(
             device_state.clone(),
             render_pass_state.render_pass.as_ref().unwrap(),
+            render_pass_state.samples,
             vec![
                 &uniform_descriptor_state,
-                &normal_descriptor_state,
-                &diffuse_descriptor_state,
-                &specular_descriptor_state
+                texture_registry.descriptor_state(),
+                texture_registry.descriptor_state(),
+                texture_registry.descriptor_state(),
             ],
             &mut gfs,
-        );
-    
-        let mut staging_pool = {
-            let device = &device_state.borrow().device;
-            unsafe {
-                device.create_command_pool_typed(
-                    &device_state.borrow().queue_group,
-                    CommandPoolCreateFlags::empty(),
-                )
-            }.unwrap()
-        };
-    
-        let mut transferred_fence = {
-            let device = &device_state.borrow_mut().device;
+            &shader_cache,
+            &object_shader_paths,
+        )?;
         
-            device.create_fence(false)
-        }.unwrap();
+        texture_registry.flush_uploads()?;
     
-        unsafe {
-            let normal_cp_cb = normal_image_state.transfer(&mut staging_pool);
-            let diffuse_cp_cb = diffuse_image_state.transfer(&mut staging_pool);
-            let specular_cp_cb = specular_image_state.transfer(&mut staging_pool);
-        
-            device_state.borrow_mut().queue_group.queues[0]
-                .submit_nosemaphores(
-                    &vec![
-                        normal_cp_cb,
-                        diffuse_cp_cb,
-                        specular_cp_cb,
-                    ],
-                    Some(&mut transferred_fence),
-                );
-        
-            let device = &device_state.borrow().device;
-        
-            device.wait_for_fence(&transferred_fence, !0).unwrap();
-        
-            device.destroy_command_pool(staging_pool.into_raw());
+        file_watcher.watch(MODEL_PATH, WatchedAsset::Mesh);
+        let shader_source_paths = shader_compiler::source_paths(file_watcher.root());
+        let shader_include_paths = shader_compiler::include_paths(file_watcher.root());
+        for source_path in shader_source_paths.iter().chain(&shader_include_paths) {
+            file_watcher.watch(source_path, WatchedAsset::Shader);
+        }
+        file_watcher.watch(POST_PROCESS_CONFIG_PATH, WatchedAsset::PostProcessConfig);
+        for (path, handle) in texture_registry.paths() {
+            file_watcher.watch(&path, WatchedAsset::Texture(handle));
         }
     
         let rebuild_swapchain = false;
-        RendererState {
+        Ok(RendererState {
             gfs,
+            shader_cache,
+            shader_variants,
+            shader_source_paths,
+            file_watcher,
+            object_shader_paths,
             instance,
             surface,
-            normal_descriptor_pool_state,
-            diffuse_descriptor_pool_state,
-            specular_descriptor_pool_state,
             adapter_state,
             vertex_buffer,
-            vert_uniform_buffer,
+            vert_uniform_ring,
             device_state,
             object_pso,
+            post_process_chain_state,
+            render_graph_state,
+            scene_pass,
             render_pass_state,
-            swapchain_state: Some(swapchain_state),
+            swapchain_state,
             viewport,
             rebuild_swapchain,
+            present_mode_policy,
+            tonemap_settings: DEFAULT_TONEMAP_SETTINGS,
+            post_process_config,
+            screenshot_requested: false,
+            screenshot_threads: vec![],
+            pending_screenshots: vec![],
             uniform_descriptor_state,
-            normal_descriptor_state,
-            diffuse_descriptor_state,
-            specular_descriptor_state,
             uniform_descriptor_pool_state,
-            normal_image_state,
-            diffuse_image_state,
+            normal_texture,
+            diffuse_texture,
+            specular_texture,
+            texture_registry,
+            sampler_cache,
             frame_buffer_state,
+            offscreen_target_state,
+            offscreen_output,
+            frame_contexts_state,
             //indices_buffer,
-            specular_image_state,
+            environment_image_state: None,
             frag_uniform_buffer,
+        })
+    }
+    
+    /// load an equirectangular Radiance .hdr image under res/
+    /// and upload it as a floating-point cube image
+    pub fn load_environment_map(&mut self, path: &str, face_size: u32) -> RendererResult<()> {
+        let hdr_image = {
+            let hdr_file = self.gfs
+                .read_file(&path)
+                .map_err(|error| RendererError::file(path, format!("{:?}", error)))?;
+            HdrImage::from_radiance_bytes(hdr_file.as_ref())
+                .map_err(|error| RendererError::asset(path, error))?
+        };
+        println!(
+            "[INFO][Environment Map Loaded]{} {}x{}",
+            path,
+            hdr_image.width,
+            hdr_image.height
+        );
+        let cubemap = equirectangular_to_cubemap(&hdr_image, face_size);
+        
+        let mut environment_image_state = SampledImageState::new_cube(
+            self.device_state.clone(),
+            &self.adapter_state,
+            cubemap.face_size,
+            cubemap.into_bytes(),
+            self.sampler_cache.get(
+                SamplerDesc::default()
+                    .with_wrap(SamplerWrap::Clamp)
+                    .with_filter(SamplerFilter::Linear)
+            )?,
+        )?;
+        
+        let mut staging_pool = {
+            let device = &self.device_state.borrow().device;
+            unsafe {
+                device.create_command_pool_typed(
+                    &self.device_state.borrow().queue_group,
+                    CommandPoolCreateFlags::empty(),
+                )
+            }.map_err(|error| RendererError::creation("environment map command pool", error))?
+        };
+        
+        let mut transferred_fence = {
+            let device = &self.device_state.borrow().device;
+            
+            device.create_fence(false)
+        }.map_err(|error| RendererError::creation("environment map fence", error))?;
+        
+        unsafe {
+            let environment_cp_cb = environment_image_state.transfer(&mut staging_pool);
+            
+            self.device_state.borrow_mut().queue_group.queues[0]
+                .submit_nosemaphores(
+                    &vec![environment_cp_cb],
+                    Some(&mut transferred_fence),
+                );
+            
+            let device = &self.device_state.borrow().device;
+            
+            let waited = device.wait_for_fence(&transferred_fence, !0);
+            
+            device.destroy_fence(transferred_fence);
+            device.destroy_command_pool(staging_pool.into_raw());
+            waited.map_err(|error| RendererError::memory("environment map upload", error))?;
+        }
+        environment_image_state.release_staging_buffer();
+        
+        self.environment_image_state = Some(environment_image_state);
+        Ok(())
+    }
+    
+    /// re-upload textures and meshes and rebuild pipelines whose files under res/ have changed,
+    /// anything that fails to load is reported and the previous version is kept
+    pub fn poll_hot_reload(&mut self) {
+        for (path, asset) in self.file_watcher.poll() {
+            let result = match asset {
+                WatchedAsset::Texture(handle) => self.reload_texture(&path, handle),
+                WatchedAsset::Mesh => self.reload_mesh(&path),
+                WatchedAsset::Shader => self.reload_shader(&path),
+                WatchedAsset::PostProcessConfig => self.reload_post_process_config(&path),
+            };
+            match result {
+                Ok(()) => println!("[INFO][Hot Reloaded]{}", path),
+                Err(error) => println!("[WARN][Hot Reload Failed]{}: {}", path, error),
+            }
+        }
+    }
+    
+    fn reload_texture(&mut self, path: &str, handle: TextureHandle) -> RendererResult<()> {
+        let file = self.file_watcher.read(path)?;
+        self.texture_registry.reload(
+            &self.adapter_state,
+            &mut self.sampler_cache,
+            handle,
+            &file,
+        )
+    }
+    
+    fn reload_mesh(&mut self, path: &str) -> RendererResult<()> {
+        let file = self.file_watcher.read(path)?;
+        let vertices = vertices_from_obj(&file)
+            .map_err(|error| RendererError::asset(path, error))?;
+        let vertex_buffer = BufferState::new_device_local(
+            self.device_state.clone(),
+            vertices,
+            buffer::Usage::VERTEX,
+        )?;
+        // the previous buffer may still be in use by frames in flight
+        self.device_state.borrow().wait_idle()?;
+        self.frame_contexts_state.complete_all();
+        self.vertex_buffer = vertex_buffer;
+        Ok(())
+    }
+    
+    /// compile every variant of the sources that are or include the file at path and rebuild
+    /// what uses them, the compiled SPIR-V is only kept if all of that succeeds so that
+    /// later rebuilds do not pick up a shader that has been rejected
+    fn reload_shader(&mut self, path: &str) -> RendererResult<()> {
+        let is_source = self.shader_source_paths.iter().any(|source_path| source_path == path);
+        let source_paths: Vec<String> = if is_source {
+            vec![path.to_string()]
+        } else {
+            // a source that cannot be preprocessed may include path through a file that is broken now,
+            // compiling it again reports why
+            let included = shader_compiler::source_name(path);
+            self.shader_source_paths
+                .iter()
+                .filter(|source_path| {
+                    shader_compiler::preprocess(&self.file_watcher, source_path, &[])
+                        .map(|preprocessed| preprocessed.files.iter().any(|file| file == included))
+                        .unwrap_or(true)
+                })
+                .cloned()
+                .collect()
+        };
+        
+        let mut compiled = vec![];
+        for source_path in &source_paths {
+            for defines in self.shader_variants.variants(source_path) {
+                let spirv = shader_compiler::compile(&self.file_watcher, source_path, &defines)?;
+                compiled.push((shader_variant::compiled_path(source_path, &defines), spirv));
+            }
+        }
+        let previous_spirv: Vec<(String, Option<Box<[u8]>>)> = compiled
+            .into_iter()
+            .map(|(spirv_path, spirv)| {
+                let previous_spirv = self.shader_cache.insert(&spirv_path, spirv);
+                (spirv_path, previous_spirv)
+            })
+            .collect();
+        
+        let object_shader_paths = self.object_shader_paths.clone();
+        let is_object_shader = |spirv_path: &str| object_shader_paths.contains(spirv_path);
+        let mut rebuilt = Ok(());
+        if previous_spirv.iter().any(|(spirv_path, _)| is_object_shader(spirv_path)) {
+            rebuilt = self.reload_object_pso();
+        }
+        // every fullscreen pass is part of the frame graph, shaders not used by any
+        // only have to compile
+        if rebuilt.is_ok() && previous_spirv.iter().any(|(spirv_path, _)| !is_object_shader(spirv_path)) {
+            let post_process_config = self.post_process_config.clone();
+            rebuilt = self.rebuild_frame_graph(&post_process_config);
+        }
+        if rebuilt.is_err() {
+            for (spirv_path, previous_spirv) in previous_spirv {
+                match previous_spirv {
+                    Some(previous_spirv) => self.shader_cache.insert(&spirv_path, previous_spirv),
+                    None => self.shader_cache.remove(&spirv_path),
+                };
+            }
+        }
+        rebuilt
+    }
+    
+    fn reload_object_pso(&mut self) -> RendererResult<()> {
+        let (vertex_spirv, fragment_spirv) =
+            ObjectPso::read_spirv(&mut self.gfs, &self.shader_cache, &self.object_shader_paths)?;
+        let object_pso = ObjectPso::from_spirv(
+            self.device_state.clone(),
+            self.render_pass_state.render_pass.as_ref().unwrap(),
+            self.render_pass_state.samples,
+            vec![
+                &self.uniform_descriptor_state,
+                self.texture_registry.descriptor_state(),
+                self.texture_registry.descriptor_state(),
+                self.texture_registry.descriptor_state(),
+            ],
+            &self.object_shader_paths,
+            &vertex_spirv,
+            &fragment_spirv,
+        )?;
+        self.device_state.borrow().wait_idle()?;
+        self.frame_contexts_state.complete_all();
+        self.object_pso = object_pso;
+        Ok(())
+    }
+    
+    fn reload_post_process_config(&mut self, path: &str) -> RendererResult<()> {
+        let file = self.file_watcher.read(path)?;
+        let post_process_config = PostProcessConfig::parse(&file)
+            .map_err(|error| RendererError::asset(path, error))?;
+        self.rebuild_frame_graph(&post_process_config)?;
+        self.post_process_config = post_process_config;
+        Ok(())
+    }
+    
+    /// replace the render graph and its post-processing chain by ones for post_process_config
+    /// writing to the current output, the current ones are kept if the new ones cannot be created
+    fn rebuild_frame_graph(
+        &mut self,
+        post_process_config: &PostProcessConfig,
+    ) -> RendererResult<()> {
+        let output = match self.swapchain_state.as_ref() {
+            Some(swapchain_state) => PostProcessOutput {
+                format: swapchain_state.color_format,
+                layout: Layout::Present,
+                extent: Extent2D {
+                    width: swapchain_state.extent.width,
+                    height: swapchain_state.extent.height,
+                },
+                hdr: false,
+            },
+            None => {
+                let extent = self.offscreen_target_state.as_ref().unwrap().extent;
+                PostProcessOutput {
+                    format: self.offscreen_output.format(),
+                    layout: Layout::TransferSrcOptimal,
+                    extent: Extent2D {
+                        width: extent.width,
+                        height: extent.height,
+                    },
+                    hdr: self.offscreen_output == OffscreenOutput::Hdr,
+                }
+            }
+        };
+        let (render_graph_state, scene_pass, mut post_process_chain_state) =
+            RendererState::create_frame_graph(
+                self.device_state.clone(),
+                &self.render_pass_state,
+                post_process_config,
+                output,
+                &mut self.gfs,
+                &self.shader_cache,
+                &self.adapter_state,
+                &mut self.sampler_cache,
+                &mut self.texture_registry,
+            )?;
+        if let Err(error) = self.texture_registry.flush_uploads() {
+            post_process_chain_state.release_luts(&mut self.texture_registry);
+            return Err(error);
         }
+        
+        // the current graph may still be in use by frames in flight
+        self.device_state.borrow().wait_idle()?;
+        self.frame_contexts_state.complete_all();
+        self.render_graph_state = render_graph_state;
+        self.scene_pass = scene_pass;
+        let mut previous_chain_state = mem::replace(
+            &mut self.post_process_chain_state,
+            post_process_chain_state,
+        );
+        
+        // LUTs no longer used are not watched anymore, the ones just loaded are
+        let previous_luts = previous_chain_state.luts();
+        let previous_lut_paths: Vec<String> = self.texture_registry
+            .paths()
+            .into_iter()
+            .filter(|(_, handle)| previous_luts.contains(handle))
+            .map(|(path, _)| path)
+            .collect();
+        previous_chain_state.release_luts(&mut self.texture_registry);
+        for path in previous_lut_paths {
+            self.file_watcher.unwatch(&path);
+        }
+        for (path, handle) in self.texture_registry.paths() {
+            self.file_watcher.watch(&path, WatchedAsset::Texture(handle));
+        }
+        Ok(())
     }
     
-    pub fn rebuild_swapchain(&mut self, render_size: Extent2D) {
-        self.device_state.borrow().device.wait_idle().unwrap();
+    /// the render graph of a frame, the scene rendered with the formats and sample count
+    /// of render_pass_state to an HDR image, then the post-processing chain writing the output
+    fn create_frame_graph(
+        device_state: Rc<RefCell<DeviceState>>,
+        render_pass_state: &RenderPassState,
+        post_process_config: &PostProcessConfig,
+        output: PostProcessOutput,
+        gfs: &mut GemFileSystem,
+        shader_cache: &ShaderCache,
+        adapter_state: &AdapterState,
+        sampler_cache: &mut SamplerCache,
+        texture_registry: &mut TextureRegistry,
+    ) -> RendererResult<(RenderGraphState, PassId, PostProcessChainState)> {
+        let mut render_graph = RenderGraphBuilder::new(output.format, output.layout);
+        let samples = render_pass_state.samples;
+        let hdr_color = render_graph.create_transient(TransientDesc {
+            format: HDR_COLOR_FORMAT,
+            samples: 1,
+            downscale: 0,
+        });
+        let depth = render_graph.create_transient(TransientDesc {
+            format: render_pass_state.depth_format.unwrap(),
+            samples,
+            downscale: 0,
+        });
+        // when multisampling, the scene is rendered to an image resolved to hdr_color
+        let scene_pass = if samples > 1 {
+            let msaa_color = render_graph.create_transient(TransientDesc {
+                format: HDR_COLOR_FORMAT,
+                samples,
+                downscale: 0,
+            });
+            render_graph.add_pass(PassDesc {
+                sampled: vec![],
+                color: msaa_color,
+                depth: Some(depth),
+                resolve: Some(hdr_color),
+            })
+        } else {
+            render_graph.add_pass(PassDesc {
+                sampled: vec![],
+                color: hdr_color,
+                depth: Some(depth),
+                resolve: None,
+            })
+        };
+        
+        let mut post_process_chain_state = PostProcessChainState::new(
+            device_state.clone(),
+            post_process_config,
+            &mut render_graph,
+            hdr_color,
+            output,
+            gfs,
+            shader_cache,
+            adapter_state,
+            sampler_cache,
+            texture_registry,
+        )?;
+        let render_graph_state = match RenderGraphState::new(device_state, render_graph, output.extent) {
+            Ok(render_graph_state) => render_graph_state,
+            Err(error) => {
+                post_process_chain_state.release_luts(texture_registry);
+                return Err(error);
+            }
+        };
+        post_process_chain_state.write_inputs(&render_graph_state);
+        Ok((render_graph_state, scene_pass, post_process_chain_state))
+    }
+    
+    /// recreate the swapchain and everything sized after it,
+    /// render_size is the physical size of the window in pixels
+    pub fn rebuild_swapchain(&mut self, render_size: Extent2D) -> RendererResult<()> {
+        self.device_state.borrow().wait_idle()?;
+        self.frame_contexts_state.complete_all();
         self.rebuild_swapchain = false;
     
         self.swapchain_state.take().unwrap();
         self.swapchain_state = Some(SwapchainState::new(
             self.device_state.clone(),
             &self.adapter_state,
-            &mut self.surface,
+            self.surface.as_mut().unwrap(),
             render_size,
-        ));
-    
-        self.render_pass_state = RenderPassState::new(
-            self.device_state.clone(),
-            self.swapchain_state.as_ref().unwrap(),
-        );
-        self.frame_buffer_state = FrameBufferState::new(
+            self.present_mode_policy,
+        )?);
+        
+        // the scene render pass does not depend on the swapchain,
+        // the images of the graph are sized after the output so all of it is created again
+        let post_process_config = self.post_process_config.clone();
+        self.rebuild_frame_graph(&post_process_config)?;
+        self.frame_buffer_state = Some(FrameBufferState::new(
             self.device_state.clone(),
-            &self.adapter_state,
-            &self.render_pass_state,
+            self.render_graph_state.output_render_pass(),
             self.swapchain_state.as_mut().unwrap(),
-        );
-    
+        )?);
+        
         self.viewport = RendererState::create_viewport(
             self.swapchain_state.as_ref().unwrap()
         );
+        println!("[INFO][Swapchain Rebuilt]{:?}", self.swapchain_state.as_ref().unwrap().extent);
+        Ok(())
     }
     
-    pub fn try_rebuild_swapchain(&mut self, render_size: Extent2D) {
-        if self.rebuild_swapchain {
-            self.rebuild_swapchain(render_size);
-        }
+    pub fn present_mode_policy(&self) -> PresentModePolicy {
+        self.present_mode_policy
     }
     
-    pub fn paint_frame(&mut self, camera: Camera, light: PointLight) {
-        self.vert_uniform_buffer.update_buffer(
-            vec![VertUniformBlock {
-                projection_matrix: camera.projection.into(),
-                model_view_matrix: camera.view.into(),
-                normal_matrix: camera.normal.into(),
-                light_position: light.position.into(),
-            }]
-        );
-        
-        let device_state = &mut self.device_state.borrow_mut();
+    /// the swapchain is rebuilt with the new policy before the next frame
+    pub fn set_present_mode_policy(&mut self, present_mode_policy: PresentModePolicy) {
+        self.present_mode_policy = present_mode_policy;
+        self.rebuild_swapchain = true;
+    }
+    
+    pub fn tonemap_settings(&self) -> TonemapSettings {
+        self.tonemap_settings
+    }
+    
+    /// applied from the next frame on
+    pub fn set_tonemap_settings(&mut self, tonemap_settings: TonemapSettings) {
+        self.tonemap_settings = tonemap_settings;
+    }
+    
+    /// the present mode the surface actually ended up with
+    pub fn present_mode(&self) -> PresentMode {
+        self.swapchain_state.as_ref().unwrap().present_mode
+    }
+    
+    /// copy the next presented frame into a timestamped PNG under SCREENSHOT_DIRECTORY,
+    /// the image is read once the frame is done and encoded without holding up rendering
+    pub fn request_screenshot(&mut self) {
+        self.screenshot_requested = true;
+    }
+    
+    /// hand the screenshots whose frame context is done to encoding threads,
+    /// all of them if frame_context_index is none
+    fn save_pending_screenshots(&mut self, frame_context_index: Option<usize>) -> RendererResult<()> {
+        let (done, pending): (Vec<_>, Vec<_>) = self.pending_screenshots
+            .drain(..)
+            .partition(|screenshot| frame_context_index
+                .map_or(true, |index| screenshot.frame_context_index == index));
+        self.pending_screenshots = pending;
         
-        let semaphore_index = self.frame_buffer_state.current_index;
+        // finished threads are let go of as new ones start so that they do not pile up
+        let (finished, running): (Vec<_>, Vec<_>) = self.screenshot_threads
+            .drain(..)
+            .partition(|screenshot_thread| screenshot_thread.is_done());
+        self.screenshot_threads = running;
+        for screenshot_thread in finished {
+            screenshot_thread.join();
+        }
+        for screenshot in done {
+            self.screenshot_threads.push(screenshot.save(SCREENSHOT_DIRECTORY)?);
+        }
+        Ok(())
+    }
+    
+    /// a minimised window has no area to build a swapchain for,
+    /// the rebuild is put off until it has one again
+    pub fn try_rebuild_swapchain(&mut self, render_size: Extent2D) -> RendererResult<()> {
+        // a headless renderer has no swapchain to rebuild
+        if self.surface.is_none() {
+            return Ok(());
+        }
+        if render_size.width == 0 || render_size.height == 0 {
+            return Ok(());
+        }
+        if self.rebuild_swapchain {
+            self.rebuild_swapchain(render_size)?;
+        }
+        Ok(())
+    }
     
-        self.frame_buffer_state.increment_current_semaphores_index();
+    /// draw the mesh once for every object, each with its own transform
+    pub fn paint_frame(
+        &mut self,
+        camera: Camera,
+        light: PointLight,
+        objects: &[ObjectPushConstants],
+    ) -> RendererResult<()> {
+        // nothing can be presented until a pending rebuild has happened,
+        // which is the case the whole time the window is minimised
+        if self.rebuild_swapchain {
+            return Ok(());
+        }
+
+        // the GPU has to be done with the last frame recorded in this context
+        // before its command pool, semaphores and uniform slot are reused
+        let frame_context_index = self.frame_contexts_state.current_index();
+        self.frame_contexts_state.wait_current()?;
+        self.collect_retired();
+        self.save_pending_screenshots(Some(frame_context_index))?;
         
         let frame_index: SwapImageIndex = {
             let acquire_semaphore =
-                &mut self.frame_buffer_state.acquire_semaphores.as_mut().unwrap()
-                    [semaphore_index];
-            match self.swapchain_state.as_mut().unwrap().swapchain.as_mut() {
+                &mut self.frame_contexts_state.current().acquire_semaphore;
+            match self.swapchain_state
+                .as_mut()
+                .and_then(|swapchain_state| swapchain_state.swapchain.as_mut()) {
                 Some(swapchain) => {
                     match
                         unsafe {
@@ -631,151 +1043,372 @@ impl RendererState {
                         Ok(i) => i,
                         Err(_) => {
                             self.rebuild_swapchain = true;
-                            return;
+                            return Ok(());
                         }
                     }
                 }
                 None => {
                     self.rebuild_swapchain = true;
-                    return;
+                    return Ok(());
                 }
             }
         };
         
+        self.vert_uniform_ring.write(
+            frame_context_index,
+            VertUniformBlock {
+                projection_matrix: camera.projection.into(),
+                view_matrix: camera.view.into(),
+                normal_matrix: camera.normal.into(),
+                light_position: light.position.into(),
+            },
+        )?;
+        let uniform_offset = self.vert_uniform_ring.dynamic_offset(frame_context_index);
+        
+        let extent = self.swapchain_state.as_ref().unwrap().extent;
+        let screenshot_buffer = if self.screenshot_requested {
+            self.screenshot_requested = false;
+            self.create_screenshot_buffer(extent)
+        } else {
+            None
+        };
+        
+        let frame_buffer = self.frame_buffer_state.as_ref().unwrap().frame_buffer(frame_index);
+        let command_buffer = unsafe {
+            let mut command_buffer = self.frame_contexts_state
+                .current()
+                .command_pool
+                .acquire_command_buffer::<OneShot>();
+            command_buffer.begin();
+            self.record_frame(&mut command_buffer, frame_buffer, uniform_offset, objects);
+            if let Some(screenshot_buffer) = screenshot_buffer.as_ref() {
+                self.frame_buffer_state.as_ref().unwrap().record_readback(
+                    &mut command_buffer,
+                    frame_index,
+                    extent,
+                    screenshot_buffer,
+                );
+            }
+            command_buffer.finish();
+            command_buffer
+        };
+        
+        let device_state = &mut self.device_state.borrow_mut();
+        
         let swapchain = self.swapchain_state.as_ref().unwrap().swapchain.as_ref().unwrap();
         
-        let (
-            (
-                frame_buffer_fence,
-                frame_buffer,
-                command_pool
-            ),
-            (
-                image_acquired,
-                image_present
-            )
-        ) = self.frame_buffer_state.get_frame_data(
-            frame_index as usize,
-            semaphore_index as usize,
-        );
+        let FrameContext {
+            fence: frame_fence,
+            acquire_semaphore: image_acquired,
+            present_semaphore: image_present,
+            ..
+        } = self.frame_contexts_state.current();
         
-        unsafe {
+        let presented = unsafe {
             let device = &device_state.device;
-            //device
-            //    .wait_for_fence(frame_buffer_fence, !0)
-            //    .unwrap();
+            // only reset once something is about to be submitted,
+            // an early return above leaves the fence signaled for the next wait
             device
-                .reset_fence(frame_buffer_fence)
-                .unwrap();
-    
-            command_pool.reset();
-            let command_buffer_1 = {
-                let mut command_buffer =
-                    command_pool.acquire_command_buffer::<gfx_hal::command::OneShot>();
-                command_buffer.begin();
-        
-                command_buffer.set_viewports(0, &[self.viewport.clone()]);
-                command_buffer.set_scissors(0, &[self.viewport.rect.clone()]);
-                command_buffer.bind_graphics_pipeline(self.object_pso.pipeline.as_ref().unwrap());
-                command_buffer.bind_vertex_buffers(
-                    0,
-                    Some((self.vertex_buffer.buffer.as_ref().unwrap(), 0)),
-                );
-                /*            command_buffer.bind_index_buffer(
-                            IndexBufferView {
-                                buffer: self.indices_buffer.buffer.as_ref().unwrap(),
-                                offset: 0,
-                                index_type: IndexType::U32,
-                            });*/
-                command_buffer.bind_graphics_descriptor_sets(
-                    self.object_pso.pipeline_layout.as_ref().unwrap(),
-                    0,
-                    vec![
-                        self.uniform_descriptor_state.descriptor_set.as_ref().unwrap(),
-                        self.normal_descriptor_state.descriptor_set.as_ref().unwrap(),
-                        self.diffuse_descriptor_state.descriptor_set.as_ref().unwrap(),
-                        self.specular_descriptor_state.descriptor_set.as_ref().unwrap(),
-                    ],
-                    &[],
-                );
-                {
-                    let mut encoder = command_buffer.begin_render_pass_inline(
-                        self.render_pass_state.render_pass.as_ref().unwrap(),
-                        frame_buffer,
-                        self.viewport.rect.clone(),
-                        &[ClearValue::Color(ClearColor::Float([0.0, 0.0, 0.0, 1.0])),
-                            ClearValue::DepthStencil(ClearDepthStencil(1.0, 0))],
-                    );
-            
-                    encoder.draw(
-                        0..((self.vertex_buffer.size.unwrap() / ::std::mem::size_of::<Vertex>() as
-                            u64)
-                            as u32),
-                        0..1,
-                    );
-                }
-                command_buffer.finish();
-                command_buffer
-            };
-            let command_buffer_2 = {
-                let mut command_buffer =
-                    command_pool.acquire_command_buffer::<gfx_hal::command::OneShot>();
-                command_buffer.begin();
-        
-                command_buffer.set_viewports(0, &[self.viewport.clone()]);
-                command_buffer.set_scissors(0, &[self.viewport.rect.clone()]);
-                command_buffer.bind_graphics_pipeline(self.object_pso.pipeline.as_ref().unwrap());
-                command_buffer.bind_vertex_buffers(
-                    0,
-                    Some((self.vertex_buffer.buffer.as_ref().unwrap(), 0)),
-                );
-                command_buffer.bind_graphics_descriptor_sets(
-                    self.object_pso.pipeline_layout.as_ref().unwrap(),
-                    0,
-                    vec![
-                        self.uniform_descriptor_state.descriptor_set.as_ref().unwrap(),
-                        self.normal_descriptor_state.descriptor_set.as_ref().unwrap(),
-                        self.diffuse_descriptor_state.descriptor_set.as_ref().unwrap(),
-                        self.specular_descriptor_state.descriptor_set.as_ref().unwrap(),
-                    ],
-                    &[],
-                );
-                {
-                    let mut encoder = command_buffer.begin_render_pass_inline(
-                        self.render_pass_state.render_pass.as_ref().unwrap(),
-                        frame_buffer,
-                        self.viewport.rect.clone(),
-                        &[ClearValue::Color(ClearColor::Float([0.0, 0.0, 0.0, 1.0])),
-                            ClearValue::DepthStencil(ClearDepthStencil(1.0, 0))],
-                    );
+                .reset_fence(frame_fence)
+                .map_err(|error| RendererError::device("reset the frame fence", error))?;
             
-                    encoder.draw(
-                        0..((self.vertex_buffer.size.unwrap() / ::std::mem::size_of::<Vertex>() as
-                            u64)
-                            as u32),
-                        0..1,
-                    );
-                }
-                command_buffer.finish();
-                command_buffer
-            };
             let submission = Submission {
-                command_buffers: &[command_buffer_1, command_buffer_2],
-                wait_semaphores: iter::once((&*image_acquired, PipelineStage::BOTTOM_OF_PIPE)),
+                command_buffers: &[command_buffer],
+                wait_semaphores: iter::once((
+                    &*image_acquired,
+                    PipelineStage::COLOR_ATTACHMENT_OUTPUT,
+                )),
                 signal_semaphores: iter::once(&*image_present),
             };
             device_state.queue_group.queues[0]
-                .submit(submission, Some(frame_buffer_fence));
+                .submit(submission, Some(&*frame_fence));
             
-            if let Err(_) = swapchain.present(
+            swapchain.present(
                 &mut device_state.queue_group.queues[0],
                 frame_index,
                 Some(&*image_present),
-            ) {
-                self.rebuild_swapchain = true;
-                return;
+            )
+        };
+        if let Some(readback_buffer) = screenshot_buffer {
+            self.pending_screenshots.push(PendingScreenshot {
+                frame_context_index,
+                readback_buffer,
+                extent,
+                color_format: self.swapchain_state.as_ref().unwrap().color_format,
+            });
+        }
+        self.frame_contexts_state.advance();
+        if let Err(_) = presented {
+            self.rebuild_swapchain = true;
+        }
+        Ok(())
+    }
+    
+    /// destroy what has been released once no frame in flight can use it anymore,
+    /// to be called once the current frame context has been waited for
+    fn collect_retired(&mut self) {
+        let timeline = self.frame_contexts_state.timeline();
+        if self.texture_registry.begin_frame(timeline) {
+            self.sampler_cache.collect_unused();
+        }
+    }
+    
+    /// a host visible buffer for a copy of a swapchain image,
+    /// none if the swapchain images cannot be copied from or the buffer cannot be had
+    fn create_screenshot_buffer(&self, extent: Extent) -> Option<BufferState<u8>> {
+        if !self.swapchain_state.as_ref().unwrap().readable {
+            println!("[WARN][Screenshot Failed]the swapchain images cannot be copied from");
+            return None;
+        }
+        if !self.frame_buffer_state.as_ref().unwrap().has_images() {
+            println!("[WARN][Screenshot Failed]the swapchain has a framebuffer instead of images");
+            return None;
+        }
+        // every surface format a screenshot can be converted from has 4 bytes per pixel
+        let size = extent.width as u64 * extent.height as u64 * 4;
+        match BufferState::new_readback(self.device_state.clone(), size) {
+            Ok(readback_buffer) => Some(readback_buffer),
+            Err(error) => {
+                println!("[WARN][Screenshot Failed]{}", error);
+                None
             }
+        }
+    }
+    
+    /// render a single frame of a headless renderer and read it back,
+    /// this blocks until the GPU is done with it, a renderer with a surface cannot
+    pub fn render_offscreen(
+        &mut self,
+        camera: Camera,
+        light: PointLight,
+        objects: &[ObjectPushConstants],
+    ) -> RendererResult<OffscreenImage> {
+        let readback_size = match self.offscreen_target_state.as_ref() {
+            Some(offscreen_target_state) => offscreen_target_state.readback_size(),
+            None => return Err(RendererError::Creation {
+                resource: "offscreen render".to_string(),
+                reason: "a renderer with a surface has no offscreen target, use new_headless".to_string(),
+            }),
         };
+        let readback_buffer = BufferState::<u8>::new_readback(
+            self.device_state.clone(),
+            readback_size,
+        )?;
+        
+        let frame_context_index = self.frame_contexts_state.current_index();
+        self.frame_contexts_state.wait_current()?;
+        self.collect_retired();
+        
+        self.vert_uniform_ring.write(
+            frame_context_index,
+            VertUniformBlock {
+                projection_matrix: camera.projection.into(),
+                view_matrix: camera.view.into(),
+                normal_matrix: camera.normal.into(),
+                light_position: light.position.into(),
+            },
+        )?;
+        let uniform_offset = self.vert_uniform_ring.dynamic_offset(frame_context_index);
+        
+        let mut command_buffer = self.frame_contexts_state
+            .current()
+            .command_pool
+            .acquire_command_buffer::<OneShot>();
+        unsafe {
+            command_buffer.begin();
+            let offscreen_target_state = self.offscreen_target_state.as_ref().unwrap();
+            self.record_frame(
+                &mut command_buffer,
+                offscreen_target_state.frame_buffer.as_ref().unwrap(),
+                uniform_offset,
+                objects,
+            );
+            offscreen_target_state.record_readback(&mut command_buffer, &readback_buffer);
+            command_buffer.finish();
+        }
+        
+        {
+            let device_state = &mut self.device_state.borrow_mut();
+            let frame_fence = &self.frame_contexts_state.current().fence;
+            unsafe {
+                device_state.device
+                    .reset_fence(frame_fence)
+                    .map_err(|error| RendererError::device("reset the frame fence", error))?;
+                device_state.queue_group.queues[0]
+                    .submit_nosemaphores(Some(&command_buffer), Some(frame_fence));
+                device_state.device
+                    .wait_for_fence(frame_fence, !0)
+                    .map_err(|error| RendererError::device("wait for the offscreen frame", error))?;
+            }
+        }
+        self.frame_contexts_state.advance();
+        
+        let extent = self.offscreen_target_state.as_ref().unwrap().extent;
+        let bytes = readback_buffer.read_bytes()?;
+        Ok(OffscreenImage::from_readback(self.offscreen_output, extent, bytes))
+    }
+    
+    /// record every pass of the render graph, the last one writing to output_frame_buffer,
+    /// command_buffer must have begun and is left open
+    unsafe fn record_frame(
+        &self,
+        command_buffer: &mut CommandBuffer<B, Graphics, OneShot>,
+        output_frame_buffer: &<B as TB>::Framebuffer,
+        uniform_offset: u32,
+        objects: &[ObjectPushConstants],
+    ) {
+        self.render_graph_state.record(
+            command_buffer,
+            output_frame_buffer,
+            |pass_id, command_buffer, context| {
+                if pass_id == self.scene_pass {
+                    self.record_scene(command_buffer, context, uniform_offset, objects);
+                } else {
+                    self.post_process_chain_state.record_pass(
+                        pass_id,
+                        command_buffer,
+                        context,
+                        self.tonemap_settings,
+                        &self.texture_registry,
+                    );
+                }
+            },
+        );
+    }
+    
+    /// record the draw of every object in the scene pass of the render graph
+    unsafe fn record_scene(
+        &self,
+        command_buffer: &mut CommandBuffer<B, Graphics, OneShot>,
+        context: &PassContext<'_>,
+        uniform_offset: u32,
+        objects: &[ObjectPushConstants],
+    ) {
+        command_buffer.set_viewports(0, &[context.viewport.clone()]);
+        command_buffer.set_scissors(0, &[context.viewport.rect.clone()]);
+        command_buffer.bind_graphics_pipeline(self.object_pso.pipeline.as_ref().unwrap());
+        command_buffer.bind_vertex_buffers(
+            0,
+            Some((self.vertex_buffer.buffer.as_ref().unwrap(), 0)),
+        );
+        command_buffer.bind_graphics_descriptor_sets(
+            self.object_pso.pipeline_layout.as_ref().unwrap(),
+            UNIFORM_SET as usize,
+            vec![self.uniform_descriptor_state.descriptor_set.as_ref().unwrap()],
+            &[uniform_offset],
+        );
+        // the texture sets follow the uniform set, the shaders only read those of the maps there are
+        let textures = [self.normal_texture, self.diffuse_texture, self.specular_texture];
+        for (set, texture) in textures.iter().enumerate() {
+            if let Some(texture) = texture {
+                command_buffer.bind_graphics_descriptor_sets(
+                    self.object_pso.pipeline_layout.as_ref().unwrap(),
+                    UNIFORM_SET as usize + 1 + set,
+                    vec![self.texture_registry.descriptor_set(*texture)],
+                    &[] as &[u32],
+                );
+            }
+        }
+        
+        let mut encoder = command_buffer.begin_render_pass_inline(
+            context.render_pass,
+            context.frame_buffer,
+            context.viewport.rect.clone(),
+            context.clear_values,
+        );
+        let vertex_count = (self.vertex_buffer.size.unwrap() / mem::size_of::<Vertex>() as u64) as u32;
+        let push_constant_stages = self.object_pso.push_constant_stages;
+        for object in objects {
+            // shaders not reading ObjectPushConstants leave the pipeline layout without a range
+            if !push_constant_stages.is_empty() {
+                encoder.push_graphics_constants(
+                    self.object_pso.pipeline_layout.as_ref().unwrap(),
+                    push_constant_stages,
+                    0,
+                    object.as_words(),
+                );
+            }
+            encoder.draw(0..vertex_count, 0..1);
+        }
+    }
+    
+    /// the variants of object.vert and object.frag to draw the model with,
+    /// vertices_from_obj fills in the attributes of MeshAttributes::obj
+    fn object_shader_paths(
+        shader_variants: &ShaderVariants,
+        material_features: MaterialFeatures,
+    ) -> ObjectShaderPaths {
+        let mesh_attributes = MeshAttributes::obj();
+        ObjectShaderPaths {
+            vertex: shader_variants.path(OBJECT_VERTEX_SOURCE_PATH, mesh_attributes, material_features),
+            fragment: shader_variants.path(OBJECT_FRAGMENT_SOURCE_PATH, mesh_attributes, material_features),
+        }
+    }
+    
+    /// point the uniform descriptor set at the vertex uniform ring and the fragment uniform buffer
+    fn write_uniform_descriptor_set(
+        device_state: &DeviceState,
+        uniform_descriptor_state: &DescriptorState,
+        vert_uniform_ring: &UniformRingState<VertUniformBlock>,
+        frag_uniform_buffer: &BufferState<FragUniformBlock>,
+    ) {
+        unsafe {
+            device_state.device.write_descriptor_sets(
+                vec![
+                    DescriptorSetWrite {
+                        set: uniform_descriptor_state.descriptor_set.as_ref().unwrap(),
+                        binding: VERT_UNIFORM_BINDING,
+                        array_offset: 0,
+                        descriptors: &[vert_uniform_ring.descriptor()],
+                    },
+                    DescriptorSetWrite {
+                        set: uniform_descriptor_state.descriptor_set.as_ref().unwrap(),
+                        binding: FRAG_UNIFORM_BINDING,
+                        array_offset: 0,
+                        descriptors: &[
+                            Descriptor::Buffer(
+                                frag_uniform_buffer.buffer.as_ref().unwrap(),
+                                None..None,
+                            )
+                        ],
+                    }
+                ]
+            );
+        }
+    }
+    
+    /// a growable host visible buffer for geometry that changes every frame,
+    /// with a copy for every frame context, begin_dynamic_buffer_frame picks the one to write
+    pub fn create_dynamic_buffer<I: Copy>(
+        &self,
+        usage: buffer::Usage,
+        capacity: usize,
+    ) -> RendererResult<DynamicBufferState<I>> {
+        DynamicBufferState::new(
+            self.device_state.clone(),
+            usage,
+            capacity,
+            self.frame_contexts_state.frame_count(),
+        )
+    }
+    
+    /// wait for the frame context the next frame is recorded with and switch dynamic_buffer
+    /// to its copy, to be called once per frame before the buffer is written
+    pub fn begin_dynamic_buffer_frame<I: Copy>(
+        &mut self,
+        dynamic_buffer: &mut DynamicBufferState<I>,
+    ) -> RendererResult<()> {
+        // waiting again in paint_frame returns right away, the fence is still signaled
+        self.frame_contexts_state.wait_current()?;
+        dynamic_buffer.begin_frame(
+            self.frame_contexts_state.current_index(),
+            self.frame_contexts_state.timeline(),
+        )
+    }
+    
+    /// the device memory held by the renderer, by category and memory type
+    pub fn memory_report(&self) -> MemoryReport {
+        self.device_state.borrow().memory_report()
     }
     
     #[inline]
@@ -793,3 +1426,20 @@ impl RendererState {
 }
 
 
+
+impl Drop for RendererState {
+    fn drop(&mut self) {
+        // nothing owned by the renderer can be destroyed while the GPU is still using it,
+        // a lost device has nothing left to wait for
+        if let Err(error) = self.device_state.borrow().wait_idle() {
+            println!("[WARN][Renderer Drop]{}", error);
+        }
+        if let Err(error) = self.save_pending_screenshots(None) {
+            println!("[WARN][Screenshot Failed]{}", error);
+        }
+        for screenshot_thread in self.screenshot_threads.drain(..) {
+            screenshot_thread.join();
+        }
+        print!("{}", self.memory_report());
+    }
+}
@@ -0,0 +1,124 @@
+use std::{
+    fs,
+    io::Read,
+    path::Path,
+};
+
+use glsl_to_spirv::ShaderType;
+
+use super::{
+    constants::SHADER_SOURCE_DIRECTORY,
+    hal::error::{
+        RendererError,
+        RendererResult,
+    },
+    hot_reload::FileWatcher,
+    shader_preprocessor::{
+        self,
+        Preprocessed,
+    },
+};
+
+/// the stage of a GLSL source under SHADER_SOURCE_DIRECTORY, told by its extension the way build.rs does,
+/// none for files that are not shaders
+fn shader_type(source_path: &str) -> Option<ShaderType> {
+    match Path::new(source_path).extension()?.to_str()? {
+        "vert" => Some(ShaderType::Vertex),
+        "frag" => Some(ShaderType::Fragment),
+        _ => None,
+    }
+}
+
+/// the path relative to res/ of every vertex and fragment shader source under res_root
+pub fn source_paths(res_root: &Path) -> Vec<String> {
+    let entries = match fs::read_dir(res_root.join(SHADER_SOURCE_DIRECTORY)) {
+        Ok(entries) => entries,
+        Err(_) => return vec![],
+    };
+    let mut source_paths: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().map(|file_type| file_type.is_file()).unwrap_or(false))
+        .map(|entry| format!("{}/{}", SHADER_SOURCE_DIRECTORY, entry.file_name().to_string_lossy()))
+        .filter(|source_path| shader_type(source_path).is_some())
+        .collect();
+    source_paths.sort();
+    source_paths
+}
+
+/// the name under SHADER_SOURCE_DIRECTORY of the file at source_path under res/,
+/// which is what includes and the permutation manifest refer to it by
+pub fn source_name(source_path: &str) -> &str {
+    let prefix = format!("{}/", SHADER_SOURCE_DIRECTORY);
+    if source_path.starts_with(&prefix) {
+        &source_path[prefix.len()..]
+    } else {
+        source_path
+    }
+}
+
+/// the path relative to res/ of every .glsl file under SHADER_SOURCE_DIRECTORY and its subdirectories,
+/// which are only compiled as part of the shaders including them
+pub fn include_paths(res_root: &Path) -> Vec<String> {
+    let mut include_paths = vec![];
+    let mut directories = vec![SHADER_SOURCE_DIRECTORY.to_string()];
+    while let Some(directory) = directories.pop() {
+        let entries = match fs::read_dir(res_root.join(&directory)) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let path = format!("{}/{}", directory, entry.file_name().to_string_lossy());
+            match entry.file_type() {
+                Ok(file_type) if file_type.is_dir() => directories.push(path),
+                Ok(file_type) if file_type.is_file() && path.ends_with(".glsl") =>
+                    include_paths.push(path),
+                _ => (),
+            }
+        }
+    }
+    include_paths.sort();
+    include_paths
+}
+
+/// the source at source_path with what it includes pasted in and defines added,
+/// read from disk through file_watcher the way build.rs does from res/shaders
+pub fn preprocess(
+    file_watcher: &FileWatcher,
+    source_path: &str,
+    defines: &[String],
+) -> RendererResult<Preprocessed> {
+    shader_preprocessor::preprocess(source_name(source_path), defines, |name| {
+        let path = format!("{}/{}", SHADER_SOURCE_DIRECTORY, name);
+        let file = file_watcher.read(&path).map_err(|error| error.to_string())?;
+        String::from_utf8(file.into_vec()).map_err(|error| format!("{}: {}", path, error))
+    }).map_err(|error| RendererError::asset(source_path, error))
+}
+
+/// compile the variant of the source at source_path with defines into SPIR-V,
+/// the errors glslang reports are given with the file and line they are at
+pub fn compile(
+    file_watcher: &FileWatcher,
+    source_path: &str,
+    defines: &[String],
+) -> RendererResult<Box<[u8]>> {
+    let shader_type = shader_type(source_path)
+        .ok_or_else(|| RendererError::asset(source_path, "not a .vert or .frag shader"))?;
+    let preprocessed = preprocess(file_watcher, source_path, defines)?;
+    
+    let mut compiled_file = glsl_to_spirv::compile(&preprocessed.source, shader_type)
+        .map_err(|log| RendererError::ShaderCompile {
+            path: source_path.to_string(),
+            defines: defines.to_vec(),
+            errors: shader_preprocessor::parse_log(&log, &preprocessed.files)
+                .into_iter()
+                .map(|(file, line, message)| {
+                    (format!("{}/{}", SHADER_SOURCE_DIRECTORY, file), line, message)
+                })
+                .collect(),
+        })?;
+    let mut spirv = vec![];
+    compiled_file
+        .read_to_end(&mut spirv)
+        .map_err(|error| RendererError::file(source_path, error))?;
+    Ok(spirv.into_boxed_slice())
+}
@@ -0,0 +1,368 @@
+// shared with build.rs through #[path], so only std, ron and serde_derive can be used here
+
+/// name under the shader source directory of the RON encoded PermutationManifest
+pub const PERMUTATION_MANIFEST_NAME: &str = "permutations.ron";
+
+/// a shader compiled once per combination of one option out of every set,
+/// an option being the defines it adds, an empty option adding none
+#[derive(Debug, Clone, Deserialize)]
+pub struct ShaderPermutations {
+    // name of the source under the shader source directory
+    pub source: String,
+    pub sets: Vec<Vec<Vec<String>>>,
+}
+
+/// the shaders compiled with defines, the ones not listed are compiled once without any
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct PermutationManifest {
+    pub shaders: Vec<ShaderPermutations>,
+}
+
+impl PermutationManifest {
+    pub fn parse(manifest_file: &[u8]) -> Result<PermutationManifest, String> {
+        let manifest: PermutationManifest = ron::de::from_bytes(manifest_file)
+            .map_err(|error| format!("cannot parse permutation manifest: {}", error))?;
+        for shader in &manifest.shaders {
+            if shader.sets.iter().any(|set| set.is_empty()) {
+                return Err(format!("{} has a permutation set without any option", shader.source));
+            }
+        }
+        Ok(manifest)
+    }
+    
+    /// the defines of every variant of the source named source_name,
+    /// a single one without any if the manifest does not list it
+    pub fn variants(&self, source_name: &str) -> Vec<Vec<String>> {
+        let sets = match self.shaders.iter().find(|shader| shader.source == source_name) {
+            Some(shader) => &shader.sets,
+            None => return vec![vec![]],
+        };
+        sets.iter().fold(vec![vec![]], |variants, set| {
+            variants
+                .iter()
+                .flat_map(|defines: &Vec<String>| set.iter().map(move |option| {
+                    defines.iter().chain(option.iter()).cloned().collect()
+                }))
+                .collect()
+        })
+    }
+    
+    /// the variant of source_name with the most defines that are all in available,
+    /// the first one if every variant has a define that is not
+    pub fn variant_for(&self, source_name: &str, available: &[&str]) -> Vec<String> {
+        let variants = self.variants(source_name);
+        variants
+            .iter()
+            .filter(|defines| defines.iter().all(|define| available.contains(&define.as_str())))
+            .max_by_key(|defines| defines.len())
+            .unwrap_or(&variants[0])
+            .clone()
+    }
+}
+
+/// the file name the SPIR-V of a variant of source_name is written to,
+/// the source name itself followed by .spv for the variant without defines
+pub fn variant_file_name(source_name: &str, defines: &[String]) -> String {
+    let mut file_name = source_name.to_string();
+    for define in defines {
+        file_name.push('.');
+        file_name.push_str(define);
+    }
+    file_name.push_str(".spv");
+    file_name
+}
+
+/// GLSL with every #include pasted in and the defines of a variant added
+pub struct Preprocessed {
+    pub source: String,
+    // the name of every file pasted in, indexed by the source string number
+    // its lines are reported with by glslang, the shader itself being 0
+    pub files: Vec<String>,
+}
+
+/// resolve the `#include "name"` lines of the shader named source_name and of what it includes,
+/// names being relative to the shader source directory, which read gives the content of,
+/// a file included more than once is only pasted in the first time,
+/// defines are added right after #version
+pub fn preprocess<F>(source_name: &str, defines: &[String], mut read: F) -> Result<Preprocessed, String>
+    where F: FnMut(&str) -> Result<String, String>
+{
+    let mut preprocessed = Preprocessed {
+        source: String::new(),
+        files: vec![],
+    };
+    paste(source_name, Some(defines), &mut read, &mut preprocessed, &mut vec![])?;
+    Ok(preprocessed)
+}
+
+fn paste<F>(
+    name: &str,
+    defines: Option<&[String]>,
+    read: &mut F,
+    preprocessed: &mut Preprocessed,
+    include_stack: &mut Vec<String>,
+) -> Result<(), String>
+    where F: FnMut(&str) -> Result<String, String>
+{
+    if include_stack.iter().any(|included| included == name) {
+        return Err(format!("{} includes itself through {}", name, include_stack.join(" -> ")));
+    }
+    if preprocessed.files.iter().any(|file| file == name) {
+        return Ok(());
+    }
+    let source = read(name)?;
+    let string_number = preprocessed.files.len();
+    preprocessed.files.push(name.to_string());
+    include_stack.push(name.to_string());
+    
+    // #line sets the number of the line after it, glslang reports errors with it
+    if defines.is_none() {
+        preprocessed.source.push_str(&format!("#line 1 {}\n", string_number));
+    }
+    let mut has_version = false;
+    for (index, line) in source.lines().enumerate() {
+        let line_number = index + 1;
+        let directive = line.trim_start();
+        if directive.starts_with("#include") {
+            let included = directive["#include".len()..].trim();
+            if included.len() < 2 || !included.starts_with('"') || !included.ends_with('"') {
+                return Err(format!("{}:{}: expected #include \"name\"", name, line_number));
+            }
+            paste(&included[1..included.len() - 1], None, read, preprocessed, include_stack)?;
+            preprocessed.source.push_str(&format!("#line {} {}\n", line_number + 1, string_number));
+            continue;
+        }
+        
+        preprocessed.source.push_str(line);
+        preprocessed.source.push('\n');
+        if let (Some(defines), true) = (defines, directive.starts_with("#version")) {
+            for define in defines {
+                preprocessed.source.push_str(&format!("#define {}\n", define));
+            }
+            preprocessed.source.push_str(&format!("#line {} {}\n", line_number + 1, string_number));
+            has_version = true;
+        }
+    }
+    if defines.is_some() && !has_version {
+        return Err(format!("{} has no #version to add defines after", name));
+    }
+    
+    include_stack.pop();
+    Ok(())
+}
+
+/// the errors in the output of glslangValidator as the file, line and message of each,
+/// glslang writes them as `ERROR: <string>:<line>: <message>` with the string being
+/// the source string number set by #line or the path of the file it was given,
+/// which is the shader itself
+pub fn parse_log(log: &str, files: &[String]) -> Vec<(String, Option<u32>, String)> {
+    let errors: Vec<(String, Option<u32>, String)> = log
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| line.starts_with("ERROR:"))
+        .filter_map(|line| {
+            let mut parts = line["ERROR:".len()..].splitn(3, ':');
+            let string = parts.next()?.trim();
+            let line = parts.next()?.trim().parse::<u32>().ok()?;
+            let message = parts.next()?.trim();
+            let file = string
+                .parse::<usize>()
+                .ok()
+                .and_then(|string_number| files.get(string_number))
+                .or_else(|| files.first())?;
+            Some((file.clone(), Some(line), message.to_string()))
+        })
+        .collect();
+    if errors.is_empty() {
+        // something else went wrong, such as glslangValidator failing to start
+        vec![(files.first().cloned().unwrap_or_default(), None, log.trim().to_string())]
+    } else {
+        errors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    
+    use super::*;
+    
+    fn reader<'a>(files: &'a [(&'a str, &'a str)]) -> impl FnMut(&str) -> Result<String, String> + 'a {
+        move |name| files
+            .iter()
+            .find(|(file, _)| *file == name)
+            .map(|(_, source)| source.to_string())
+            .ok_or_else(|| format!("{} not found", name))
+    }
+    
+    fn defines(defines: &[&str]) -> Vec<String> {
+        defines.iter().map(|define| define.to_string()).collect()
+    }
+    
+    // build.rs reads the same manifest
+    fn manifest() -> PermutationManifest {
+        PermutationManifest::parse(&fs::read("res/shaders/permutations.ron").unwrap()).unwrap()
+    }
+    
+    #[test]
+    fn nested_includes_are_pasted_with_line_directives() {
+        let files = [
+            ("main.frag", "#version 450\n#include \"lighting.glsl\"\nvoid main() {}\n"),
+            ("lighting.glsl", "#include \"common.glsl\"\nfloat light() { return 1.0; }\n"),
+            ("common.glsl", "const float PI = 3.14;\n"),
+        ];
+        let preprocessed = preprocess("main.frag", &[], reader(&files)).unwrap();
+        assert_eq!(preprocessed.files, vec!["main.frag", "lighting.glsl", "common.glsl"]);
+        assert_eq!(preprocessed.source, concat!(
+            "#version 450\n",
+            "#line 2 0\n",
+            "#line 1 1\n",
+            "#line 1 2\n",
+            "const float PI = 3.14;\n",
+            "#line 2 1\n",
+            "float light() { return 1.0; }\n",
+            "#line 3 0\n",
+            "void main() {}\n",
+        ));
+    }
+    
+    #[test]
+    fn a_file_included_twice_is_pasted_once() {
+        let files = [
+            ("main.frag", "#version 450\n#include \"a.glsl\"\n#include \"common.glsl\"\n"),
+            ("a.glsl", "#include \"common.glsl\"\n"),
+            ("common.glsl", "const float PI = 3.14;\n"),
+        ];
+        let preprocessed = preprocess("main.frag", &[], reader(&files)).unwrap();
+        assert_eq!(preprocessed.files, vec!["main.frag", "a.glsl", "common.glsl"]);
+        assert_eq!(preprocessed.source.matches("const float PI").count(), 1);
+    }
+    
+    #[test]
+    fn include_cycles_are_rejected() {
+        let files = [
+            ("main.frag", "#version 450\n#include \"a.glsl\"\n"),
+            ("a.glsl", "#include \"b.glsl\"\n"),
+            ("b.glsl", "#include \"a.glsl\"\n"),
+        ];
+        let error = preprocess("main.frag", &[], reader(&files)).err().unwrap();
+        assert_eq!(error, "a.glsl includes itself through main.frag -> a.glsl -> b.glsl");
+    }
+    
+    #[test]
+    fn malformed_and_missing_includes_are_rejected() {
+        let files = [
+            ("main.frag", "#version 450\n#include <a.glsl>\n"),
+            ("other.frag", "#version 450\n#include \"missing.glsl\"\n"),
+        ];
+        let error = preprocess("main.frag", &[], reader(&files)).err().unwrap();
+        assert_eq!(error, "main.frag:2: expected #include \"name\"");
+        let error = preprocess("other.frag", &[], reader(&files)).err().unwrap();
+        assert_eq!(error, "missing.glsl not found");
+    }
+    
+    #[test]
+    fn defines_are_added_after_version() {
+        let files = [
+            ("main.frag", "// a comment\n#version 450\nvoid main() {}\n"),
+        ];
+        let preprocessed = preprocess("main.frag", &defines(&["HAS_UV", "HAS_NORMALS"]), reader(&files))
+            .unwrap();
+        assert_eq!(preprocessed.source, concat!(
+            "// a comment\n",
+            "#version 450\n",
+            "#define HAS_UV\n",
+            "#define HAS_NORMALS\n",
+            "#line 3 0\n",
+            "void main() {}\n",
+        ));
+        
+        let files = [("main.frag", "void main() {}\n")];
+        let error = preprocess("main.frag", &defines(&["HAS_UV"]), reader(&files)).err().unwrap();
+        assert_eq!(error, "main.frag has no #version to add defines after");
+    }
+    
+    #[test]
+    fn log_errors_map_back_to_the_included_file() {
+        let files = defines(&["main.frag", "lighting.glsl"]);
+        let log = concat!(
+            "res/shaders/main.frag\n",
+            "ERROR: 1:3: 'PI' : undeclared identifier\n",
+            "ERROR: 0:7: '' : compilation terminated\n",
+            "ERROR: res/shaders/main.frag:2: 'x' : syntax error\n",
+            "ERROR: 3 compilation errors.  No code generated.\n",
+        );
+        assert_eq!(parse_log(log, &files), vec![
+            ("lighting.glsl".to_string(), Some(3), "'PI' : undeclared identifier".to_string()),
+            ("main.frag".to_string(), Some(7), "'' : compilation terminated".to_string()),
+            ("main.frag".to_string(), Some(2), "'x' : syntax error".to_string()),
+        ]);
+    }
+    
+    #[test]
+    fn logs_without_errors_are_reported_whole() {
+        let files = defines(&["main.frag"]);
+        assert_eq!(parse_log("  cannot start glslangValidator\n", &files), vec![
+            ("main.frag".to_string(), None, "cannot start glslangValidator".to_string()),
+        ]);
+    }
+    
+    #[test]
+    fn variants_combine_an_option_of_every_set() {
+        let manifest = PermutationManifest::parse(br#"(
+            shaders: [
+                (source: "a.frag", sets: [[[], ["X"]], [["Y"], ["Y", "Z"]]]),
+            ],
+        )"#).unwrap();
+        assert_eq!(manifest.variants("a.frag"), vec![
+            defines(&["Y"]),
+            defines(&["Y", "Z"]),
+            defines(&["X", "Y"]),
+            defines(&["X", "Y", "Z"]),
+        ]);
+        assert_eq!(manifest.variants("b.frag"), vec![defines(&[])]);
+        
+        assert_eq!(manifest.variant_for("a.frag", &["X", "Y"]), defines(&["X", "Y"]));
+        assert_eq!(manifest.variant_for("a.frag", &["Y", "Z", "W"]), defines(&["Y", "Z"]));
+        // every variant needs Y
+        assert_eq!(manifest.variant_for("a.frag", &["X"]), defines(&["Y"]));
+        assert_eq!(manifest.variant_for("b.frag", &["X"]), defines(&[]));
+    }
+    
+    #[test]
+    fn empty_permutation_sets_are_rejected() {
+        let error = PermutationManifest::parse(br#"(shaders: [(source: "a.frag", sets: [[]])])"#)
+            .err()
+            .unwrap();
+        assert_eq!(error, "a.frag has a permutation set without any option");
+    }
+    
+    #[test]
+    fn variant_file_names() {
+        assert_eq!(variant_file_name("pbr-ref.frag", &[]), "pbr-ref.frag.spv");
+        assert_eq!(
+            variant_file_name("pbr-ref.frag", &defines(&["HAS_NORMALS", "HAS_UV"])),
+            "pbr-ref.frag.HAS_NORMALS.HAS_UV.spv"
+        );
+    }
+    
+    #[test]
+    fn manifest_variant_counts() {
+        let manifest = manifest();
+        assert_eq!(manifest.variants("object.frag").len(), 8);
+        assert_eq!(manifest.variants("object.vert"), vec![defines(&[])]);
+        // nothing renders with the reference PBR shaders, they are only checked to compile
+        assert_eq!(manifest.variants("pbr-ref.frag"), vec![defines(&[])]);
+    }
+    
+    #[test]
+    fn manifest_variant_for_mesh_and_material() {
+        let manifest = manifest();
+        let available = ["HAS_NORMALS", "HAS_UV", "HAS_BASECOLORMAP", "HAS_NORMALMAP"];
+        assert_eq!(
+            manifest.variant_for("object.frag", &available),
+            defines(&["HAS_NORMALMAP", "HAS_BASECOLORMAP"])
+        );
+        assert_eq!(manifest.variant_for("object.frag", &[]), defines(&[]));
+    }
+}
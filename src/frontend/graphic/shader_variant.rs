@@ -0,0 +1,153 @@
+use gfs::{GemFileSystem, ReadFile};
+
+use super::{
+    constants::{
+        SHADER_GEN_DIRECTORY,
+        SHADER_SOURCE_DIRECTORY,
+    },
+    hal::error::{
+        RendererError,
+        RendererResult,
+    },
+    material::MaterialMaps,
+    shader_compiler::source_name,
+    shader_preprocessor::{
+        PermutationManifest,
+        PERMUTATION_MANIFEST_NAME,
+        variant_file_name,
+    },
+};
+
+/// the shaders the scene is drawn with, compiled for every combination of their HAS_* toggles
+pub const OBJECT_VERTEX_SOURCE_PATH: &str = "shaders/object.vert";
+pub const OBJECT_FRAGMENT_SOURCE_PATH: &str = "shaders/object.frag";
+
+/// the vertex attributes a mesh has besides its position
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MeshAttributes {
+    pub normals: bool,
+    pub tangents: bool,
+    pub uv: bool,
+}
+
+impl MeshAttributes {
+    /// what vertices_from_obj fills in, it leaves the tangents zero
+    pub fn obj() -> MeshAttributes {
+        MeshAttributes {
+            normals: true,
+            tangents: false,
+            uv: true,
+        }
+    }
+    
+    fn defines(&self) -> Vec<&'static str> {
+        let mut defines = vec![];
+        if self.normals {
+            defines.push("HAS_NORMALS");
+        }
+        if self.tangents {
+            defines.push("HAS_TANGENTS");
+        }
+        if self.uv {
+            defines.push("HAS_UV");
+        }
+        defines
+    }
+}
+
+/// the texture maps a material has
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MaterialFeatures {
+    pub base_color_map: bool,
+    pub normal_map: bool,
+    pub emissive_map: bool,
+    pub metallic_roughness_map: bool,
+    pub occlusion_map: bool,
+    pub specular_map: bool,
+}
+
+impl MaterialFeatures {
+    /// MTL files have no emissive, metallic roughness or occlusion maps the parser knows of,
+    /// the diffuse map is the base color
+    pub fn from_maps(maps: &MaterialMaps) -> MaterialFeatures {
+        MaterialFeatures {
+            base_color_map: maps.diffuse.is_some(),
+            normal_map: maps.normal.is_some(),
+            specular_map: maps.specular.is_some(),
+            ..MaterialFeatures::default()
+        }
+    }
+    
+    fn defines(&self) -> Vec<&'static str> {
+        let mut defines = vec![];
+        if self.base_color_map {
+            defines.push("HAS_BASECOLORMAP");
+        }
+        if self.normal_map {
+            defines.push("HAS_NORMALMAP");
+        }
+        if self.emissive_map {
+            defines.push("HAS_EMISSIVEMAP");
+        }
+        if self.metallic_roughness_map {
+            defines.push("HAS_METALROUGHNESSMAP");
+        }
+        if self.occlusion_map {
+            defines.push("HAS_OCCLUSIONMAP");
+        }
+        if self.specular_map {
+            defines.push("HAS_SPECULARMAP");
+        }
+        defines
+    }
+}
+
+/// the permutations build.rs compiled every shader with,
+/// which tell the SPIR-V to use for a mesh and its material
+pub struct ShaderVariants {
+    manifest: PermutationManifest,
+}
+
+impl ShaderVariants {
+    /// read the manifest under SHADER_SOURCE_DIRECTORY,
+    /// without one every shader only has the variant without defines
+    pub fn load(gfs: &mut GemFileSystem) -> RendererResult<ShaderVariants> {
+        let manifest_path = format!("{}/{}", SHADER_SOURCE_DIRECTORY, PERMUTATION_MANIFEST_NAME);
+        let manifest = match gfs.read_file(manifest_path.clone()) {
+            Ok(manifest_file) => PermutationManifest::parse(manifest_file.as_ref())
+                .map_err(|error| RendererError::asset(&manifest_path, error))?,
+            Err(_) => PermutationManifest::default(),
+        };
+        Ok(ShaderVariants {
+            manifest,
+        })
+    }
+    
+    /// the defines of every variant of the source at source_path under res/
+    pub fn variants(&self, source_path: &str) -> Vec<Vec<String>> {
+        self.manifest.variants(source_name(source_path))
+    }
+    
+    /// the defines of the variant of the source at source_path using the most of what
+    /// the mesh and its material have
+    pub fn defines(
+        &self,
+        source_path: &str,
+        mesh: MeshAttributes,
+        material: MaterialFeatures,
+    ) -> Vec<String> {
+        let mut available = mesh.defines();
+        available.extend(material.defines());
+        self.manifest.variant_for(source_name(source_path), &available)
+    }
+    
+    /// the path under res/ of the SPIR-V to draw a mesh and its material with
+    pub fn path(&self, source_path: &str, mesh: MeshAttributes, material: MaterialFeatures) -> String {
+        compiled_path(source_path, &self.defines(source_path, mesh, material))
+    }
+}
+
+/// the path under res/ build.rs writes the SPIR-V of a variant of the source at source_path to
+pub fn compiled_path(source_path: &str, defines: &[String]) -> String {
+    format!("{}/{}", SHADER_GEN_DIRECTORY, variant_file_name(source_name(source_path), defines))
+}
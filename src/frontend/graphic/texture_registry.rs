@@ -0,0 +1,497 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    io::Cursor,
+    mem,
+    rc::Rc,
+    str,
+};
+
+use ::image::{ImageFormat, load};
+use gfs::{GemFileSystem, ReadFile};
+
+use crate::frontend::graphic::hal::{
+    adapter::AdapterState,
+    descriptor::{
+        DescriptorPoolState,
+        DescriptorState,
+    },
+    device::DeviceState,
+    frame::{
+        FrameTimeline,
+        RetireQueue,
+    },
+    error::{
+        RendererError,
+        RendererResult,
+    },
+    image::SampledImageState,
+    prelude::*,
+    sampler::{
+        SamplerCache,
+        SamplerDesc,
+    },
+};
+
+use super::material::TextureOptions;
+
+/// a texture at path may be accompanied by path + SAMPLER_SIDECAR_EXTENSION,
+/// a RON encoded SamplerDesc overriding the options given by the material
+pub const SAMPLER_SIDECAR_EXTENSION: &str = ".sampler.ron";
+
+/// a typed handle to a texture owned by TextureRegistry,
+/// the generation guards against using a handle whose slot has been reused
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TextureHandle {
+    index: usize,
+    generation: u32,
+}
+
+struct TextureEntry {
+    path: String,
+    reference_count: usize,
+    sampler_desc: SamplerDesc,
+    image_state: SampledImageState,
+    descriptor_set: Option<<B as TB>::DescriptorSet>,
+    // the index in descriptor_pools of the pool descriptor_set is allocated from
+    pool_index: usize,
+}
+
+/// a descriptor pool of TextureRegistry together with the number of sets allocated from it
+struct TexturePool {
+    descriptor_pool_state: DescriptorPoolState,
+    allocated: usize,
+}
+
+/// loads every texture path only once and hands out reference counted handles,
+/// all textures share one descriptor set layout and have their descriptor sets allocated
+/// from shared pools, another one being added whenever they are all full
+pub struct TextureRegistry {
+    device_state: Rc<RefCell<DeviceState>>,
+    entries: Vec<Option<TextureEntry>>,
+    generations: Vec<u32>,
+    free_slots: Vec<usize>,
+    handles_by_path: HashMap<String, TextureHandle>,
+    // textures that have been created but not yet copied to device local memory
+    pending_uploads: Vec<TextureHandle>,
+    // textures whose last handle has been released and images replaced by reload,
+    // destroyed once no frame in flight uses them
+    released: RetireQueue<TextureEntry>,
+    // the timeline given to the last begin_frame, released textures are retired with it
+    timeline: FrameTimeline,
+    sets_per_pool: usize,
+    
+    // the order of the following matters, sets are returned to the pools before they are destroyed
+    descriptor_state: DescriptorState,
+    descriptor_pools: Vec<TexturePool>,
+}
+
+impl TextureRegistry {
+    /// every descriptor pool holds sets_per_pool textures
+    pub fn new(
+        device_state: Rc<RefCell<DeviceState>>,
+        sets_per_pool: usize,
+    ) -> RendererResult<TextureRegistry> {
+        assert!(sets_per_pool > 0, "a texture descriptor pool has to hold at least one set");
+        let descriptor_state = DescriptorState::new(
+            device_state.clone(),
+            &[
+                DescriptorSetLayoutBinding {
+                    binding: 0,
+                    ty: DescriptorType::CombinedImageSampler,
+                    count: 1,
+                    stage_flags: ShaderStageFlags::FRAGMENT,
+                    immutable_samplers: false,
+                },
+            ],
+            &[],
+        )?;
+        
+        Ok(TextureRegistry {
+            device_state,
+            entries: vec![],
+            generations: vec![],
+            free_slots: vec![],
+            handles_by_path: HashMap::new(),
+            pending_uploads: vec![],
+            released: RetireQueue::new(),
+            timeline: FrameTimeline::default(),
+            sets_per_pool,
+            descriptor_state,
+            descriptor_pools: vec![],
+        })
+    }
+    
+    /// the layout every texture descriptor set in this registry is allocated with,
+    /// to be used when creating pipeline layouts
+    pub fn descriptor_state(&self) -> &DescriptorState {
+        &self.descriptor_state
+    }
+    
+    /// return a handle to the texture at path under res/,
+    /// decoding and creating it only if it has not been loaded before,
+    /// a texture already loaded with another sampler cannot be loaded again until it is released
+    pub fn load(
+        &mut self,
+        gfs: &mut GemFileSystem,
+        adapter_state: &AdapterState,
+        sampler_cache: &mut SamplerCache,
+        path: &str,
+        options: TextureOptions,
+    ) -> RendererResult<TextureHandle> {
+        let sampler_desc = read_sampler_sidecar(gfs, path)
+            .unwrap_or_else(|| options.sampler_desc());
+        
+        if let Some(handle) = self.handles_by_path.get(path).cloned() {
+            let loaded_sampler_desc = self.entry(handle).sampler_desc;
+            if loaded_sampler_desc != sampler_desc {
+                return Err(RendererError::asset(path, format!(
+                    "already loaded with sampler {:?}, cannot be loaded again with {:?}",
+                    loaded_sampler_desc,
+                    sampler_desc,
+                )));
+            }
+            self.retain(handle);
+            return Ok(handle);
+        }
+        
+        let image = {
+            let file = gfs
+                .read_file(&path)
+                .map_err(|error| RendererError::file(path, format!("{:?}", error)))?;
+            load(
+                Cursor::new(file),
+                image_format_from_path(path)?)
+                .map_err(|error| RendererError::asset(path, error))?
+                .to_rgba()
+        };
+        
+        let image_state = SampledImageState::new(
+            self.device_state.clone(),
+            adapter_state,
+            image,
+            buffer::Usage::TRANSFER_SRC,
+            sampler_cache.get(sampler_desc)?,
+        )?;
+        
+        let (pool_index, descriptor_set) = self.allocate_descriptor_set()?;
+        image_state.write_descriptor_set(&descriptor_set);
+        
+        let entry = TextureEntry {
+            path: path.to_string(),
+            reference_count: 1,
+            sampler_desc,
+            image_state,
+            descriptor_set: Some(descriptor_set),
+            pool_index,
+        };
+        
+        let index = match self.free_slots.pop() {
+            Some(index) => {
+                self.entries[index] = Some(entry);
+                index
+            }
+            None => {
+                self.entries.push(Some(entry));
+                self.generations.push(0);
+                self.entries.len() - 1
+            }
+        };
+        let handle = TextureHandle {
+            index,
+            generation: self.generations[index],
+        };
+        println!("[INFO][Texture Loaded]{} {:?}", path, handle);
+        
+        self.handles_by_path.insert(path.to_string(), handle);
+        self.pending_uploads.push(handle);
+        Ok(handle)
+    }
+    
+    pub fn retain(&mut self, handle: TextureHandle) {
+        self.entry_mut(handle).reference_count += 1;
+    }
+    
+    /// drop one reference to the texture, when the last one goes away the handle becomes invalid
+    /// and the image and its descriptor set are destroyed by the first begin_frame
+    /// after every frame that may use them has completed
+    pub fn release(&mut self, handle: TextureHandle) {
+        let reference_count = {
+            let entry = self.entry_mut(handle);
+            entry.reference_count -= 1;
+            entry.reference_count
+        };
+        if reference_count > 0 {
+            return;
+        }
+        
+        let entry = self.entries[handle.index].take().unwrap();
+        self.handles_by_path.remove(&entry.path);
+        self.pending_uploads.retain(|pending| *pending != handle);
+        self.generations[handle.index] += 1;
+        self.free_slots.push(handle.index);
+        println!("[INFO][Texture Released]{} {:?}", entry.path, handle);
+        self.released.retire(self.timeline, entry);
+    }
+    
+    /// to be called once the frame context about to be recorded has been waited for,
+    /// destroys the released textures the GPU is done with
+    /// and returns whether there were any
+    pub fn begin_frame(&mut self, timeline: FrameTimeline) -> bool {
+        self.timeline = timeline;
+        let released = self.released.collect(timeline);
+        let destroyed = !released.is_empty();
+        for entry in released {
+            self.destroy(entry);
+        }
+        destroyed
+    }
+    
+    /// decode new content for an already loaded texture and upload it,
+    /// the handle stays valid and the previous image is kept if anything fails,
+    /// otherwise it and its descriptor set are retired like a released texture
+    pub fn reload(
+        &mut self,
+        adapter_state: &AdapterState,
+        sampler_cache: &mut SamplerCache,
+        handle: TextureHandle,
+        file: &[u8],
+    ) -> RendererResult<()> {
+        let path = self.entry(handle).path.clone();
+        let sampler_desc = self.entry(handle).sampler_desc;
+        let image = load(
+            Cursor::new(file),
+            image_format_from_path(&path)?)
+            .map_err(|error| RendererError::asset(&path, error))?
+            .to_rgba();
+        
+        let mut image_state = SampledImageState::new(
+            self.device_state.clone(),
+            adapter_state,
+            image,
+            buffer::Usage::TRANSFER_SRC,
+            sampler_cache.get(sampler_desc)?,
+        )?;
+        upload(&self.device_state, vec![&mut image_state])?;
+        image_state.release_staging_buffer();
+        
+        // frames in flight may still use the previous descriptor set,
+        // which cannot be written until they have completed, so the new image gets its own
+        let (pool_index, descriptor_set) = self.allocate_descriptor_set()?;
+        image_state.write_descriptor_set(&descriptor_set);
+        
+        let previous = {
+            let entry = self.entry_mut(handle);
+            TextureEntry {
+                path: entry.path.clone(),
+                reference_count: 0,
+                sampler_desc: entry.sampler_desc,
+                image_state: mem::replace(&mut entry.image_state, image_state),
+                descriptor_set: mem::replace(&mut entry.descriptor_set, Some(descriptor_set)),
+                pool_index: mem::replace(&mut entry.pool_index, pool_index),
+            }
+        };
+        // the previous image has not been uploaded yet if the texture was loaded since the last flush
+        self.pending_uploads.retain(|pending| *pending != handle);
+        self.released.retire(self.timeline, previous);
+        println!("[INFO][Texture Reloaded]{} {:?}", path, handle);
+        Ok(())
+    }
+    
+    /// every loaded texture together with the path it was loaded from
+    pub fn paths(&self) -> Vec<(String, TextureHandle)> {
+        self.handles_by_path
+            .iter()
+            .map(|(path, handle)| (path.clone(), *handle))
+            .collect()
+    }
+    
+    pub fn descriptor_set(&self, handle: TextureHandle) -> &<B as TB>::DescriptorSet {
+        self.entry(handle).descriptor_set.as_ref().unwrap()
+    }
+    
+    pub fn image_state(&self, handle: TextureHandle) -> &SampledImageState {
+        &self.entry(handle).image_state
+    }
+    
+    /// copy every texture loaded since the last call into device local memory,
+    /// blocking until the copies are done
+    pub fn flush_uploads(&mut self) -> RendererResult<()> {
+        if self.pending_uploads.is_empty() {
+            return Ok(());
+        }
+        
+        let pending_uploads = mem::replace(&mut self.pending_uploads, vec![]);
+        {
+            let image_states = self.entries
+                .iter_mut()
+                .enumerate()
+                .filter(|(index, _)| pending_uploads.iter().any(|handle| handle.index == *index))
+                .filter_map(|(_, entry)| entry.as_mut())
+                .map(|entry| &mut entry.image_state)
+                .collect();
+            upload(&self.device_state, image_states)?;
+        }
+        
+        for handle in pending_uploads {
+            self.entry_mut(handle).image_state.release_staging_buffer();
+        }
+        Ok(())
+    }
+    
+    /// a set from the first pool with room for one, adding a pool if none has
+    fn allocate_descriptor_set(&mut self) -> RendererResult<(usize, <B as TB>::DescriptorSet)> {
+        let sets_per_pool = self.sets_per_pool;
+        let pool_with_room = self.descriptor_pools
+            .iter()
+            .position(|pool| pool.allocated < sets_per_pool);
+        let pool_index = match pool_with_room {
+            Some(pool_index) => pool_index,
+            None => {
+                let descriptor_pool_state = DescriptorPoolState::new_with_max_sets(
+                    self.device_state.clone(),
+                    sets_per_pool,
+                    &[
+                        DescriptorRangeDesc {
+                            ty: DescriptorType::CombinedImageSampler,
+                            count: sets_per_pool,
+                        },
+                    ],
+                    pso::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET,
+                )?;
+                self.descriptor_pools.push(TexturePool {
+                    descriptor_pool_state,
+                    allocated: 0,
+                });
+                println!("[INFO][Texture Descriptor Pools] {}", self.descriptor_pools.len());
+                self.descriptor_pools.len() - 1
+            }
+        };
+        
+        let pool = &mut self.descriptor_pools[pool_index];
+        let descriptor_set = pool.descriptor_pool_state.allocate_set(
+            self.descriptor_state.descriptor_set_layout.as_ref().unwrap()
+        )?;
+        pool.allocated += 1;
+        Ok((pool_index, descriptor_set))
+    }
+    
+    /// return the descriptor set of a texture no frame uses anymore to its pool,
+    /// the image is destroyed with entry
+    fn destroy(&mut self, mut entry: TextureEntry) {
+        if let Some(descriptor_set) = entry.descriptor_set.take() {
+            let pool = &mut self.descriptor_pools[entry.pool_index];
+            pool.descriptor_pool_state.free_set(descriptor_set);
+            pool.allocated -= 1;
+        }
+    }
+    
+    fn entry(&self, handle: TextureHandle) -> &TextureEntry {
+        assert_eq!(
+            self.generations[handle.index],
+            handle.generation,
+            "stale texture handle"
+        );
+        self.entries[handle.index].as_ref().unwrap()
+    }
+    
+    fn entry_mut(&mut self, handle: TextureHandle) -> &mut TextureEntry {
+        assert_eq!(
+            self.generations[handle.index],
+            handle.generation,
+            "stale texture handle"
+        );
+        self.entries[handle.index].as_mut().unwrap()
+    }
+}
+
+impl Drop for TextureRegistry {
+    fn drop(&mut self) {
+        // the registry is only dropped once the device is idle
+        let entries: Vec<TextureEntry> = self.entries
+            .drain(..)
+            .filter_map(|entry| entry)
+            .chain(self.released.drain())
+            .collect();
+        for entry in entries {
+            self.destroy(entry);
+        }
+    }
+}
+
+/// copy the staging buffers of image_states into device local memory,
+/// blocking until the copies are done
+fn upload(
+    device_state: &Rc<RefCell<DeviceState>>,
+    image_states: Vec<&mut SampledImageState>,
+) -> RendererResult<()> {
+    let mut staging_pool = {
+        let device = &device_state.borrow().device;
+        unsafe {
+            device.create_command_pool_typed(
+                &device_state.borrow().queue_group,
+                CommandPoolCreateFlags::empty(),
+            )
+        }.map_err(|error| RendererError::creation("texture upload command pool", error))?
+    };
+    
+    let mut transferred_fence = {
+        let device = &device_state.borrow().device;
+        
+        device.create_fence(false)
+    }.map_err(|error| RendererError::creation("texture upload fence", error))?;
+    
+    unsafe {
+        let command_buffers = image_states
+            .into_iter()
+            .map(|image_state| image_state.transfer(&mut staging_pool))
+            .collect::<Vec<_>>();
+        
+        device_state.borrow_mut().queue_group.queues[0]
+            .submit_nosemaphores(
+                &command_buffers,
+                Some(&mut transferred_fence),
+            );
+        
+        let device = &device_state.borrow().device;
+        
+        let waited = device.wait_for_fence(&transferred_fence, !0);
+        
+        device.destroy_fence(transferred_fence);
+        device.destroy_command_pool(staging_pool.into_raw());
+        waited.map_err(|error| RendererError::memory("texture upload", error))
+    }
+}
+
+/// the SamplerDesc in the sidecar of the texture at path, if there is a valid one
+fn read_sampler_sidecar(gfs: &mut GemFileSystem, path: &str) -> Option<SamplerDesc> {
+    let sidecar_path = format!("{}{}", path, SAMPLER_SIDECAR_EXTENSION);
+    let sidecar = gfs.read_file(&sidecar_path).ok()?;
+    let parsed = str::from_utf8(sidecar.as_ref())
+        .map_err(|error| error.to_string())
+        .and_then(|source| ron::de::from_str::<SamplerDesc>(source)
+            .map_err(|error| error.to_string()));
+    match parsed {
+        Ok(sampler_desc) => Some(sampler_desc),
+        Err(error) => {
+            println!("[WARN][Invalid Sampler Sidecar]{}: {}", sidecar_path, error);
+            None
+        }
+    }
+}
+
+/// the image crate cannot guess TGA from its content, so go by the extension instead
+pub fn image_format_from_path(path: &str) -> RendererResult<ImageFormat> {
+    let extension = path
+        .rsplit('.')
+        .next()
+        .unwrap_or("")
+        .to_lowercase();
+    match extension.as_ref() {
+        "png" => Ok(ImageFormat::PNG),
+        "jpg" | "jpeg" => Ok(ImageFormat::JPEG),
+        "bmp" => Ok(ImageFormat::BMP),
+        "tga" => Ok(ImageFormat::TGA),
+        _ => Err(RendererError::asset(path, "unsupported texture format")),
+    }
+}
@@ -1,11 +1,27 @@
 use winit::{
-    WindowBuilder, Window, EventsLoop, dpi::LogicalSize, WindowEvent,
+    WindowBuilder, Window, EventsLoop, MonitorId, dpi::{LogicalPosition, LogicalSize}, WindowEvent,
 };
+use gfx_hal::window::Extent2D;
 use super::constants::*;
 
+/// how the window takes up the screen,
+/// monitor is an index into the available monitors, None meaning the one the window is on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowMode {
+    Windowed,
+    /// an undecorated window covering the whole monitor
+    Borderless { monitor: Option<usize> },
+    /// fullscreen as managed by the window system, winit keeps the video mode of the monitor
+    Fullscreen { monitor: Option<usize> },
+}
+
 pub struct WindowState {
     pub window: Window,
     pub events_loop: EventsLoop,
+    mode: WindowMode,
+    // size and position to go back to when leaving a fullscreen mode
+    windowed_size: LogicalSize,
+    windowed_position: Option<LogicalPosition>,
 }
 
 impl WindowState {
@@ -19,6 +35,87 @@ impl WindowState {
         WindowState {
             window,
             events_loop,
+            mode: WindowMode::Windowed,
+            windowed_size: WINDOW_SIZE,
+            windowed_position: None,
+        }
+    }
+    
+    pub fn mode(&self) -> WindowMode {
+        self.mode
+    }
+    
+    /// the window gets resized by the switch,
+    /// the swapchain has to be rebuilt at physical_size afterwards
+    pub fn set_mode(&mut self, mode: WindowMode) {
+        if mode == self.mode {
+            return;
+        }
+        if self.mode == WindowMode::Windowed {
+            if let Some(size) = self.window.get_inner_size() {
+                self.windowed_size = size;
+            }
+            self.windowed_position = self.window.get_position();
+        }
+        match mode {
+            WindowMode::Windowed => {
+                self.window.set_fullscreen(None);
+                self.window.set_decorations(true);
+                self.window.set_inner_size(self.windowed_size);
+                if let Some(position) = self.windowed_position {
+                    self.window.set_position(position);
+                }
+            }
+            WindowMode::Borderless { monitor } => {
+                let monitor = self.monitor(monitor);
+                let hidpi_factor = monitor.get_hidpi_factor();
+                self.window.set_fullscreen(None);
+                self.window.set_decorations(false);
+                self.window.set_position(LogicalPosition::from_physical(
+                    monitor.get_position(),
+                    hidpi_factor,
+                ));
+                self.window.set_inner_size(LogicalSize::from_physical(
+                    monitor.get_dimensions(),
+                    hidpi_factor,
+                ));
+            }
+            WindowMode::Fullscreen { monitor } => {
+                let monitor = self.monitor(monitor);
+                self.window.set_fullscreen(Some(monitor));
+            }
+        }
+        println!("[INFO][Window Mode]{:?}", mode);
+        self.mode = mode;
+    }
+    
+    /// between windowed and the given fullscreen mode, as done by Alt+Enter
+    pub fn toggle_mode(&mut self, fullscreen_mode: WindowMode) {
+        let mode = if self.mode == WindowMode::Windowed {
+            fullscreen_mode
+        } else {
+            WindowMode::Windowed
+        };
+        self.set_mode(mode);
+    }
+    
+    fn monitor(&self, index: Option<usize>) -> MonitorId {
+        index
+            .and_then(|index| self.events_loop.get_available_monitors().nth(index))
+            .unwrap_or_else(|| self.window.get_current_monitor())
+    }
+    
+    /// the size in pixels of the drawable area of the window,
+    /// winit reports logical sizes which have to be scaled by the HiDPI factor,
+    /// 0x0 while the window is minimised or once it is gone
+    pub fn physical_size(&self) -> Extent2D {
+        let logical_size = self.window
+            .get_inner_size()
+            .unwrap_or(LogicalSize::new(0.0, 0.0));
+        let physical_size = logical_size.to_physical(self.window.get_hidpi_factor());
+        Extent2D {
+            width: physical_size.width.round() as u32,
+            height: physical_size.height.round() as u32,
         }
     }
 }
\ No newline at end of file
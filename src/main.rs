@@ -9,10 +9,14 @@ extern crate gfs;
 extern crate gfx_backend_vulkan as backend;
 #[macro_use]
 extern crate gfx_hal;
+extern crate glsl_to_spirv;
 extern crate glutin;
 extern crate image;
 extern crate obj;
 extern crate ron;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
 extern crate specs;
 extern crate spirv_cross;
 extern crate time;
@@ -23,6 +27,7 @@ use specs::prelude::*;
 use spirv_cross::{ErrorCode, glsl, spirv};
 use time::Duration;
 
+use crate::frontend::graphic::data_type::ObjectPushConstants;
 use crate::lib::math::camera::Camera;
 use crate::lib::math::light::PointLight;
 use crate::lib::util::HistoryDefault;
@@ -40,19 +45,42 @@ const PI: f32 = ::std::f64::consts::PI as f32;
 const FPS: f32 = 1000.0;
 
 fn main() {
+    // `--headless [path]` renders a single frame without any window and saves it,
+    // the HDR color is kept for a Radiance .hdr path
+    let arguments: Vec<String> = ::std::env::args().collect();
+    if arguments.get(1).map(|argument| argument.as_str()) == Some("--headless") {
+        let path = arguments
+            .get(2)
+            .map(|path| path.as_str())
+            .unwrap_or(frontend::graphic::constants::HEADLESS_RENDER_PATH);
+        if let Err(error) = render_headless(path) {
+            exit_with_error(error);
+        }
+        return;
+    }
+    
     let mut window_state = frontend::graphic::window::WindowState::new();
     
     let mut renderer_state =
-        frontend::graphic::renderer::RendererState::new(
+        match frontend::graphic::renderer::RendererState::new(
             &window_state,
-            frontend::graphic::constants::RENDER_SIZE,
-        );
+            window_state.physical_size(),
+        ) {
+            Ok(renderer_state) => renderer_state,
+            Err(error) => exit_with_error(error),
+        };
     
     let mut fps_history: lib::util::History<Duration> = lib::util::History::new(32);
     let mut render_time_history: lib::util::History<Duration> = lib::util::History::new(32);
     let startup_time = time::now();
     let mut one_second_timer = time::now();
     let mut running = true;
+    let mut print_memory_report = false;
+    let mut cycle_present_mode = false;
+    let mut toggle_fullscreen = false;
+    let mut cycle_tonemap_operator = false;
+    // in stops, applied once the events of this iteration are handled
+    let mut exposure_change = 0.0;
     'main: loop {
         let loop_start = time::now();
         let frame_start_time = time::now();
@@ -66,8 +94,66 @@ fn main() {
                         },
                         ..
                     } => running = false,
+                    winit::WindowEvent::KeyboardInput {
+                        input: winit::KeyboardInput {
+                            virtual_keycode: Some(winit::VirtualKeyCode::M),
+                            state: winit::ElementState::Pressed,
+                            ..
+                        },
+                        ..
+                    } => print_memory_report = true,
+                    winit::WindowEvent::KeyboardInput {
+                        input: winit::KeyboardInput {
+                            virtual_keycode: Some(winit::VirtualKeyCode::V),
+                            state: winit::ElementState::Pressed,
+                            ..
+                        },
+                        ..
+                    } => cycle_present_mode = true,
+                    winit::WindowEvent::KeyboardInput {
+                        input: winit::KeyboardInput {
+                            virtual_keycode: Some(winit::VirtualKeyCode::Return),
+                            state: winit::ElementState::Pressed,
+                            modifiers: winit::ModifiersState { alt: true, .. },
+                            ..
+                        },
+                        ..
+                    } => toggle_fullscreen = true,
+                    winit::WindowEvent::KeyboardInput {
+                        input: winit::KeyboardInput {
+                            virtual_keycode: Some(winit::VirtualKeyCode::F12),
+                            state: winit::ElementState::Pressed,
+                            ..
+                        },
+                        ..
+                    } => renderer_state.request_screenshot(),
+                    winit::WindowEvent::KeyboardInput {
+                        input: winit::KeyboardInput {
+                            virtual_keycode: Some(winit::VirtualKeyCode::T),
+                            state: winit::ElementState::Pressed,
+                            ..
+                        },
+                        ..
+                    } => cycle_tonemap_operator = true,
+                    winit::WindowEvent::KeyboardInput {
+                        input: winit::KeyboardInput {
+                            virtual_keycode: Some(winit::VirtualKeyCode::Equals),
+                            state: winit::ElementState::Pressed,
+                            ..
+                        },
+                        ..
+                    } => exposure_change += frontend::graphic::constants::EXPOSURE_STEP,
+                    winit::WindowEvent::KeyboardInput {
+                        input: winit::KeyboardInput {
+                            virtual_keycode: Some(winit::VirtualKeyCode::Minus),
+                            state: winit::ElementState::Pressed,
+                            ..
+                        },
+                        ..
+                    } => exposure_change -= frontend::graphic::constants::EXPOSURE_STEP,
                     winit::WindowEvent::CloseRequested => running = false,
-                    winit::WindowEvent::Resized(dims) => {
+                    winit::WindowEvent::Resized(_) |
+                    winit::WindowEvent::HiDpiFactorChanged(_) => {
                         renderer_state.rebuild_swapchain = true;
                     }
                     _ => (),
@@ -77,33 +163,52 @@ fn main() {
         if !running {
             break 'main;
         }
-        renderer_state.try_rebuild_swapchain(frontend::graphic::constants::RENDER_SIZE);
+        if print_memory_report {
+            print!("{}", renderer_state.memory_report());
+            print_memory_report = false;
+        }
+        if cycle_present_mode {
+            let present_mode_policy = renderer_state.present_mode_policy().next();
+            println!("[INFO][Present Mode Policy]{:?}", present_mode_policy);
+            renderer_state.set_present_mode_policy(present_mode_policy);
+            cycle_present_mode = false;
+        }
+        if cycle_tonemap_operator || exposure_change != 0.0 {
+            let mut tonemap_settings = renderer_state.tonemap_settings();
+            if cycle_tonemap_operator {
+                tonemap_settings.operator = tonemap_settings.operator.next();
+            }
+            tonemap_settings.exposure += exposure_change;
+            println!("[INFO][Tonemap Settings]{:?}", tonemap_settings);
+            renderer_state.set_tonemap_settings(tonemap_settings);
+            cycle_tonemap_operator = false;
+            exposure_change = 0.0;
+        }
+        if toggle_fullscreen {
+            window_state.toggle_mode(frontend::graphic::constants::FULLSCREEN_MODE);
+            renderer_state.rebuild_swapchain = true;
+            toggle_fullscreen = false;
+        }
+        if let Err(error) =
+        renderer_state.try_rebuild_swapchain(window_state.physical_size()) {
+            // let the renderer wait for the device before going away
+            drop(renderer_state);
+            exit_with_error(error);
+        }
+        renderer_state.poll_hot_reload();
     
         let duration = time::now() - startup_time;
     
         let angle = duration.num_milliseconds() as f32 / 1000.0 * 45.0;
         let light_angle = duration.num_milliseconds() as f32 / 1000.0 * 45.0;
-        let camera = Camera::perspective(
-            cgmath::Point3 {
-                x: 0.0,
-                y: 0.0,
-                z: 0.0,
-            },
-            cgmath::Point3 {
-                x: 2.0 * f32::sin(2.0 * PI * angle / 360.0),
-                y: -2.0 * f32::sin(2.0 * PI * angle / 360.0),
-                z: 2.0 * 1.414 * f32::cos(2.0 * PI * angle / 360.0),
-            },
-        );
-        let light = PointLight {
-            position: cgmath::Point3 {
-                x: 4.0,
-                y: -4.0,
-                z: 4.0,
-            }
-        };
+        let camera = orbit_camera(angle);
+        let light = scene_light();
+        let objects = scene_objects();
         let render_start = time::now();
-        renderer_state.paint_frame(camera, light);
+        if let Err(error) = renderer_state.paint_frame(camera, light, &objects) {
+            drop(renderer_state);
+            exit_with_error(error);
+        }
         render_time_history.push(time::now() - render_start);
         fps_history.push(time::now() - loop_start);
         {
@@ -133,6 +238,59 @@ fn main() {
     }
 }
 
+/// the camera circling the chest, angle in degrees
+fn orbit_camera(angle: f32) -> Camera {
+    Camera::perspective(
+        cgmath::Point3 {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+        },
+        cgmath::Point3 {
+            x: 2.0 * f32::sin(2.0 * PI * angle / 360.0),
+            y: -2.0 * f32::sin(2.0 * PI * angle / 360.0),
+            z: 2.0 * 1.414 * f32::cos(2.0 * PI * angle / 360.0),
+        },
+    )
+}
+
+fn scene_light() -> PointLight {
+    PointLight {
+        position: cgmath::Point3 {
+            x: 4.0,
+            y: -4.0,
+            z: 4.0,
+        }
+    }
+}
+
+fn scene_objects() -> Vec<ObjectPushConstants> {
+    vec![
+        ObjectPushConstants::new(cgmath::Matrix4::from_scale(1.0), 0),
+    ]
+}
+
+/// render the scene once at RENDER_SIZE without a window and save it to path
+fn render_headless(path: &str) -> frontend::graphic::hal::error::RendererResult<()> {
+    let path = ::std::path::Path::new(path);
+    let mut renderer_state = frontend::graphic::renderer::RendererState::new_headless(
+        frontend::graphic::constants::RENDER_SIZE,
+        frontend::graphic::capture::OffscreenOutput::for_path(path),
+    )?;
+    let image = renderer_state.render_offscreen(
+        orbit_camera(0.0),
+        scene_light(),
+        &scene_objects(),
+    )?;
+    frontend::graphic::capture::save_offscreen_image(&image, path)
+}
+
+/// report an error the renderer cannot recover from and quit
+fn exit_with_error(error: frontend::graphic::hal::error::RendererError) -> ! {
+    eprintln!("[ERROR]{}", error);
+    ::std::process::exit(1)
+}
+
 
 
 